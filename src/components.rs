@@ -1,9 +1,14 @@
 use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
+use std::str::FromStr;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Piece {
+    // Moves as a bishop or a knight.
+    Archbishop,
     Bishop,
+    // Moves as a rook or a knight.
+    Chancellor,
     King,
     Knight,
     Pawn,
@@ -11,27 +16,210 @@ pub enum Piece {
     Rook,
 }
 
+impl Piece {
+    // Converts a letter from FEN notation (in either case) to a piece, using A for archbishop and C
+    // for chancellor. Returns None for any other character.
+    pub fn from_letter(letter: char) -> Option<Piece> {
+        match letter.to_ascii_uppercase() {
+            'A' => Some(Piece::Archbishop),
+            'B' => Some(Piece::Bishop),
+            'C' => Some(Piece::Chancellor),
+            'K' => Some(Piece::King),
+            'N' => Some(Piece::Knight),
+            'P' => Some(Piece::Pawn),
+            'Q' => Some(Piece::Queen),
+            'R' => Some(Piece::Rook),
+            _ => None,
+        }
+    }
+
+    // Returns the piece's value in pawns, used to compare each side's material. The king can't be
+    // captured, so it has no value.
+    pub fn get_value(&self) -> u32 {
+        match self {
+            Piece::Archbishop => 7,
+            Piece::Bishop => 3,
+            Piece::Chancellor => 8,
+            Piece::King => 0,
+            Piece::Knight => 3,
+            Piece::Pawn => 1,
+            Piece::Queen => 9,
+            Piece::Rook => 5,
+        }
+    }
+
+    // Returns the piece's uppercase letter in FEN notation. This is the reverse of from_letter().
+    pub fn get_letter(&self) -> char {
+        match self {
+            Piece::Archbishop => 'A',
+            Piece::Bishop => 'B',
+            Piece::Chancellor => 'C',
+            Piece::King => 'K',
+            Piece::Knight => 'N',
+            Piece::Pawn => 'P',
+            Piece::Queen => 'Q',
+            Piece::Rook => 'R',
+        }
+    }
+
+    // Returns the ways this piece can move, not including castling. Pawns are left out here because
+    // how they move depends on their color and on whether they are capturing, so they are handled
+    // separately.
+    pub fn get_movements(&self) -> Vec<Movement> {
+        match self {
+            Piece::Archbishop => get_movements_in(&DIAGONAL_DIRECTIONS, true)
+                .chain(get_movements_in(&KNIGHT_DIRECTIONS, false))
+                .collect(),
+            Piece::Bishop => get_movements_in(&DIAGONAL_DIRECTIONS, true).collect(),
+            Piece::Chancellor => get_movements_in(&ORTHOGONAL_DIRECTIONS, true)
+                .chain(get_movements_in(&KNIGHT_DIRECTIONS, false))
+                .collect(),
+            Piece::King => get_movements_in(&ORTHOGONAL_DIRECTIONS, false)
+                .chain(get_movements_in(&DIAGONAL_DIRECTIONS, false))
+                .collect(),
+            Piece::Knight => get_movements_in(&KNIGHT_DIRECTIONS, false).collect(),
+            Piece::Pawn => Vec::new(),
+            Piece::Queen => get_movements_in(&ORTHOGONAL_DIRECTIONS, true)
+                .chain(get_movements_in(&DIAGONAL_DIRECTIONS, true))
+                .collect(),
+            Piece::Rook => get_movements_in(&ORTHOGONAL_DIRECTIONS, true).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Direction {
+    pub x: i32,
+    pub y: i32,
+}
+impl Direction {
+    pub fn reverse(&self) -> Direction {
+        Direction {
+            x: -self.x,
+            y: -self.y,
+        }
+    }
+}
+
+pub const ORTHOGONAL_DIRECTIONS: [Direction; 4] = [
+    Direction { x: 0, y: 1 },
+    Direction { x: 1, y: 0 },
+    Direction { x: 0, y: -1 },
+    Direction { x: -1, y: 0 },
+];
+pub const DIAGONAL_DIRECTIONS: [Direction; 4] = [
+    Direction { x: 1, y: 1 },
+    Direction { x: 1, y: -1 },
+    Direction { x: -1, y: -1 },
+    Direction { x: -1, y: 1 },
+];
+pub const KNIGHT_DIRECTIONS: [Direction; 8] = [
+    Direction { x: 1, y: 2 },
+    Direction { x: 2, y: 1 },
+    Direction { x: 2, y: -1 },
+    Direction { x: 1, y: -2 },
+    Direction { x: -1, y: -2 },
+    Direction { x: -2, y: -1 },
+    Direction { x: -2, y: 1 },
+    Direction { x: -1, y: 2 },
+];
+
+// Describes one way a piece can move: a step in the given direction, which is repeated until the
+// piece is blocked if it slides (like a rook), or taken only once if it doesn't (like a knight).
 #[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Movement {
+    pub direction: Direction,
+    pub slides: bool,
+}
+
+fn get_movements_in(directions: &[Direction], slides: bool) -> impl Iterator<Item = Movement> + '_ {
+    directions
+        .iter()
+        .map(move |&direction| Movement { direction, slides })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Color {
     White,
     Black,
 }
+impl Color {
+    pub fn get_name(&self) -> &str {
+        match self {
+            Color::White => "White",
+            Color::Black => "Black",
+        }
+    }
 
-#[derive(Component, Clone, Copy, PartialEq, Eq)]
+    pub fn opposite(&self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "white" => Ok(Color::White),
+            "black" => Ok(Color::Black),
+            _ => Err(format!("Unknown color \"{s}\"")),
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
 pub struct GamePiece {
     pub piece: Piece,
     pub color: Color,
 }
 impl GamePiece {
+    // Returns the ways this piece can capture. This is the same as how it moves for every piece
+    // except pawns, which only capture one step diagonally forwards.
+    pub fn get_attacks(&self) -> Vec<Movement> {
+        if self.piece == Piece::Pawn {
+            let y = if self.color == Color::White { 1 } else { -1 };
+            vec![
+                Movement {
+                    direction: Direction { x: 1, y },
+                    slides: false,
+                },
+                Movement {
+                    direction: Direction { x: -1, y },
+                    slides: false,
+                },
+            ]
+        } else {
+            self.piece.get_movements()
+        }
+    }
+
+    // Returns the piece's letter in FEN notation, which is uppercase for white pieces and lowercase
+    // for black pieces.
+    pub fn get_letter(&self) -> char {
+        let letter = self.piece.get_letter();
+        match self.color {
+            Color::White => letter,
+            Color::Black => letter.to_ascii_lowercase(),
+        }
+    }
+
     pub fn get_asset_path(&self) -> &str {
         match (&self.piece, &self.color) {
+            (Piece::Archbishop, Color::White) => "pieces/archbishop_white.png",
             (Piece::Bishop, Color::White) => "pieces/bishop_white.png",
+            (Piece::Chancellor, Color::White) => "pieces/chancellor_white.png",
             (Piece::King, Color::White) => "pieces/king_white.png",
             (Piece::Knight, Color::White) => "pieces/knight_white.png",
             (Piece::Pawn, Color::White) => "pieces/pawn_white.png",
             (Piece::Queen, Color::White) => "pieces/queen_white.png",
             (Piece::Rook, Color::White) => "pieces/rook_white.png",
+            (Piece::Archbishop, Color::Black) => "pieces/archbishop_black.png",
             (Piece::Bishop, Color::Black) => "pieces/bishop_black.png",
+            (Piece::Chancellor, Color::Black) => "pieces/chancellor_black.png",
             (Piece::King, Color::Black) => "pieces/king_black.png",
             (Piece::Knight, Color::Black) => "pieces/knight_black.png",
             (Piece::Pawn, Color::Black) => "pieces/pawn_black.png",
@@ -58,3 +246,162 @@ pub struct LegalMoves(pub Vec<LegalMove>);
 
 #[derive(Component)]
 pub struct CursorDisplay;
+
+// A UI image of a game piece, which is redrawn when the piece set changes.
+#[derive(Component)]
+pub struct PieceImage(pub GamePiece);
+
+#[derive(Component)]
+pub struct EditorPanel;
+
+// A palette button in the position editor, which selects the game piece to place on tiles. The
+// eraser button has no game piece and removes pieces instead.
+#[derive(Component)]
+pub struct PaletteButton(pub Option<GamePiece>);
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum EditorButton {
+    ColorToMove,
+    WhiteKingside,
+    WhiteQueenside,
+    BlackKingside,
+    BlackQueenside,
+    PlayFromHere,
+}
+
+#[derive(Component)]
+pub struct EditorStatusText;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CoordinateLabelKind {
+    // A file's letter below the board.
+    File,
+    // A rank's number to the left of the board.
+    Rank,
+    // A tile's full name inside the tile.
+    Tile,
+}
+
+// A label naming a tile, or the file or rank containing it, which follows the board's orientation.
+#[derive(Component)]
+pub struct CoordinateLabel {
+    pub tile_pos: TilePos,
+    pub kind: CoordinateLabelKind,
+}
+
+// The part of the move list panel holding the moves, which is rebuilt whenever a move is made.
+#[derive(Component)]
+pub struct MoveListEntries;
+
+// A move in the move list, which shows the position after the given number of plies when pressed.
+#[derive(Component)]
+pub struct MoveButton(pub usize);
+
+#[derive(Component)]
+pub struct BackToLiveButton;
+
+// A row of the pieces captured by the color, shown beside the board.
+#[derive(Component)]
+pub struct CaptureTray(pub Color);
+
+// Holds the capture trays, which are ordered to match the board's orientation.
+#[derive(Component)]
+pub struct CaptureTrayPanel;
+
+// A temporary sprite sliding a moved piece to its destination, or fading out a captured piece. The
+// piece on the hidden tile isn't shown until the animation finishes.
+#[derive(Component)]
+pub struct PieceAnimation {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub elapsed: f32,
+    pub hidden_tile: Option<TilePos>,
+    pub fades_out: bool,
+}
+
+// Something that happened in the game which has a sound.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SoundEffect {
+    Move,
+    Capture,
+    Castle,
+    Check,
+    GameOver,
+    // The local player's clock dropped below the time at which it's shown in red.
+    LowTime,
+}
+
+impl SoundEffect {
+    // Returns the frequency (in hertz) and length (in seconds) of the effect's tone.
+    pub fn get_tone(&self) -> (f32, f32) {
+        match self {
+            SoundEffect::Move => (440.0, 0.06),
+            SoundEffect::Capture => (330.0, 0.12),
+            SoundEffect::Castle => (523.25, 0.1),
+            SoundEffect::Check => (880.0, 0.15),
+            SoundEffect::GameOver => (220.0, 0.6),
+            SoundEffect::LowTime => (660.0, 0.3),
+        }
+    }
+}
+
+// A sound effect being played, which is despawned once it finishes.
+#[derive(Component)]
+pub struct SoundEffectSource(pub SoundEffect);
+
+// A sprite drawing part of an arrow or a marked square, which is respawned whenever the annotations
+// change.
+#[derive(Component)]
+pub struct AnnotationSprite;
+
+// A button on one of the menu screens.
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuButton {
+    // Continues the game saved when the last one was left unfinished.
+    ContinueGame,
+    NewGame,
+    Quit,
+    // Cycles through the choices for one of the new game's settings.
+    Opponent,
+    Variant,
+    TimeControl,
+    Color,
+    Start,
+    BackToMainMenu,
+    Resume,
+    Settings,
+    // Cycles through the choices for one of the settings.
+    BoardScale,
+    Volume,
+    Mute,
+    InputMode,
+    LegalMoveHints,
+    TileNames,
+    AnimationSpeed,
+    DefaultTimeControl,
+    BoardTheme,
+    PieceSet,
+    // Resigns, offers a draw or asks for a takeback, from the pause menu.
+    Resign,
+    OfferDraw,
+    RequestTakeback,
+    // Answers the draw offer or takeback request made by the other side.
+    AcceptOffer,
+    DeclineOffer,
+}
+
+// The panel showing the draw offer or takeback request waiting to be answered.
+#[derive(Component)]
+pub struct OfferPanel;
+
+// The text of a button on the new game or settings screen showing the current choice for that
+// setting.
+#[derive(Component)]
+pub struct SettingText(pub MenuButton);
+
+// Holds both clocks, which is only shown in timed games.
+#[derive(Component)]
+pub struct ClockPanel;
+
+#[derive(Component)]
+pub struct ClockText(pub Color);
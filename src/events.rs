@@ -0,0 +1,173 @@
+use crate::components::{Color, GamePiece};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+// A move that does more than move a single piece from one tile to another.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SpecialMove {
+    // The king moved two tiles towards a rook, which jumped to the tile on the king's other side.
+    Castling {
+        rook_from: TilePos,
+        rook_to: TilePos,
+    },
+}
+
+// Asks for a piece to be moved, whether by the mouse or by something else such as an engine or a
+// network opponent. The move is only made if it's legal in the current position.
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MoveRequested {
+    pub from: TilePos,
+    pub to: TilePos,
+}
+
+// Sent after a piece is moved to one of its legal moves.
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MoveMade {
+    pub from: TilePos,
+    pub to: TilePos,
+    pub game_piece: GamePiece,
+    pub captured: Option<GamePiece>,
+    pub special_move: Option<SpecialMove>,
+}
+
+// Sent when the side to move starts their turn with their king under attack.
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KingInCheck {
+    pub color: Color,
+    pub king_tile: TilePos,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    Win(Color),
+    Draw,
+}
+
+impl GameResult {
+    // Returns the result as written in PGN's Result tag.
+    pub fn to_pgn(&self) -> &str {
+        match self {
+            GameResult::Win(Color::White) => "1-0",
+            GameResult::Win(Color::Black) => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+
+    // Parses a result written by to_pgn().
+    pub fn from_pgn(text: &str) -> Option<Self> {
+        match text {
+            "1-0" => Some(GameResult::Win(Color::White)),
+            "0-1" => Some(GameResult::Win(Color::Black)),
+            "1/2-1/2" => Some(GameResult::Draw),
+            _ => None,
+        }
+    }
+}
+
+pub const GAME_END_REASONS: [GameEndReason; 6] = [
+    GameEndReason::Checkmate,
+    GameEndReason::Stalemate,
+    GameEndReason::AllPiecesCaptured,
+    GameEndReason::Timeout,
+    GameEndReason::Resignation,
+    GameEndReason::Agreement,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameEndReason {
+    Checkmate,
+    Stalemate,
+    // Horde's side without a king loses when all of its pieces are captured.
+    AllPiecesCaptured,
+    // The side to move ran out of time.
+    Timeout,
+    // The losing side gave up.
+    Resignation,
+    // One side offered a draw and the other accepted it.
+    Agreement,
+}
+
+impl GameEndReason {
+    pub fn get_name(&self) -> &str {
+        match self {
+            GameEndReason::Checkmate => "checkmate",
+            GameEndReason::Stalemate => "stalemate",
+            GameEndReason::AllPiecesCaptured => "all pieces captured",
+            GameEndReason::Timeout => "timeout",
+            GameEndReason::Resignation => "resignation",
+            GameEndReason::Agreement => "agreement",
+        }
+    }
+}
+
+// Sent when the game is over, either because the side to move can't continue or because a side
+// resigned or the sides agreed to a draw.
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GameEnded {
+    pub result: GameResult,
+    pub reason: GameEndReason,
+}
+
+impl GameEnded {
+    // Describes how the game ended, such as "White wins by checkmate" or "Draw by agreement".
+    pub fn get_description(&self) -> String {
+        match self.result {
+            GameResult::Win(color) => {
+                format!("{} wins by {}", color.get_name(), self.reason.get_name())
+            }
+            GameResult::Draw => format!("Draw by {}", self.reason.get_name()),
+        }
+    }
+}
+
+// What a side can do other than moving. Resigning ends the game at once, while a draw offer or a
+// takeback request waits for the other side to accept or decline it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameAction {
+    Resign,
+    OfferDraw,
+    // Asks to take back the side's last move, along with the reply to it if there was one.
+    RequestTakeback,
+}
+
+// Asks for a side to resign, offer a draw or request a takeback, whether from the menu or by
+// something else such as a network opponent.
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GameActionRequested {
+    pub color: Color,
+    pub action: GameAction,
+}
+
+impl GameActionRequested {
+    // Describes the action, such as "White offers a draw".
+    pub fn get_description(&self) -> String {
+        let color = self.color.get_name();
+        match self.action {
+            GameAction::Resign => format!("{color} resigns"),
+            GameAction::OfferDraw => format!("{color} offers a draw"),
+            GameAction::RequestTakeback => format!("{color} asks to take back a move"),
+        }
+    }
+}
+
+// Sent when a side answers the draw offer (for OfferDraw) or takeback request (for
+// RequestTakeback) made by the other side.
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OfferAnswered {
+    pub color: Color,
+    pub action: GameAction,
+    pub is_accepted: bool,
+}
+
+// Asks for the last number of plies (moves by either side) to be taken back, such as once a
+// takeback request is accepted.
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UndoRequested {
+    pub plies: usize,
+}
+
+// Sent after moves are taken back, with the moves that were taken back, latest first.
+#[derive(Event, Clone, PartialEq, Eq, Debug)]
+pub struct MovesUndone {
+    pub moves: Vec<MoveMade>,
+}
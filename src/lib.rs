@@ -0,0 +1,30 @@
+use bevy_ecs_tilemap::prelude::*;
+
+pub mod components;
+pub mod events;
+pub mod network;
+pub mod notation;
+pub mod plugin;
+pub mod resources;
+pub mod save;
+pub mod settings;
+pub mod system;
+#[cfg(test)]
+mod tests;
+pub mod theme;
+
+pub use plugin::{
+    ChessAudioPlugin, ChessInputPlugin, ChessNetworkPlugin, ChessPlugin, ChessRenderingPlugin,
+    ChessRulesPlugin, ChessSet, ChessSettingsPlugin, ChessUiPlugin,
+};
+
+pub const MAP_TYPE: TilemapType = TilemapType::Square;
+pub const TILE_SIZE: TilemapTileSize = TilemapTileSize { x: 10.0, y: 10.0 };
+pub const GRID_SIZE: TilemapGridSize = TilemapGridSize { x: 10.0, y: 10.0 };
+pub const SCALE: f32 = 8.0;
+
+// Used to properly space sprites on the grid after it is scaled up.
+pub const SCALED_GRID_SIZE: TilemapGridSize = TilemapGridSize {
+    x: GRID_SIZE.x * SCALE,
+    y: GRID_SIZE.y * SCALE,
+};
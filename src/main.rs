@@ -1,60 +1,108 @@
 use bevy::prelude::*;
-use bevy_ecs_tilemap::prelude::*;
-use resources::{
-    ColorToMove, CursorPos, MustRecalculateLegalMoves, RightToCastle, SelectedPiece,
-    SelectedPieceOriginalPosition,
-};
-use system::input::update_cursor_pos;
-use system::setup::{setup_board, setup_cursor, setup_pieces};
-use system::update::{
-    find_mouseover_tile, highlight_tile, pick_up_piece, put_down_piece, recalculate_legal_moves,
-    update_cursor_display,
-};
-
-mod components;
-mod resources;
-mod system;
-
-const MAP_SIZE: TilemapSize = TilemapSize { x: 8, y: 8 };
-const MAP_TYPE: TilemapType = TilemapType::Square;
-const TILE_SIZE: TilemapTileSize = TilemapTileSize { x: 10.0, y: 10.0 };
-const GRID_SIZE: TilemapGridSize = TilemapGridSize { x: 10.0, y: 10.0 };
-const SCALE: f32 = 8.0;
-
-// Used to properly space sprites on the grid after it is scaled up.
-const SCALED_GRID_SIZE: TilemapGridSize = TilemapGridSize {
-    x: GRID_SIZE.x * SCALE,
-    y: GRID_SIZE.y * SCALE,
+use bevy_chess::components::Color;
+use bevy_chess::network::DEFAULT_PORT;
+use bevy_chess::notation::parse_placement;
+use bevy_chess::resources::{
+    NetworkGame, NewGameSettings, Odds, Opponent, PositionSnapshot, RightToCastle, SaveFile,
+    StartingPlacement, UserSettings, Variant,
 };
+use bevy_chess::system::settings::load_settings;
+use bevy_chess::ChessPlugin;
 
 fn main() {
+    // The variant can be chosen with "--variant <name>", otherwise standard chess is played.
+    let variant = match get_arg_value("--variant") {
+        Some(name) => name.parse().unwrap_or_else(|error| panic!("{error}")),
+        None => Variant::default(),
+    };
+    // Odds can be given with "--odds <pawn|knight|rook|queen>", by white unless
+    // "--odds-giver black" is also given.
+    let odds = get_arg_value("--odds").map(|handicap| {
+        let handicap = handicap.parse().unwrap_or_else(|error| panic!("{error}"));
+        let giver = match get_arg_value("--odds-giver") {
+            Some(giver) => giver.parse().unwrap_or_else(|error| panic!("{error}")),
+            None => Color::White,
+        };
+        (handicap, giver)
+    });
+    // A custom piece placement in FEN notation can be given with "--setup <placement>", otherwise
+    // the variant's usual starting placement is used. White moves first, and can castle with any
+    // rook and king on their starting tiles.
+    let starting_placement = get_arg_value("--setup");
+    if let Some(starting_placement) = &starting_placement {
+        if let Err(error) = validate_starting_placement(starting_placement, &variant) {
+            eprintln!("Invalid starting placement \"{starting_placement}\": {error}");
+            std::process::exit(1);
+        }
+    }
+
     let mut app = App::new();
     app.add_plugins(
         DefaultPlugins
             // Prevent anti-aliasing
             .set(ImagePlugin::default_nearest()),
     )
-    .add_plugins(TilemapPlugin)
-    .init_resource::<CursorPos>()
-    .init_resource::<SelectedPiece>()
-    .init_resource::<SelectedPieceOriginalPosition>()
-    .init_resource::<ColorToMove>()
-    .init_resource::<MustRecalculateLegalMoves>()
-    .init_resource::<RightToCastle>()
-    .add_systems(Startup, (setup_board, setup_pieces).chain())
-    .add_systems(Startup, setup_cursor)
-    .add_systems(First, update_cursor_pos)
-    .add_systems(
-        Update,
-        (
-            find_mouseover_tile,
-            pick_up_piece,
-            put_down_piece,
-            recalculate_legal_moves,
-            highlight_tile,
-        )
-            .chain(),
-    )
-    .add_systems(Update, update_cursor_display)
-    .run();
+    .insert_resource(variant)
+    .insert_resource(Odds(odds));
+    if let Some(starting_placement) = starting_placement {
+        app.insert_resource(StartingPlacement(starting_placement));
+    }
+    // How many seconds pieces take to slide to their new tile can be given with
+    // "--animation-duration <seconds>", where 0 turns the animations off. This replaces the
+    // duration in the settings file.
+    if let Some(duration) = get_arg_value("--animation-duration") {
+        let duration: f32 = duration.parse().unwrap_or_else(|error| panic!("{error}"));
+        app.add_systems(
+            Startup,
+            (move |mut user_settings: ResMut<UserSettings>| {
+                user_settings.animation_duration = duration;
+            })
+            .after(load_settings),
+        );
+    }
+    // Unfinished games are saved to saved_game.txt in the working directory, or to the file given
+    // with "--save-file <path>".
+    if let Some(path) = get_arg_value("--save-file") {
+        app.insert_resource(SaveFile(Some(path.into())));
+    }
+    // "--host [port]" lets another computer join the game started here against a remote opponent,
+    // on port 7878 unless another is given. "--join <address>" joins a game hosted at the address,
+    // such as "192.168.1.20" or "192.168.1.20:7878", and "--spectate <address>" watches it.
+    if std::env::args().any(|arg| arg == "--host") {
+        let port = get_arg_value("--host")
+            .and_then(|port| port.parse().ok())
+            .unwrap_or(DEFAULT_PORT);
+        let network_game = NetworkGame::host(port).unwrap_or_else(|error| panic!("{error}"));
+        app.insert_resource(network_game)
+            .insert_resource(NewGameSettings {
+                opponent: Opponent::Remote,
+                ..default()
+            });
+    } else if let Some(address) = get_arg_value("--join") {
+        let network_game = NetworkGame::join(&address).unwrap_or_else(|error| panic!("{error}"));
+        app.insert_resource(network_game);
+    } else if let Some(address) = get_arg_value("--spectate") {
+        let network_game =
+            NetworkGame::spectate(&address).unwrap_or_else(|error| panic!("{error}"));
+        app.insert_resource(network_game);
+    }
+    app.add_plugins(ChessPlugin);
+    app.run();
+}
+
+// Checks that a game can be played from the placement, as it would be set up at the start.
+fn validate_starting_placement(starting_placement: &str, variant: &Variant) -> Result<(), String> {
+    let map_size = variant.get_map_size();
+    let pieces = parse_placement(starting_placement, &map_size)?;
+    PositionSnapshot {
+        right_to_castle: RightToCastle::from_starting_pieces(&pieces, &map_size),
+        pieces,
+        color_to_move: Color::White,
+    }
+    .validate(variant)
+}
+
+// Returns the argument following the given flag on the command line, if there is one.
+fn get_arg_value(flag: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != flag).nth(1)
 }
@@ -0,0 +1,358 @@
+use crate::components::Color;
+use crate::events::{GameEnded, GameResult, GAME_END_REASONS};
+use crate::notation::{parse_coordinate_move, write_coordinate_move};
+use crate::resources::{TimeControl, Variant};
+use bevy_ecs_tilemap::prelude::*;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// The version of the messages sent between games over the network, which both sides must speak. It
+// goes up whenever a message changes meaning or a new message must be understood.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+const PROTOCOL_NAME: &str = "bevy-chess";
+
+// The port games are hosted on when no port is given.
+pub const DEFAULT_PORT: u16 = 7878;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Whether a joining game plays the other color or only watches.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PeerRole {
+    Player,
+    // Spectators are sent every move and the clocks, but can't move or offer anything.
+    Spectator,
+}
+
+impl PeerRole {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            PeerRole::Player => "player",
+            PeerRole::Spectator => "spectator",
+        }
+    }
+}
+
+// The messages sent between two games over the network, each as a line of text starting with its
+// name, such as "move e2e4".
+#[derive(Clone, PartialEq, Debug)]
+pub enum NetworkMessage {
+    // Sent by the joining side as soon as it connects.
+    Hello {
+        version: u32,
+        role: PeerRole,
+    },
+    // The host's answer to Hello, giving the joining player's color (or none for a spectator) and
+    // the game. It's followed by the moves played so far, then the clocks, and then Synced.
+    Welcome {
+        color: Option<Color>,
+        variant: Variant,
+        time_control: Option<TimeControl>,
+        starting_fen: String,
+    },
+    // The host's answer to Hello when the game can't be joined, after which it hangs up.
+    Reject(String),
+    // A move in coordinate notation, as used by UCI. Promotion isn't implemented, so neither are
+    // UCI's promotion letters.
+    Move {
+        from: TilePos,
+        to: TilePos,
+    },
+    // Ends the moves sent after Welcome. Every move after it is made by the other player, or by
+    // either player when spectating.
+    Synced,
+    // The time left on each side's clock, sent after each move so every game shows the same time.
+    Clock {
+        white_seconds: f32,
+        black_seconds: f32,
+    },
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    RequestTakeback,
+    AcceptTakeback,
+    DeclineTakeback,
+    // Pauses or resumes the game on both sides and for spectators, so a clock can't be stopped on
+    // one side only. Resigning, offering or answering resumes it too, as they're sent from the
+    // pause menu, which resumes the game.
+    Pause,
+    Resume,
+    // Tells spectators the last number of plies were taken back.
+    Undo {
+        plies: usize,
+    },
+    // Tells the other player and spectators how the game ended, such as "game-over 1-0
+    // resignation". The host decides how every game ends, and a game joined over the network only
+    // ends once it's sent this.
+    GameOver(GameEnded),
+}
+
+fn write_time_control(time_control: Option<TimeControl>) -> String {
+    time_control.map_or("untimed".to_string(), |time_control| {
+        time_control.get_name()
+    })
+}
+
+// Writes a message as a line of text, without the newline.
+pub fn write_message(message: &NetworkMessage) -> String {
+    match message {
+        NetworkMessage::Hello { version, role } => {
+            format!("hello {PROTOCOL_NAME} {version} {}", role.get_name())
+        }
+        NetworkMessage::Welcome {
+            color,
+            variant,
+            time_control,
+            starting_fen,
+        } => format!(
+            "welcome {} {} {} {starting_fen}",
+            color.map_or(PeerRole::Spectator.get_name().to_string(), |color| color
+                .get_name()
+                .to_lowercase()),
+            variant.get_name().to_lowercase(),
+            write_time_control(*time_control)
+        ),
+        NetworkMessage::Reject(reason) => format!("reject {reason}"),
+        NetworkMessage::Move { from, to } => format!("move {}", write_coordinate_move(from, to)),
+        NetworkMessage::Synced => "synced".to_string(),
+        NetworkMessage::Clock {
+            white_seconds,
+            black_seconds,
+        } => format!("clock {white_seconds} {black_seconds}"),
+        NetworkMessage::Resign => "resign".to_string(),
+        NetworkMessage::OfferDraw => "offer-draw".to_string(),
+        NetworkMessage::AcceptDraw => "accept-draw".to_string(),
+        NetworkMessage::DeclineDraw => "decline-draw".to_string(),
+        NetworkMessage::RequestTakeback => "request-takeback".to_string(),
+        NetworkMessage::AcceptTakeback => "accept-takeback".to_string(),
+        NetworkMessage::DeclineTakeback => "decline-takeback".to_string(),
+        NetworkMessage::Pause => "pause".to_string(),
+        NetworkMessage::Resume => "resume".to_string(),
+        NetworkMessage::Undo { plies } => format!("undo {plies}"),
+        NetworkMessage::GameOver(game_ended) => format!(
+            "game-over {} {}",
+            game_ended.result.to_pgn(),
+            game_ended.reason.get_name().replace(' ', "-")
+        ),
+    }
+}
+
+// Parses a line written by write_message().
+pub fn parse_message(line: &str) -> Result<NetworkMessage, String> {
+    let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+    let invalid = || format!("Invalid message \"{line}\"");
+    let message = match name {
+        "hello" => {
+            let mut fields = value.split(' ');
+            if fields.next() != Some(PROTOCOL_NAME) {
+                return Err(format!("Not a {PROTOCOL_NAME} game: \"{line}\""));
+            }
+            let version = fields
+                .next()
+                .and_then(|version| version.parse().ok())
+                .ok_or_else(invalid)?;
+            let role = match fields.next() {
+                Some("player") => PeerRole::Player,
+                Some("spectator") => PeerRole::Spectator,
+                _ => return Err(invalid()),
+            };
+            NetworkMessage::Hello { version, role }
+        }
+        "welcome" => {
+            let mut fields = value.splitn(4, ' ');
+            let (Some(color), Some(variant), Some(time_control), Some(starting_fen)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            NetworkMessage::Welcome {
+                color: match color {
+                    "spectator" => None,
+                    color => Some(color.parse()?),
+                },
+                variant: variant.parse()?,
+                time_control: match time_control {
+                    "untimed" => None,
+                    time_control => Some(time_control.parse()?),
+                },
+                starting_fen: starting_fen.to_string(),
+            }
+        }
+        "reject" => NetworkMessage::Reject(value.to_string()),
+        "move" => {
+            let (from, to) = parse_coordinate_move(value).ok_or_else(invalid)?;
+            NetworkMessage::Move { from, to }
+        }
+        "clock" => {
+            let (white_seconds, black_seconds) = value.split_once(' ').ok_or_else(invalid)?;
+            NetworkMessage::Clock {
+                white_seconds: white_seconds.parse().map_err(|_| invalid())?,
+                black_seconds: black_seconds.parse().map_err(|_| invalid())?,
+            }
+        }
+        "synced" => NetworkMessage::Synced,
+        "resign" => NetworkMessage::Resign,
+        "offer-draw" => NetworkMessage::OfferDraw,
+        "accept-draw" => NetworkMessage::AcceptDraw,
+        "decline-draw" => NetworkMessage::DeclineDraw,
+        "request-takeback" => NetworkMessage::RequestTakeback,
+        "accept-takeback" => NetworkMessage::AcceptTakeback,
+        "decline-takeback" => NetworkMessage::DeclineTakeback,
+        "pause" => NetworkMessage::Pause,
+        "resume" => NetworkMessage::Resume,
+        "undo" => NetworkMessage::Undo {
+            plies: value.parse().map_err(|_| invalid())?,
+        },
+        "game-over" => {
+            let (result, reason) = value.split_once(' ').ok_or_else(invalid)?;
+            let reason = GAME_END_REASONS
+                .into_iter()
+                .find(|other| other.get_name().replace(' ', "-") == reason)
+                .ok_or_else(invalid)?;
+            NetworkMessage::GameOver(GameEnded {
+                result: GameResult::from_pgn(result).ok_or_else(invalid)?,
+                reason,
+            })
+        }
+        _ => return Err(format!("Unknown message \"{line}\"")),
+    };
+    Ok(message)
+}
+
+// How far a connection has got.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PeerState {
+    // Waiting for Hello when hosting, or for Welcome when joining.
+    Greeting,
+    // Receiving the moves played before joining, up to Synced.
+    Syncing,
+    Playing,
+}
+
+// A connection to the game on another computer. The connection never blocks, so it can be checked
+// for messages every frame.
+pub struct NetworkPeer {
+    stream: TcpStream,
+    // Bytes received after the last complete line.
+    received_bytes: Vec<u8>,
+    // Bytes of sent messages that the connection hasn't taken yet.
+    unsent_bytes: Vec<u8>,
+    // The messages received, waiting to be handled in order.
+    pub incoming: VecDeque<NetworkMessage>,
+    pub state: PeerState,
+}
+
+impl NetworkPeer {
+    pub fn new(stream: TcpStream) -> Result<Self, String> {
+        stream
+            .set_nonblocking(true)
+            .and_then(|()| stream.set_nodelay(true))
+            .map_err(|error| format!("Couldn't set up the connection: {error}"))?;
+        Ok(NetworkPeer {
+            stream,
+            received_bytes: Vec::new(),
+            unsent_bytes: Vec::new(),
+            incoming: VecDeque::new(),
+            state: PeerState::Greeting,
+        })
+    }
+
+    // Connects to a hosted game, such as "192.168.1.20:7878". The port can be left out to use the
+    // default one.
+    pub fn connect(address: &str) -> Result<Self, String> {
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{address}:{DEFAULT_PORT}")
+        };
+        let socket_address = address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut socket_addresses| socket_addresses.next())
+            .ok_or_else(|| format!("Couldn't find {address}"))?;
+        let stream = TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT)
+            .map_err(|error| format!("Couldn't connect to {address}: {error}"))?;
+        NetworkPeer::new(stream)
+    }
+
+    // Sends a message. Whatever the connection can't take straight away, such as when a game's
+    // moves are all sent at once, is kept to be sent by flush() on a later frame.
+    pub fn send(&mut self, message: &NetworkMessage) -> Result<(), String> {
+        let mut line = write_message(message);
+        line.push('\n');
+        self.unsent_bytes.extend_from_slice(line.as_bytes());
+        self.flush()
+            .map_err(|error| format!("Couldn't send \"{}\": {error}", line.trim_end()))
+    }
+
+    // Sends as much of the messages waiting to be sent as the connection will take without waiting.
+    // Fails once the connection is closed.
+    pub fn flush(&mut self) -> Result<(), String> {
+        let mut sent_length = 0;
+        let result = loop {
+            if sent_length == self.unsent_bytes.len() {
+                break Ok(());
+            }
+            match self.stream.write(&self.unsent_bytes[sent_length..]) {
+                Ok(0) => break Err("The connection was closed".to_string()),
+                Ok(length) => sent_length += length,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => break Err(error.to_string()),
+            }
+        };
+        self.unsent_bytes.drain(..sent_length);
+        result
+    }
+
+    // Reads whatever has arrived, queueing each message in incoming. Lines that aren't messages are
+    // skipped, and returned as warnings. Fails once the connection is closed, after queueing the
+    // messages sent before it was, such as why a game was turned away.
+    pub fn receive(&mut self) -> Result<Vec<String>, String> {
+        let mut buffer = [0; 4096];
+        let result = loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => break Err("The connection was closed".to_string()),
+                Ok(length) => self.received_bytes.extend_from_slice(&buffer[..length]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break Ok(()),
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => break Err(error.to_string()),
+            }
+        };
+
+        let mut warnings = Vec::new();
+        let mut line_start = 0;
+        while let Some(length) = self.received_bytes[line_start..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+        {
+            let line_bytes = &self.received_bytes[line_start..line_start + length];
+            line_start += length + 1;
+            let line = String::from_utf8_lossy(line_bytes);
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match parse_message(line) {
+                Ok(message) => self.incoming.push_back(message),
+                Err(error) => warnings.push(error),
+            }
+        }
+        self.received_bytes.drain(..line_start);
+        result.map(|()| warnings)
+    }
+}
+
+// Listens for players and spectators to join on the given address, such as "0.0.0.0:7878".
+pub fn listen(address: &str) -> Result<TcpListener, String> {
+    let listener = TcpListener::bind(address)
+        .map_err(|error| format!("Couldn't host a game on {address}: {error}"))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|error| format!("Couldn't host a game on {address}: {error}"))?;
+    Ok(listener)
+}
@@ -0,0 +1,379 @@
+use crate::components::{Color, GamePiece, Piece};
+use crate::events::{MoveMade, SpecialMove};
+use crate::resources::{
+    AnnotationColor, Arrow, BoardAnnotations, PositionSnapshot, RightToCastle, SquareMark,
+};
+use bevy_ecs_tilemap::prelude::*;
+
+pub const STANDARD_STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// Returns the letter naming the file (column) of tiles at the x coordinate, starting from "a".
+pub fn get_file_letter(x: u32) -> char {
+    char::from(b'a' + x as u8)
+}
+
+// Returns the number naming the rank (row) of tiles at the y coordinate, starting from 1.
+pub fn get_rank_number(y: u32) -> u32 {
+    y + 1
+}
+
+// Writes the tile's name in algebraic notation, such as "e4".
+pub fn write_square(tile_pos: &TilePos) -> String {
+    format!(
+        "{}{}",
+        get_file_letter(tile_pos.x),
+        get_rank_number(tile_pos.y)
+    )
+}
+
+// Writes a move in standard algebraic notation (SAN), such as "Nbd7", "exd5+" or "O-O". The origins
+// of any other pieces of the same kind that could also have moved to the destination are needed to
+// tell them apart.
+pub fn write_san(
+    move_made: &MoveMade,
+    ambiguous_origins: &[TilePos],
+    is_check: bool,
+    is_checkmate: bool,
+) -> String {
+    let mut san = String::new();
+    if let Some(SpecialMove::Castling { rook_from, .. }) = move_made.special_move {
+        san.push_str(if rook_from.x > move_made.from.x {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        if move_made.game_piece.piece == Piece::Pawn {
+            // Pawns are unnamed, but a capturing pawn is named by its file.
+            if move_made.captured.is_some() {
+                san.push(get_file_letter(move_made.from.x));
+            }
+        } else {
+            san.push(move_made.game_piece.piece.get_letter());
+            // Name the origin's file if it tells the pieces apart, otherwise its rank, and
+            // otherwise both.
+            if !ambiguous_origins.is_empty() {
+                let is_file_unique = ambiguous_origins
+                    .iter()
+                    .all(|origin| origin.x != move_made.from.x);
+                let is_rank_unique = ambiguous_origins
+                    .iter()
+                    .all(|origin| origin.y != move_made.from.y);
+                if is_file_unique {
+                    san.push(get_file_letter(move_made.from.x));
+                } else if is_rank_unique {
+                    san.push_str(&get_rank_number(move_made.from.y).to_string());
+                } else {
+                    san.push_str(&write_square(&move_made.from));
+                }
+            }
+        }
+        if move_made.captured.is_some() {
+            san.push('x');
+        }
+        san.push_str(&write_square(&move_made.to));
+    }
+
+    if is_checkmate {
+        san.push('#');
+    } else if is_check {
+        san.push('+');
+    }
+    san
+}
+
+// Parses the piece placement field of FEN notation, such as
+// "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR" for the standard starting position. Ranks are
+// listed from the last rank down to the first and separated by slashes, uppercase letters are white
+// pieces and lowercase letters are black pieces, and numbers count empty tiles (these may have more
+// than one digit on boards wider than 9 tiles). Returns every piece along with the tile it's placed
+// on.
+pub fn parse_placement(
+    placement: &str,
+    map_size: &TilemapSize,
+) -> Result<Vec<(TilePos, GamePiece)>, String> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() as u32 != map_size.y {
+        return Err(format!(
+            "Placement has {} ranks, but the board has {}",
+            ranks.len(),
+            map_size.y
+        ));
+    }
+
+    let mut pieces = Vec::new();
+    for (rank, y) in ranks.iter().zip((0..map_size.y).rev()) {
+        let too_wide = || {
+            format!(
+                "Rank \"{rank}\" is wider than the board's {} tiles",
+                map_size.x
+            )
+        };
+        let mut x: u32 = 0;
+        let mut empty_tiles: u32 = 0;
+        for letter in rank.chars() {
+            if let Some(digit) = letter.to_digit(10) {
+                // A count too large to fit is far wider than any board.
+                empty_tiles = empty_tiles
+                    .checked_mul(10)
+                    .and_then(|empty_tiles| empty_tiles.checked_add(digit))
+                    .ok_or_else(too_wide)?;
+                continue;
+            }
+            x = x.checked_add(empty_tiles).ok_or_else(too_wide)?;
+            empty_tiles = 0;
+
+            let piece = Piece::from_letter(letter)
+                .ok_or_else(|| format!("Unknown piece \"{letter}\" in rank \"{rank}\""))?;
+            if x >= map_size.x {
+                return Err(too_wide());
+            }
+            let color = if letter.is_ascii_uppercase() {
+                Color::White
+            } else {
+                Color::Black
+            };
+            pieces.push((TilePos { x, y }, GamePiece { piece, color }));
+            x += 1;
+        }
+        x = x.checked_add(empty_tiles).ok_or_else(too_wide)?;
+
+        if x != map_size.x {
+            return Err(format!(
+                "Rank \"{rank}\" covers {x} tiles, but the board is {} tiles wide",
+                map_size.x
+            ));
+        }
+    }
+
+    Ok(pieces)
+}
+
+// Writes pieces as the piece placement field of FEN notation. This is the reverse of
+// parse_placement().
+pub fn write_placement(pieces: &[(TilePos, GamePiece)], map_size: &TilemapSize) -> String {
+    let mut ranks = Vec::with_capacity(map_size.y as usize);
+    for y in (0..map_size.y).rev() {
+        let mut rank = String::new();
+        let mut empty_tiles = 0;
+        for x in 0..map_size.x {
+            match pieces
+                .iter()
+                .find(|(tile_pos, _)| *tile_pos == TilePos { x, y })
+            {
+                Some((_, game_piece)) => {
+                    if empty_tiles > 0 {
+                        rank.push_str(&empty_tiles.to_string());
+                        empty_tiles = 0;
+                    }
+                    rank.push(game_piece.get_letter());
+                }
+                None => empty_tiles += 1,
+            }
+        }
+        if empty_tiles > 0 {
+            rank.push_str(&empty_tiles.to_string());
+        }
+        ranks.push(rank);
+    }
+    ranks.join("/")
+}
+
+// Writes a position in full FEN notation. En passant isn't implemented yet, so there is never an
+// en passant target square.
+pub fn write_fen(
+    pieces: &[(TilePos, GamePiece)],
+    map_size: &TilemapSize,
+    color_to_move: Color,
+    right_to_castle: &RightToCastle,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+) -> String {
+    let color_to_move = match color_to_move {
+        Color::White => "w",
+        Color::Black => "b",
+    };
+
+    let mut castling = String::new();
+    for (has_right, letter) in [
+        (right_to_castle.white_kingside, 'K'),
+        (right_to_castle.white_queenside, 'Q'),
+        (right_to_castle.black_kingside, 'k'),
+        (right_to_castle.black_queenside, 'q'),
+    ] {
+        if has_right {
+            castling.push(letter);
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+
+    format!(
+        "{} {color_to_move} {castling} - {halfmove_clock} {fullmove_number}",
+        write_placement(pieces, map_size)
+    )
+}
+
+// Parses a position in full FEN notation, such as the standard starting position
+// "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1". En passant isn't implemented yet, so
+// the en passant target square is ignored, as are the move counters.
+pub fn parse_fen(fen: &str, map_size: &TilemapSize) -> Result<PositionSnapshot, String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let [placement, color_to_move, castling, ..] = fields[..] else {
+        return Err(format!(
+            "FEN \"{fen}\" needs a placement, color to move and castling"
+        ));
+    };
+
+    let color_to_move = match color_to_move {
+        "w" => Color::White,
+        "b" => Color::Black,
+        _ => return Err(format!("Unknown color to move \"{color_to_move}\"")),
+    };
+
+    let mut right_to_castle = RightToCastle {
+        white_kingside: false,
+        white_queenside: false,
+        black_kingside: false,
+        black_queenside: false,
+    };
+    if castling != "-" {
+        for letter in castling.chars() {
+            match letter {
+                'K' => right_to_castle.white_kingside = true,
+                'Q' => right_to_castle.white_queenside = true,
+                'k' => right_to_castle.black_kingside = true,
+                'q' => right_to_castle.black_queenside = true,
+                _ => return Err(format!("Unknown right to castle \"{letter}\"")),
+            }
+        }
+    }
+
+    Ok(PositionSnapshot {
+        pieces: parse_placement(placement, map_size)?,
+        color_to_move,
+        right_to_castle,
+    })
+}
+
+// Parses a tile's name in algebraic notation, such as "e4". Ranks may have more than one digit on
+// boards taller than 9 tiles.
+pub fn parse_square(square: &str) -> Option<TilePos> {
+    let mut chars = square.chars();
+    let file = chars.next().filter(char::is_ascii_lowercase)?;
+    let rank: u32 = chars.as_str().parse().ok()?;
+    Some(TilePos {
+        x: file as u32 - 'a' as u32,
+        y: rank.checked_sub(1)?,
+    })
+}
+
+// Writes a move in coordinate notation (as used by UCI), which is the names of the tiles it's from
+// and to, such as "e2e4".
+pub fn write_coordinate_move(from: &TilePos, to: &TilePos) -> String {
+    format!("{}{}", write_square(from), write_square(to))
+}
+
+// Parses a move written by write_coordinate_move(). The tile it's to starts at the second letter,
+// as the rank of the tile it's from may have two digits.
+pub fn parse_coordinate_move(coordinates: &str) -> Option<(TilePos, TilePos)> {
+    let (split, _) = coordinates
+        .char_indices()
+        .skip(1)
+        .find(|(_, letter)| letter.is_ascii_lowercase())?;
+    Some((
+        parse_square(&coordinates[..split])?,
+        parse_square(&coordinates[split..])?,
+    ))
+}
+
+// Writes the arrows and marked squares as the commands used in PGN comments, such as
+// "[%csl Rd5][%cal Ge2e4,Bg1f3]".
+pub fn write_annotations(annotations: &BoardAnnotations) -> String {
+    let mut commands = String::new();
+    if !annotations.square_marks.is_empty() {
+        let square_marks: Vec<String> = annotations
+            .square_marks
+            .iter()
+            .map(|square_mark| {
+                format!(
+                    "{}{}",
+                    square_mark.color.get_letter(),
+                    write_square(&square_mark.tile_pos)
+                )
+            })
+            .collect();
+        commands.push_str(&format!("[%csl {}]", square_marks.join(",")));
+    }
+    if !annotations.arrows.is_empty() {
+        let arrows: Vec<String> = annotations
+            .arrows
+            .iter()
+            .map(|arrow| {
+                format!(
+                    "{}{}{}",
+                    arrow.color.get_letter(),
+                    write_square(&arrow.from),
+                    write_square(&arrow.to)
+                )
+            })
+            .collect();
+        commands.push_str(&format!("[%cal {}]", arrows.join(",")));
+    }
+    commands
+}
+
+// Parses the arrows and marked squares from a PGN comment. Anything else in the comment, including
+// arrows or squares that can't be read, is ignored.
+pub fn parse_annotations(comment: &str) -> BoardAnnotations {
+    // Returns the comma-separated entries of every command with the name, such as "%cal".
+    let get_entries = |name: &str| -> Vec<String> {
+        comment
+            .split('[')
+            .filter_map(|command| command.split_once(']').map(|(command, _)| command))
+            .filter_map(|command| command.trim().strip_prefix(name))
+            .flat_map(|entries| entries.split(','))
+            .map(|entry| entry.trim().to_string())
+            .collect()
+    };
+    // Splits an entry such as "Ge2e4" into its color and squares.
+    let parse_entry = |entry: &str| {
+        let mut chars = entry.chars();
+        let color = AnnotationColor::from_letter(chars.next()?)?;
+        Some((color, chars.as_str().to_string()))
+    };
+
+    let square_marks = get_entries("%csl")
+        .iter()
+        .filter_map(|entry| {
+            let (color, square) = parse_entry(entry)?;
+            Some(SquareMark {
+                tile_pos: parse_square(&square)?,
+                color,
+            })
+        })
+        .collect();
+    let arrows = get_entries("%cal")
+        .iter()
+        .filter_map(|entry| {
+            let (color, squares) = parse_entry(entry)?;
+            // The destination starts at the second letter, as the origin's rank may have two
+            // digits.
+            let split = squares
+                .char_indices()
+                .skip(1)
+                .find(|(_, letter)| letter.is_ascii_alphabetic())?
+                .0;
+            Some(Arrow {
+                from: parse_square(&squares[..split])?,
+                to: parse_square(&squares[split..])?,
+                color,
+            })
+        })
+        .collect();
+    BoardAnnotations {
+        arrows,
+        square_marks,
+    }
+}
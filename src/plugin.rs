@@ -0,0 +1,501 @@
+use crate::events::{
+    GameActionRequested, GameEnded, KingInCheck, MoveMade, MoveRequested, MovesUndone,
+    OfferAnswered, UndoRequested,
+};
+use crate::resources::{
+    AppState, BoardOrientation, BoardScale, BoardTheme, CheckedKingTile, Clocks, ColorToMove,
+    CoordinateLabels, CursorPos, GameOutcome, GameTags, InGame, InputMode, LastMove,
+    LegalMoveHints, LocalPlayer, MoveAnimation, MoveHistory, MustRecalculateLegalMoves,
+    NetworkGame, NewGameSettings, Odds, OfferPolicy, PendingOffer, PieceSet, PositionEditor,
+    Premoves, ResumedGame, RightToCastle, SaveFile, SelectedPiece, SelectedPieceOriginalPosition,
+    SettingsFile, SoundSettings, StartingPlacement, ThemeCatalog, UserSettings, Variant,
+};
+use crate::system::actions::{
+    answer_offers_by_policy, handle_game_action_buttons, handle_game_actions, resolve_offers,
+    undo_moves,
+};
+use crate::system::animation::{
+    animate_moves, animate_undone_moves, sync_piece_sprites, update_piece_animations,
+};
+use crate::system::annotations::{draw_annotations, update_annotation_sprites};
+use crate::system::captures::{setup_capture_trays, update_capture_trays};
+use crate::system::clock::{
+    rewind_clocks, setup_clock_panel, setup_clocks, update_clock_panel, update_clocks,
+};
+use crate::system::coordinates::{
+    setup_coordinate_labels, toggle_coordinate_labels, update_coordinate_labels,
+};
+use crate::system::editor::{
+    edit_tile, handle_editor_buttons, is_editing, setup_editor_panel, toggle_position_editor,
+    update_editor_panel,
+};
+use crate::system::game_state::{end_game, teardown_game, toggle_pause};
+use crate::system::history::{is_live, record_move};
+use crate::system::input::{toggle_legal_move_hints, update_cursor_pos};
+use crate::system::menu::{
+    handle_menu_buttons, setup_game_over_panel, setup_main_menu, setup_new_game_screen,
+    setup_pause_menu, setup_settings_screen, update_offer_panel, update_setting_texts,
+};
+use crate::system::move_list::{handle_move_list_buttons, setup_move_list_panel, update_move_list};
+use crate::system::network::{
+    accept_network_connections, greet_network_peers, handle_network_actions,
+    handle_network_messages, has_remote_opponent, is_spectating, leave_network_game,
+    play_network_moves, receive_network_messages, send_network_actions, send_network_moves,
+    send_network_pauses,
+};
+use crate::system::orientation::{
+    apply_board_orientation, auto_flip_board, fit_board_to_window, flip_board,
+};
+use crate::system::premove::{
+    cancel_premoves, is_opponents_turn, pick_up_premove_piece, play_premove, queue_premove,
+};
+use crate::system::save::{resume_saved_game, save_game};
+use crate::system::settings::{
+    apply_settings, load_settings, record_settings, reload_settings, save_settings,
+};
+use crate::system::setup::{setup_board, setup_camera, setup_cursor, setup_pieces};
+use crate::system::sound::{adjust_sound_settings, play_sound_effects, setup_sound_effects};
+use crate::system::theme::{apply_board_theme, apply_piece_set, find_theme_catalog};
+use crate::system::update::{
+    find_mouseover_tile, highlight_tile, is_drag_released, make_move, pick_up_piece,
+    put_down_piece, recalculate_legal_moves, record_last_move, update_cursor_display,
+    update_cursor_piece,
+};
+use bevy::app::PluginGroupBuilder;
+use bevy::input::common_conditions::input_just_pressed;
+use bevy::prelude::*;
+use bevy::render::RenderApp;
+use bevy::window::WindowResized;
+use bevy_ecs_tilemap::prelude::*;
+
+// The stages of a frame of the game, which run in this order during Update. Systems from other
+// plugins can be ordered relative to these, such as running after ChessSet::LegalMoves to see the
+// legal moves for the position after the latest move. The input, move and legal move stages only
+// run while the game is being played, and the others while there's a game on the board.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChessSet {
+    // Reading the cursor and keyboard, and finding the tile under the cursor.
+    Input,
+    // Handling the position editor panel.
+    Ui,
+    // Picking up and putting down pieces, or editing tiles in the position editor.
+    Move,
+    // Recalculating the legal moves after the position changes.
+    LegalMoves,
+    // Updating tile highlights, the cursor display and the board's transform.
+    Render,
+}
+
+// Adds the whole game. This doesn't add Bevy's own plugins, so an app should add DefaultPlugins
+// (or at least MinimalPlugins, AssetPlugin, InputPlugin and StatesPlugin) first. The app starts in
+// the main menu, unless another AppState is inserted beforehand. Any of the game's resources
+// inserted beforehand (such as the variant) are kept. Each part of the game is its own plugin, so
+// for example the renderer can be swapped out with:
+//     ChessPlugin.build().disable::<ChessRenderingPlugin>()
+pub struct ChessPlugin;
+
+impl PluginGroup for ChessPlugin {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(ChessRulesPlugin)
+            .add(ChessSettingsPlugin)
+            .add(ChessInputPlugin)
+            .add(ChessRenderingPlugin)
+            .add(ChessUiPlugin)
+            .add(ChessAudioPlugin)
+            .add(ChessNetworkPlugin)
+    }
+}
+
+// The board, the game's state and the rules for moving pieces.
+pub struct ChessRulesPlugin;
+
+impl Plugin for ChessRulesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SelectedPiece>()
+            .init_resource::<SelectedPieceOriginalPosition>()
+            .init_resource::<ColorToMove>()
+            .init_resource::<MustRecalculateLegalMoves>()
+            .init_resource::<RightToCastle>()
+            .init_resource::<Variant>()
+            // The starting placement defaults to the variant's, so it must be initialized after it.
+            .init_resource::<StartingPlacement>()
+            .init_resource::<Odds>()
+            .init_resource::<GameTags>()
+            .init_resource::<PositionEditor>()
+            .init_resource::<BoardOrientation>()
+            .init_resource::<LastMove>()
+            .init_resource::<CheckedKingTile>()
+            .init_resource::<MoveHistory>()
+            .init_resource::<LocalPlayer>()
+            .init_resource::<Premoves>()
+            .init_resource::<NewGameSettings>()
+            .init_resource::<Clocks>()
+            .init_resource::<GameOutcome>()
+            .init_resource::<PendingOffer>()
+            .init_resource::<OfferPolicy>()
+            .init_resource::<SaveFile>()
+            .init_resource::<ResumedGame>()
+            .init_state::<AppState>()
+            .add_computed_state::<InGame>()
+            .enable_state_scoped_entities::<AppState>()
+            .enable_state_scoped_entities::<InGame>()
+            .add_event::<MoveRequested>()
+            .add_event::<MoveMade>()
+            .add_event::<KingInCheck>()
+            .add_event::<GameEnded>()
+            .add_event::<GameActionRequested>()
+            .add_event::<OfferAnswered>()
+            .add_event::<UndoRequested>()
+            .add_event::<MovesUndone>()
+            .configure_sets(
+                Update,
+                (
+                    ChessSet::Input,
+                    ChessSet::Ui,
+                    ChessSet::Move,
+                    ChessSet::LegalMoves,
+                    ChessSet::Render,
+                )
+                    .chain(),
+            )
+            .configure_sets(
+                Update,
+                (ChessSet::Input, ChessSet::Move, ChessSet::LegalMoves)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .configure_sets(
+                Update,
+                (ChessSet::Ui, ChessSet::Render).run_if(in_state(InGame)),
+            )
+            .add_systems(
+                OnEnter(InGame),
+                (
+                    (setup_board, setup_pieces).chain(),
+                    setup_clocks,
+                    resume_saved_game.after(setup_pieces).after(setup_clocks),
+                ),
+            )
+            // The game is saved when it's left, on demand with S, and when the app is closed. Its
+            // save is deleted once it's over. A game being watched over the network isn't this
+            // player's to save.
+            .add_systems(
+                OnExit(InGame),
+                (save_game.run_if(not(is_spectating)), teardown_game).chain(),
+            )
+            .add_systems(
+                OnEnter(AppState::GameOver),
+                save_game.run_if(not(is_spectating)),
+            )
+            .add_systems(
+                Update,
+                save_game
+                    .run_if(not(is_spectating))
+                    .run_if(input_just_pressed(KeyCode::KeyS))
+                    .in_set(ChessSet::Input),
+            )
+            .add_systems(
+                Last,
+                save_game
+                    .run_if(on_event::<AppExit>())
+                    .run_if(in_state(InGame))
+                    .run_if(not(is_spectating)),
+            )
+            .add_systems(
+                Update,
+                (make_move.run_if(not(is_editing)), record_last_move)
+                    .chain()
+                    .in_set(ChessSet::Move),
+            )
+            .add_systems(
+                Update,
+                (
+                    recalculate_legal_moves,
+                    update_clocks.run_if(not(is_editing)),
+                    record_move,
+                    // Resigning, offers and takebacks, after the move that may have been made.
+                    handle_game_actions,
+                    answer_offers_by_policy,
+                    resolve_offers,
+                    undo_moves,
+                    rewind_clocks,
+                    play_premove,
+                    end_game,
+                )
+                    .chain()
+                    .in_set(ChessSet::LegalMoves),
+            );
+    }
+}
+
+// The settings file, and the board themes and piece sets in the assets folder that it can name. The
+// file is read at startup and kept in step with the resources of the other plugins that use the
+// settings, so any of those plugins can be disabled.
+pub struct ChessSettingsPlugin;
+
+impl Plugin for ChessSettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SettingsFile>()
+            .init_resource::<ThemeCatalog>()
+            // The board themes and piece sets are looked for before the file is read, and again
+            // when the settings screen is opened, to find newly added ones. Changes are recorded
+            // after the rest of the frame, so toggles such as H are seen the frame they're pressed.
+            .add_systems(Startup, (find_theme_catalog, load_settings).chain())
+            .add_systems(OnEnter(AppState::Settings), find_theme_catalog)
+            .add_systems(
+                Update,
+                (
+                    reload_settings,
+                    apply_settings.run_if(resource_changed::<UserSettings>),
+                    record_settings,
+                    save_settings.run_if(resource_changed::<UserSettings>),
+                )
+                    .chain()
+                    .after(ChessSet::Render),
+            );
+    }
+}
+
+// Turning the mouse and keyboard into the cursor's position, the tile under it, picking up and
+// putting down pieces, premoves, board flips, annotations and pausing.
+pub struct ChessInputPlugin;
+
+impl Plugin for ChessInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorPos>()
+            .init_resource::<InputMode>()
+            .add_systems(First, update_cursor_pos)
+            .add_systems(
+                Update,
+                (flip_board, find_mouseover_tile)
+                    .chain()
+                    .in_set(ChessSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    pick_up_piece
+                        .run_if(not(is_editing))
+                        .run_if(is_live)
+                        .run_if(not(is_opponents_turn))
+                        .run_if(not(is_spectating)),
+                    put_down_piece
+                        .run_if(not(is_editing))
+                        .run_if(is_live)
+                        .run_if(not(is_opponents_turn))
+                        .run_if(not(is_spectating))
+                        .run_if(input_just_pressed(MouseButton::Left).or_else(is_drag_released)),
+                )
+                    .chain()
+                    .in_set(ChessSet::Move)
+                    .before(make_move),
+            )
+            // Premoves are queued by clicking like normal moves, but during the opponent's turn.
+            .add_systems(
+                Update,
+                (
+                    cancel_premoves.run_if(input_just_pressed(MouseButton::Right)),
+                    pick_up_premove_piece.run_if(is_opponents_turn),
+                    queue_premove
+                        .run_if(is_opponents_turn)
+                        .run_if(input_just_pressed(MouseButton::Left).or_else(is_drag_released)),
+                )
+                    .chain()
+                    .run_if(not(is_editing))
+                    .run_if(is_live)
+                    .run_if(not(is_spectating))
+                    .in_set(ChessSet::Move)
+                    .before(make_move),
+            )
+            .add_systems(
+                Update,
+                (
+                    auto_flip_board.after(put_down_piece),
+                    draw_annotations.run_if(not(is_editing)),
+                )
+                    .in_set(ChessSet::Move),
+            )
+            // Pausing works in both directions, so it isn't in the sets that stop while paused.
+            .add_systems(
+                Update,
+                toggle_pause.run_if(input_just_pressed(KeyCode::Escape)),
+            );
+    }
+}
+
+// Drawing the board with bevy_ecs_tilemap, along with the camera and the piece on the cursor. This
+// includes the board theme, piece set and the display options, such as move hints (toggled with H)
+// and tile names (toggled with C).
+pub struct ChessRenderingPlugin;
+
+impl Plugin for ChessRenderingPlugin {
+    fn build(&self, app: &mut App) {
+        // bevy_ecs_tilemap draws with Bevy's renderer, which isn't there when running headlessly
+        // (such as in tests). Everything else still runs, it just isn't drawn.
+        if app.get_sub_app(RenderApp).is_some() && !app.is_plugin_added::<TilemapPlugin>() {
+            app.add_plugins(TilemapPlugin);
+        }
+        app.init_resource::<BoardTheme>()
+            .init_resource::<PieceSet>()
+            .init_resource::<BoardScale>()
+            .init_resource::<LegalMoveHints>()
+            .init_resource::<CoordinateLabels>()
+            .init_resource::<MoveAnimation>()
+            .add_systems(Startup, setup_camera)
+            .add_systems(
+                Update,
+                (
+                    // The board is refitted when the window is resized, and when its size in tiles
+                    // changes with the variant.
+                    fit_board_to_window.run_if(
+                        on_event::<WindowResized>()
+                            .or_else(resource_changed::<BoardScale>)
+                            .or_else(resource_changed::<Variant>),
+                    ),
+                    apply_board_theme.run_if(resource_changed::<BoardTheme>),
+                ),
+            )
+            .add_systems(
+                OnEnter(InGame),
+                (
+                    apply_board_theme.after(setup_board),
+                    setup_cursor,
+                    setup_coordinate_labels,
+                ),
+            )
+            .add_systems(
+                Update,
+                (toggle_legal_move_hints, toggle_coordinate_labels).in_set(ChessSet::Input),
+            )
+            .add_systems(
+                Update,
+                (
+                    highlight_tile,
+                    update_cursor_piece.run_if(resource_changed::<SelectedPiece>),
+                    update_cursor_display,
+                    apply_board_orientation,
+                    update_coordinate_labels,
+                    update_annotation_sprites,
+                    (
+                        animate_moves,
+                        animate_undone_moves,
+                        update_piece_animations,
+                        sync_piece_sprites,
+                    )
+                        .chain(),
+                )
+                    .in_set(ChessSet::Render),
+            );
+    }
+}
+
+// Sound effects for moves, checks and the end of the game.
+pub struct ChessAudioPlugin;
+
+impl Plugin for ChessAudioPlugin {
+    fn build(&self, app: &mut App) {
+        // Without Bevy's audio plugin (such as in tests), the sounds are still made but never
+        // played.
+        if !app.world().contains_resource::<Assets<Pitch>>() {
+            app.init_asset::<Pitch>();
+        }
+        app.init_resource::<SoundSettings>()
+            .add_systems(Startup, setup_sound_effects)
+            .add_systems(Update, adjust_sound_settings.in_set(ChessSet::Input))
+            .add_systems(Update, play_sound_effects.in_set(ChessSet::Render));
+    }
+}
+
+// The menus, the position editor's panel and its editing of tiles, and the panels beside the board.
+// These draw pieces with the rendering plugin's piece set, and the settings screen changes the
+// settings plugin's settings, so both of those plugins are needed too.
+pub struct ChessUiPlugin;
+
+impl Plugin for ChessUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::MainMenu), setup_main_menu)
+            .add_systems(OnEnter(AppState::NewGameSetup), setup_new_game_screen)
+            .add_systems(OnEnter(AppState::Settings), setup_settings_screen)
+            .add_systems(OnEnter(AppState::Paused), setup_pause_menu)
+            .add_systems(OnEnter(AppState::GameOver), setup_game_over_panel)
+            .add_systems(
+                Update,
+                (
+                    handle_menu_buttons,
+                    update_setting_texts,
+                    apply_piece_set.run_if(resource_changed::<PieceSet>),
+                ),
+            )
+            .add_systems(
+                OnEnter(InGame),
+                (
+                    setup_editor_panel,
+                    setup_move_list_panel,
+                    setup_capture_trays,
+                    setup_clock_panel,
+                ),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_position_editor,
+                    handle_editor_buttons,
+                    update_editor_panel,
+                )
+                    .chain()
+                    .run_if(in_state(AppState::Playing))
+                    .run_if(not(has_remote_opponent))
+                    .in_set(ChessSet::Ui),
+            )
+            .add_systems(
+                Update,
+                (
+                    handle_move_list_buttons,
+                    update_move_list,
+                    update_capture_trays,
+                    update_clock_panel,
+                    handle_game_action_buttons,
+                    update_offer_panel.run_if(resource_changed::<PendingOffer>),
+                )
+                    .chain()
+                    .in_set(ChessSet::Ui),
+            )
+            .add_systems(Update, edit_tile.run_if(is_editing).in_set(ChessSet::Move));
+    }
+}
+
+// Playing against someone on another computer over TCP, once a NetworkGame is hosted or joined, and
+// watching hosted games. Moves from the other player are played like any other MoveRequested, but
+// only once they've been checked against the legal moves.
+pub struct ChessNetworkPlugin;
+
+impl Plugin for ChessNetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkGame>()
+            .add_systems(
+                Update,
+                (
+                    accept_network_connections.run_if(in_state(InGame)),
+                    receive_network_messages,
+                    greet_network_peers,
+                    // Before the other player's pause or resume is handled, so it isn't sent back.
+                    send_network_pauses,
+                    handle_network_messages,
+                    handle_network_actions.run_if(in_state(AppState::Playing)),
+                )
+                    .chain()
+                    .before(ChessSet::Input),
+            )
+            .add_systems(Update, play_network_moves.in_set(ChessSet::Input))
+            .add_systems(
+                Update,
+                send_network_moves
+                    .after(update_clocks)
+                    .in_set(ChessSet::LegalMoves),
+            )
+            // This runs in every state, so a resignation is sent even though it ends the game.
+            .add_systems(Update, send_network_actions.after(ChessSet::LegalMoves))
+            // The game is saved first, while it's still known whether it was being watched.
+            .add_systems(OnExit(InGame), leave_network_game.after(save_game));
+    }
+}
@@ -1,6 +1,431 @@
-use crate::components::{Color, GamePiece};
+use crate::components::{Color, GamePiece, Piece, SoundEffect};
+use crate::events::{GameActionRequested, GameEnded, MoveMade};
+use crate::network::{listen, NetworkMessage, NetworkPeer, PeerRole, PROTOCOL_VERSION};
+use crate::notation::write_annotations;
+use crate::save::SavedGame;
+use crate::settings::get_settings_path;
+use crate::system::update::is_king_threatened;
 use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+// The screens the app moves between. A game is set up when play starts, kept while it's paused or
+// over, and torn down when returning to the menus.
+#[derive(States, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum AppState {
+    #[default]
+    MainMenu,
+    NewGameSetup,
+    Settings,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+// The states in which a game is on the board, whether or not it's being played. The board and
+// everything else belonging to the game only exists in these states.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct InGame;
+
+impl ComputedStates for InGame {
+    type SourceStates = AppState;
+
+    fn compute(app_state: AppState) -> Option<Self> {
+        match app_state {
+            AppState::Playing | AppState::Paused | AppState::GameOver => Some(InGame),
+            AppState::MainMenu | AppState::NewGameSetup | AppState::Settings => None,
+        }
+    }
+}
+
+// Who plays the side not played with the mouse.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Opponent {
+    // Both sides are played on this computer.
+    Local,
+    // The other side is played somewhere else, such as by an engine or over the network, which
+    // sends its moves as MoveRequested events.
+    Remote,
+}
+
+impl Opponent {
+    pub fn get_name(&self) -> &str {
+        match self {
+            Opponent::Local => "Same computer",
+            Opponent::Remote => "Remote",
+        }
+    }
+}
+
+// The time each side starts with, and the time added to a side's clock after each of its moves.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeControl {
+    pub minutes: u32,
+    pub increment_seconds: u32,
+}
+
+impl TimeControl {
+    // Returns the time control written as minutes and increment, such as "5+3".
+    pub fn get_name(&self) -> String {
+        format!("{}+{}", self.minutes, self.increment_seconds)
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    // Parses minutes and increment, such as "5+3".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once('+')
+            .and_then(|(minutes, increment_seconds)| {
+                Some(TimeControl {
+                    minutes: minutes.parse().ok()?,
+                    increment_seconds: increment_seconds.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| format!("Invalid time control \"{s}\""))
+    }
+}
+
+// The time controls offered on the new game screen, where None is untimed.
+pub const TIME_CONTROLS: [Option<TimeControl>; 6] = [
+    None,
+    Some(TimeControl {
+        minutes: 1,
+        increment_seconds: 0,
+    }),
+    Some(TimeControl {
+        minutes: 3,
+        increment_seconds: 2,
+    }),
+    Some(TimeControl {
+        minutes: 5,
+        increment_seconds: 0,
+    }),
+    Some(TimeControl {
+        minutes: 10,
+        increment_seconds: 5,
+    }),
+    Some(TimeControl {
+        minutes: 15,
+        increment_seconds: 10,
+    }),
+];
+
+// The choices on the new game screen, other than the variant, which is kept in its own resource.
+#[derive(Resource, Clone, Copy)]
+pub struct NewGameSettings {
+    pub opponent: Opponent,
+    pub time_control: Option<TimeControl>,
+    // The color played with the mouse against a remote opponent (or shown at the bottom of the
+    // board against a local one), or None to pick one at random.
+    pub color: Option<Color>,
+}
+
+impl Default for NewGameSettings {
+    fn default() -> Self {
+        NewGameSettings {
+            opponent: Opponent::Local,
+            time_control: None,
+            color: Some(Color::White),
+        }
+    }
+}
+
+// The time each side has left in a timed game, in seconds.
+#[derive(Resource, Clone, Copy, Default)]
+pub struct Clocks {
+    pub time_control: Option<TimeControl>,
+    pub white_seconds: f32,
+    pub black_seconds: f32,
+}
+
+impl Clocks {
+    // Starts both clocks from the time control's starting time, if the game is timed.
+    pub fn new(time_control: Option<TimeControl>) -> Self {
+        let seconds = time_control.map_or(0.0, |time_control| time_control.minutes as f32 * 60.0);
+        Clocks {
+            time_control,
+            white_seconds: seconds,
+            black_seconds: seconds,
+        }
+    }
+
+    pub fn get_seconds_mut(&mut self, color: Color) -> &mut f32 {
+        match color {
+            Color::White => &mut self.white_seconds,
+            Color::Black => &mut self.black_seconds,
+        }
+    }
+
+    pub fn get_seconds(&self, color: Color) -> f32 {
+        match color {
+            Color::White => self.white_seconds,
+            Color::Black => self.black_seconds,
+        }
+    }
+}
+
+// Where the unfinished game is saved when leaving it, so it can be continued from the main menu, or
+// None to never save games.
+#[derive(Resource)]
+pub struct SaveFile(pub Option<PathBuf>);
+
+impl Default for SaveFile {
+    fn default() -> Self {
+        SaveFile(Some(PathBuf::from("saved_game.txt")))
+    }
+}
+
+// A saved game to set up in place of a new one when play next starts.
+#[derive(Resource, Default)]
+pub struct ResumedGame(pub Option<SavedGame>);
+
+// How the last game ended, once it has.
+#[derive(Resource, Default)]
+pub struct GameOutcome(pub Option<GameEnded>);
+
+// The draw offer or takeback request waiting for the other side to answer. Only one can wait at a
+// time, and it stands until it's answered, even if more moves are made meanwhile.
+#[derive(Resource, Default)]
+pub struct PendingOffer(pub Option<GameActionRequested>);
+
+// How a remote opponent that isn't another player, such as an engine, answers draw offers and
+// takeback requests, as it can't be asked. It can be replaced to answer otherwise.
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OfferPolicy {
+    pub accepts_draws: bool,
+    pub accepts_takebacks: bool,
+}
+
+impl Default for OfferPolicy {
+    fn default() -> Self {
+        OfferPolicy {
+            accepts_draws: false,
+            accepts_takebacks: true,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Variant {
+    #[default]
+    Standard,
+    // Played on a 10x8 board, with an archbishop and chancellor added to each side.
+    Capablanca,
+    // Played on a 10x10 board, with an archbishop and chancellor added to each side. Only the rooks
+    // start on the first rank, and there is no castling.
+    Grand,
+    // White has 36 pawns and no king against black's usual army. Pawns on white's first rank can
+    // make a double move too.
+    Horde,
+}
+impl Variant {
+    pub fn get_name(&self) -> &str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::Capablanca => "Capablanca",
+            Variant::Grand => "Grand",
+            Variant::Horde => "Horde",
+        }
+    }
+
+    pub fn get_map_size(&self) -> TilemapSize {
+        match self {
+            Variant::Standard | Variant::Horde => TilemapSize { x: 8, y: 8 },
+            Variant::Capablanca => TilemapSize { x: 10, y: 8 },
+            Variant::Grand => TilemapSize { x: 10, y: 10 },
+        }
+    }
+
+    // Returns the variant's usual starting piece placement, in FEN notation.
+    pub fn get_starting_placement(&self) -> &'static str {
+        match self {
+            Variant::Standard => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+            Variant::Capablanca => "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR",
+            Variant::Grand => "r8r/1nbqkcabn1/pppppppppp/10/10/10/10/PPPPPPPPPP/1NBQKCABN1/R8R",
+            Variant::Horde => "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP",
+        }
+    }
+
+    // Returns whether a pawn of the given color on the given row is allowed to make a double move.
+    pub fn can_pawn_double_move(&self, color: Color, row: u32) -> bool {
+        let last_row = self.get_map_size().y - 1;
+        match (self, color) {
+            (Variant::Standard | Variant::Capablanca, Color::White) => row == 1,
+            (Variant::Standard | Variant::Capablanca | Variant::Horde, Color::Black) => {
+                row == last_row - 1
+            }
+            (Variant::Grand, Color::White) => row == 2,
+            (Variant::Grand, Color::Black) => row == last_row - 2,
+            (Variant::Horde, Color::White) => row <= 1,
+        }
+    }
+
+    // Returns whether the given color has a king that must not be left under attack. A side
+    // without a king can't be checked or checkmated, and is instead defeated by losing all of its
+    // pieces.
+    pub fn has_king(&self, color: Color) -> bool {
+        !(*self == Variant::Horde && color == Color::White)
+    }
+}
+// Every variant, in the order they're offered on the new game screen.
+pub const VARIANTS: [Variant; 4] = [
+    Variant::Standard,
+    Variant::Capablanca,
+    Variant::Grand,
+    Variant::Horde,
+];
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "standard" => Ok(Variant::Standard),
+            "capablanca" => Ok(Variant::Capablanca),
+            "grand" => Ok(Variant::Grand),
+            "horde" => Ok(Variant::Horde),
+            _ => Err(format!("Unknown variant \"{s}\"")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Handicap {
+    // Removes the f-pawn, and also gives the first move to the other side.
+    PawnAndMove,
+    // Removes the queenside knight.
+    Knight,
+    // Removes the queenside rook.
+    Rook,
+    Queen,
+}
+impl Handicap {
+    pub fn get_name(&self) -> &str {
+        match self {
+            Handicap::PawnAndMove => "Pawn and move",
+            Handicap::Knight => "Knight",
+            Handicap::Rook => "Rook",
+            Handicap::Queen => "Queen",
+        }
+    }
+
+    // Removes the handicapped piece from the giver's starting pieces. Returns an error if the giver
+    // doesn't have that piece to give.
+    pub fn remove_from(
+        &self,
+        starting_pieces: &mut Vec<(TilePos, GamePiece)>,
+        giver: Color,
+        map_size: &TilemapSize,
+    ) -> Result<(), String> {
+        let piece = match self {
+            Handicap::PawnAndMove => Piece::Pawn,
+            Handicap::Knight => Piece::Knight,
+            Handicap::Rook => Piece::Rook,
+            Handicap::Queen => Piece::Queen,
+        };
+        // The f-file is the column to the kingside of the king's starting column.
+        let f_file = map_size.x / 2 + 1;
+        // Distance from the giver's first rank, so the piece closest to its starting rank is
+        // preferred.
+        let get_distance_from_first_rank = |tile_pos: &TilePos| match giver {
+            Color::White => tile_pos.y,
+            Color::Black => map_size.y - 1 - tile_pos.y,
+        };
+
+        let index = starting_pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, (tile_pos, game_piece))| {
+                *game_piece
+                    == GamePiece {
+                        piece,
+                        color: giver,
+                    }
+                    && (*self != Handicap::PawnAndMove || tile_pos.x == f_file)
+            })
+            // Take the piece closest to the queenside, then closest to the giver's first rank.
+            .min_by_key(|(_, (tile_pos, _))| (tile_pos.x, get_distance_from_first_rank(tile_pos)))
+            .map(|(index, _)| index)
+            .ok_or_else(|| {
+                format!(
+                    "{} has no piece to give {} odds with",
+                    giver.get_name(),
+                    self.get_name().to_lowercase()
+                )
+            })?;
+        starting_pieces.remove(index);
+        Ok(())
+    }
+}
+impl FromStr for Handicap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pawn" | "pawn-and-move" => Ok(Handicap::PawnAndMove),
+            "knight" => Ok(Handicap::Knight),
+            "rook" => Ok(Handicap::Rook),
+            "queen" => Ok(Handicap::Queen),
+            _ => Err(format!("Unknown odds \"{s}\"")),
+        }
+    }
+}
+
+// The handicap one side gives the other at the start of the game, along with the color giving it,
+// or None for an even game.
+#[derive(Resource, Default)]
+pub struct Odds(pub Option<(Handicap, Color)>);
+
+// PGN-style tag pairs describing the game, such as the variant and starting position, in the order
+// they should be written.
+#[derive(Resource, Default)]
+pub struct GameTags(pub Vec<(String, String)>);
+impl GameTags {
+    pub fn remove(&mut self, name: &str) {
+        self.0.retain(|(tag, _)| tag != name);
+    }
+
+    pub fn set(&mut self, name: &str, value: String) {
+        if let Some((_, existing_value)) = self.0.iter_mut().find(|(tag, _)| tag == name) {
+            *existing_value = value;
+        } else {
+            self.0.push((name.to_string(), value));
+        }
+    }
+
+    // Writes the tags in PGN format, one per line, such as [Variant "Horde"].
+    pub fn to_pgn(&self) -> String {
+        self.0
+            .iter()
+            .map(|(name, value)| format!("[{name} \"{}\"]\n", value.replace('"', "\\\"")))
+            .collect()
+    }
+}
+
+// The piece placement that the game starts from, in FEN notation. This is normally the variant's
+// usual starting placement, but can be replaced to start from a custom setup.
+#[derive(Resource)]
+pub struct StartingPlacement(pub String);
+impl FromWorld for StartingPlacement {
+    fn from_world(world: &mut World) -> Self {
+        let variant = world.get_resource::<Variant>().copied().unwrap_or_default();
+        StartingPlacement(variant.get_starting_placement().to_string())
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct PositionEditor {
+    pub is_active: bool,
+    // The game piece placed on tiles when they're clicked, or None to remove pieces instead.
+    pub palette_piece: Option<GamePiece>,
+}
 
 #[derive(Resource)]
 pub struct ColorToMove(pub Color);
@@ -24,6 +449,727 @@ pub struct SelectedPiece(pub Option<GamePiece>);
 #[derive(Resource, Default)]
 pub struct SelectedPieceOriginalPosition(pub Option<TilePos>);
 
+// Whether the legal moves of the hovered or carried piece are marked on the board. Players can turn
+// these hints off for serious play.
+#[derive(Resource, PartialEq)]
+pub struct LegalMoveHints {
+    pub is_enabled: bool,
+}
+
+impl Default for LegalMoveHints {
+    fn default() -> Self {
+        LegalMoveHints { is_enabled: true }
+    }
+}
+
+// Whether every tile shows its name, in addition to the file and rank labels along the board's
+// edges.
+#[derive(Resource, PartialEq, Default)]
+pub struct CoordinateLabels {
+    pub show_in_tiles: bool,
+}
+
+// How long pieces take to slide to their destination after a move, in seconds. Moves aren't
+// animated if this is zero.
+#[derive(Resource, PartialEq)]
+pub struct MoveAnimation {
+    pub duration: f32,
+}
+
+impl Default for MoveAnimation {
+    fn default() -> Self {
+        MoveAnimation { duration: 0.2 }
+    }
+}
+
+// How pieces are moved with the mouse.
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum InputMode {
+    // Click a piece to pick it up, then click where it should go.
+    #[default]
+    Click,
+    // Pieces can also be dragged, being put down where the button is released.
+    DragAndDrop,
+}
+
+impl InputMode {
+    pub fn get_name(&self) -> &str {
+        match self {
+            InputMode::Click => "Click",
+            InputMode::DragAndDrop => "Drag and drop",
+        }
+    }
+}
+
+// How much the board is zoomed in, where 1 is the board's usual size.
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct BoardScale(pub f32);
+
+impl Default for BoardScale {
+    fn default() -> Self {
+        BoardScale(1.0)
+    }
+}
+
+// Where each kind of tile is in a board theme's tile atlas, given as the index of its dark version.
+// The light version of each tile comes straight after the dark one, so the plain tiles can be
+// picked by the checkerboard pattern.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TileLayout {
+    pub plain: u32,
+    pub mouseover: u32,
+    pub quiet_move: u32,
+    pub last_move: u32,
+    pub check: u32,
+    pub capture: u32,
+    pub premove: u32,
+}
+
+impl Default for TileLayout {
+    fn default() -> Self {
+        TileLayout {
+            plain: 0,
+            mouseover: 2,
+            quiet_move: 4,
+            last_move: 6,
+            check: 8,
+            capture: 10,
+            premove: 12,
+        }
+    }
+}
+
+// How the board's tiles are drawn, as described by a manifest in assets/themes.
+#[derive(Resource, Deserialize, Clone, PartialEq, Debug)]
+pub struct BoardTheme {
+    // The manifest's file name without its extension, which is how the settings refer to it.
+    #[serde(skip)]
+    pub id: String,
+    pub name: String,
+    // The tile atlas, relative to the assets folder.
+    pub tiles: String,
+    #[serde(default)]
+    pub layout: TileLayout,
+}
+
+impl Default for BoardTheme {
+    fn default() -> Self {
+        BoardTheme {
+            id: "classic".to_string(),
+            name: "Classic".to_string(),
+            tiles: "tiles.png".to_string(),
+            layout: TileLayout::default(),
+        }
+    }
+}
+
+// The images the pieces are drawn with. Besides the classic set, each folder in assets/pieces with
+// a piece_set.ron manifest is a piece set.
+#[derive(Resource, Deserialize, Clone, PartialEq, Debug)]
+pub struct PieceSet {
+    // The folder's name, which is how the settings refer to it.
+    #[serde(skip)]
+    pub id: String,
+    pub name: String,
+    // The image of each piece by its FEN letter, which is uppercase for white pieces, relative to
+    // the assets folder. Pieces without an image are drawn with the classic set's.
+    #[serde(default)]
+    pub images: HashMap<char, String>,
+}
+
+impl PieceSet {
+    pub fn get_asset_path(&self, game_piece: &GamePiece) -> String {
+        self.images
+            .get(&game_piece.get_letter())
+            .map_or(game_piece.get_asset_path(), String::as_str)
+            .to_string()
+    }
+}
+
+impl Default for PieceSet {
+    fn default() -> Self {
+        PieceSet {
+            id: "classic".to_string(),
+            name: "Classic".to_string(),
+            images: HashMap::new(),
+        }
+    }
+}
+
+// The board themes and piece sets found in the assets folder, to choose between on the settings
+// screen. The folder is looked through again whenever the settings screen is opened, so sets
+// dropped in while the game runs can be picked straight away.
+#[derive(Resource, Default)]
+pub struct ThemeCatalog {
+    pub board_themes: Vec<BoardTheme>,
+    pub piece_sets: Vec<PieceSet>,
+}
+
+// The player's choices that are kept between runs in the settings file. Each is also kept in the
+// resource that uses it, which can be changed while playing (such as turning hints off with H), so
+// the two are kept in step by the settings systems. Any setting missing from the file is left at
+// its default.
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct UserSettings {
+    pub board_scale: f32,
+    pub volume: f32,
+    #[serde(rename = "muted")]
+    pub is_muted: bool,
+    pub input_mode: InputMode,
+    #[serde(rename = "legal_move_hints")]
+    pub show_legal_move_hints: bool,
+    #[serde(rename = "tile_names")]
+    pub show_tile_names: bool,
+    pub animation_duration: f32,
+    // The time control chosen on the new game screen to begin with.
+    pub default_time_control: Option<TimeControl>,
+    // The ids of the board theme and piece set.
+    pub board_theme: String,
+    pub piece_set: String,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        UserSettings {
+            board_scale: BoardScale::default().0,
+            volume: SoundSettings::default().volume,
+            is_muted: SoundSettings::default().is_muted,
+            input_mode: InputMode::default(),
+            show_legal_move_hints: LegalMoveHints::default().is_enabled,
+            show_tile_names: CoordinateLabels::default().show_in_tiles,
+            animation_duration: MoveAnimation::default().duration,
+            default_time_control: NewGameSettings::default().time_control,
+            board_theme: BoardTheme::default().id,
+            piece_set: PieceSet::default().id,
+        }
+    }
+}
+
+// Where the settings are kept, or None to neither read nor write them. The file is checked for
+// changes while the game runs, so editing it by hand takes effect straight away.
+#[derive(Resource)]
+pub struct SettingsFile {
+    pub path: Option<PathBuf>,
+    // When the file was last read or written, to tell when it's been changed by something else.
+    pub modified: Option<SystemTime>,
+    pub check_timer: Timer,
+}
+
+impl SettingsFile {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        SettingsFile {
+            path,
+            modified: None,
+            check_timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+}
+
+impl Default for SettingsFile {
+    fn default() -> Self {
+        SettingsFile::new(get_settings_path())
+    }
+}
+
+// The color played at this computer when the other side is played somewhere else (such as by an
+// engine or over the network), or None when both sides are played here. While the other side is
+// thinking, the local player can queue premoves.
+#[derive(Resource, Default)]
+pub struct LocalPlayer(pub Option<Color>);
+
+// A move queued during the opponent's turn.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Premove {
+    pub from: TilePos,
+    pub to: TilePos,
+    pub is_castling: bool,
+}
+
+// A game played against someone on another computer, either hosted here, joined or watched. None
+// of them happen until set up, such as with the --host, --join and --spectate flags.
+#[derive(Resource, Default)]
+pub struct NetworkGame {
+    // Listens for the other player and spectators to join, when hosting. They can join once a game
+    // against a remote opponent is started here.
+    pub listener: Option<TcpListener>,
+    // The connection to the other player, or to the host when spectating, once there is one.
+    pub peer: Option<NetworkPeer>,
+    // Connections to a hosted game that haven't said yet whether they're playing or watching.
+    pub arrivals: Vec<NetworkPeer>,
+    // The games watching the one hosted here.
+    pub spectators: Vec<NetworkPeer>,
+    // Whether this game only watches the hosted one, so neither color can be moved here.
+    pub is_spectating: bool,
+    // How the host said the game ended, once it has, when playing or watching a hosted game.
+    pub game_ended_by_host: Option<GameEnded>,
+    // Whether the game is paused on every side, so a pause or resume is only sent by the side that
+    // made it.
+    pub is_paused: bool,
+}
+
+impl NetworkGame {
+    // Hosts games on the given port of every network this computer is on.
+    pub fn host(port: u16) -> Result<Self, String> {
+        NetworkGame::host_on(&format!("0.0.0.0:{port}"))
+    }
+
+    // Hosts games on the given address, such as "127.0.0.1:0" for any free port that's only
+    // reachable from this computer.
+    pub fn host_on(address: &str) -> Result<Self, String> {
+        Ok(NetworkGame {
+            listener: Some(listen(address)?),
+            ..default()
+        })
+    }
+
+    // Joins the game hosted at the given address, such as "192.168.1.20" or "192.168.1.20:7878".
+    pub fn join(address: &str) -> Result<Self, String> {
+        NetworkGame::connect(address, PeerRole::Player)
+    }
+
+    // Watches the game hosted at the given address, from the moves already played onwards.
+    pub fn spectate(address: &str) -> Result<Self, String> {
+        NetworkGame::connect(address, PeerRole::Spectator)
+    }
+
+    fn connect(address: &str, role: PeerRole) -> Result<Self, String> {
+        let mut peer = NetworkPeer::connect(address)?;
+        peer.send(&NetworkMessage::Hello {
+            version: PROTOCOL_VERSION,
+            role,
+        })?;
+        Ok(NetworkGame {
+            peer: Some(peer),
+            is_spectating: role == PeerRole::Spectator,
+            ..default()
+        })
+    }
+
+    pub fn is_hosting(&self) -> bool {
+        self.listener.is_some()
+    }
+
+    // Whether the host decides how the game ends, as it does for games joined or watched over the
+    // network. Each game runs its own clocks, so otherwise the host could see the other player run
+    // out of time while their move is still on its way, and the games would end differently.
+    pub fn leaves_game_end_to_host(&self) -> bool {
+        self.peer.is_some() && !self.is_hosting()
+    }
+}
+
+// The premoves waiting to be made, in order. Each one is made as soon as it's the local player's
+// turn if it's legal then, otherwise every premove is cancelled.
+#[derive(Resource, Default)]
+pub struct Premoves(pub Vec<Premove>);
+
+impl Premoves {
+    // Returns where the pieces will be and which rights to castle will be left once every premove
+    // is made, assuming the opponent doesn't get in the way.
+    pub fn get_position_after<F: Fn(&TilePos) -> Option<GamePiece>>(
+        &self,
+        right_to_castle: &RightToCastle,
+        map_size: &TilemapSize,
+        get_game_piece_at_position: F,
+    ) -> (HashMap<TilePos, GamePiece>, RightToCastle) {
+        let mut pieces: HashMap<TilePos, GamePiece> = (0..map_size.x)
+            .flat_map(|x| (0..map_size.y).map(move |y| TilePos { x, y }))
+            .filter_map(|tile_pos| {
+                get_game_piece_at_position(&tile_pos).map(|game_piece| (tile_pos, game_piece))
+            })
+            .collect();
+        let mut right_to_castle = *right_to_castle;
+        for premove in &self.0 {
+            let Some(game_piece) = pieces.remove(&premove.from) else {
+                continue;
+            };
+            pieces.insert(premove.to, game_piece);
+            // The rook jumps over the king, from the corner it moved towards.
+            if premove.is_castling {
+                let (rook_from_x, rook_to_x) = if premove.to.x < premove.from.x {
+                    (0, premove.to.x + 1)
+                } else {
+                    (map_size.x - 1, premove.to.x - 1)
+                };
+                let rook_from = TilePos {
+                    x: rook_from_x,
+                    y: premove.to.y,
+                };
+                if let Some(rook) = pieces.remove(&rook_from) {
+                    pieces.insert(
+                        TilePos {
+                            x: rook_to_x,
+                            y: premove.to.y,
+                        },
+                        rook,
+                    );
+                }
+            }
+            right_to_castle.remove_for_tile(&premove.from, map_size);
+            right_to_castle.remove_for_tile(&premove.to, map_size);
+        }
+        (pieces, right_to_castle)
+    }
+}
+
+// How loud sound effects are, from 0 (silent) to 1.
+#[derive(Resource, PartialEq)]
+pub struct SoundSettings {
+    pub volume: f32,
+    pub is_muted: bool,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        SoundSettings {
+            volume: 0.5,
+            is_muted: false,
+        }
+    }
+}
+
+// The tone played for each sound effect.
+#[derive(Resource, Default)]
+pub struct SoundEffects(pub HashMap<SoundEffect, Handle<Pitch>>);
+
+// The origin and destination tiles of the most recent move, if a move has been made.
+#[derive(Resource, Default)]
+pub struct LastMove(pub Option<(TilePos, TilePos)>);
+
+// Everything needed to return to a position.
+#[derive(Clone)]
+pub struct PositionSnapshot {
+    pub pieces: Vec<(TilePos, GamePiece)>,
+    pub color_to_move: Color,
+    pub right_to_castle: RightToCastle,
+}
+
+impl PositionSnapshot {
+    // Checks that the position can be played from in the variant, returning a description of the
+    // first problem found if it can't.
+    pub fn validate(&self, variant: &Variant) -> Result<(), String> {
+        let pieces = &self.pieces;
+        let color_to_move = self.color_to_move;
+        let right_to_castle = &self.right_to_castle;
+        let map_size = &variant.get_map_size();
+        let get_game_piece_at_position = |tile_pos: &TilePos| {
+            pieces
+                .iter()
+                .find(|(other_tile_pos, _)| other_tile_pos == tile_pos)
+                .map(|(_, game_piece)| *game_piece)
+        };
+
+        for color in [Color::White, Color::Black] {
+            let king = GamePiece {
+                piece: Piece::King,
+                color,
+            };
+            let king_count = pieces
+                .iter()
+                .filter(|(_, game_piece)| *game_piece == king)
+                .count();
+            if variant.has_king(color) && king_count != 1 {
+                return Err(format!("{} must have exactly one king", color.get_name()));
+            }
+            if !variant.has_king(color) && king_count != 0 {
+                return Err(format!(
+                    "{} can't have a king in {}",
+                    color.get_name(),
+                    variant.get_name()
+                ));
+            }
+
+            // Pawns can never be on their last rank, and can only be on their first rank if the
+            // variant lets them double move from there.
+            let (first_row, last_row) = match color {
+                Color::White => (0, map_size.y - 1),
+                Color::Black => (map_size.y - 1, 0),
+            };
+            let pawn = GamePiece {
+                piece: Piece::Pawn,
+                color,
+            };
+            if pieces.iter().any(|(tile_pos, game_piece)| {
+                *game_piece == pawn
+                    && (tile_pos.y == last_row
+                        || (tile_pos.y == first_row
+                            && !variant.can_pawn_double_move(color, first_row)))
+            }) {
+                return Err(format!("{} has a pawn on a back rank", color.get_name()));
+            }
+        }
+
+        // The side that just "moved" can't have left its own king in check.
+        let color_not_to_move = color_to_move.opposite();
+        let king_not_to_move = GamePiece {
+            piece: Piece::King,
+            color: color_not_to_move,
+        };
+        if let Some((king_tile, _)) = pieces
+            .iter()
+            .find(|(_, game_piece)| *game_piece == king_not_to_move)
+        {
+            if is_king_threatened(
+                king_tile,
+                &color_not_to_move,
+                map_size,
+                get_game_piece_at_position,
+            ) {
+                return Err(format!(
+                    "{} can't be in check while it's {}'s move",
+                    color_not_to_move.get_name(),
+                    color_to_move.get_name()
+                ));
+            }
+        }
+
+        // Each right to castle needs the king and that side's rook on their starting tiles.
+        let possible_right_to_castle = RightToCastle::from_starting_pieces(pieces, map_size);
+        if (right_to_castle.white_kingside && !possible_right_to_castle.white_kingside)
+            || (right_to_castle.white_queenside && !possible_right_to_castle.white_queenside)
+            || (right_to_castle.black_kingside && !possible_right_to_castle.black_kingside)
+            || (right_to_castle.black_queenside && !possible_right_to_castle.black_queenside)
+        {
+            return Err("Castling needs the king and rook on their starting tiles".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+// The colors arrows and marked squares can be drawn in, which are chosen by holding a modifier key.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AnnotationColor {
+    Green,
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl AnnotationColor {
+    // Returns the color drawn while the modifier keys being held are pressed: none for green, shift
+    // for red, control for blue and alt for yellow.
+    pub fn from_modifiers(keyboard: &ButtonInput<KeyCode>) -> Self {
+        if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            AnnotationColor::Red
+        } else if keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+            AnnotationColor::Blue
+        } else if keyboard.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+            AnnotationColor::Yellow
+        } else {
+            AnnotationColor::Green
+        }
+    }
+
+    // Returns the letter standing for the color in PGN comments.
+    pub fn get_letter(&self) -> char {
+        match self {
+            AnnotationColor::Green => 'G',
+            AnnotationColor::Red => 'R',
+            AnnotationColor::Blue => 'B',
+            AnnotationColor::Yellow => 'Y',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Self> {
+        match letter {
+            'G' => Some(AnnotationColor::Green),
+            'R' => Some(AnnotationColor::Red),
+            'B' => Some(AnnotationColor::Blue),
+            'Y' => Some(AnnotationColor::Yellow),
+            _ => None,
+        }
+    }
+
+    pub fn get_color(&self) -> Srgba {
+        match self {
+            AnnotationColor::Green => Srgba::new(0.08, 0.55, 0.15, 0.8),
+            AnnotationColor::Red => Srgba::new(0.75, 0.1, 0.1, 0.8),
+            AnnotationColor::Blue => Srgba::new(0.1, 0.35, 0.8, 0.8),
+            AnnotationColor::Yellow => Srgba::new(0.9, 0.7, 0.05, 0.8),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Arrow {
+    pub from: TilePos,
+    pub to: TilePos,
+    pub color: AnnotationColor,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SquareMark {
+    pub tile_pos: TilePos,
+    pub color: AnnotationColor,
+}
+
+// The arrows and marked squares drawn on a position, such as by a coach explaining it.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct BoardAnnotations {
+    pub arrows: Vec<Arrow>,
+    pub square_marks: Vec<SquareMark>,
+}
+
+impl BoardAnnotations {
+    pub fn is_empty(&self) -> bool {
+        self.arrows.is_empty() && self.square_marks.is_empty()
+    }
+
+    // Draws the arrow, or erases it if it's already drawn in the same color. An arrow between the
+    // same tiles in another color is replaced.
+    pub fn toggle_arrow(&mut self, arrow: Arrow) {
+        let existing_arrow = self
+            .arrows
+            .iter()
+            .position(|other| other.from == arrow.from && other.to == arrow.to);
+        if let Some(index) = existing_arrow {
+            if self.arrows.remove(index).color == arrow.color {
+                return;
+            }
+        }
+        self.arrows.push(arrow);
+    }
+
+    // Marks the square, or unmarks it if it's already marked in the same color. A mark on the same
+    // square in another color is replaced.
+    pub fn toggle_square_mark(&mut self, square_mark: SquareMark) {
+        let existing_square_mark = self
+            .square_marks
+            .iter()
+            .position(|other| other.tile_pos == square_mark.tile_pos);
+        if let Some(index) = existing_square_mark {
+            if self.square_marks.remove(index).color == square_mark.color {
+                return;
+            }
+        }
+        self.square_marks.push(square_mark);
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordedMove {
+    pub move_made: MoveMade,
+    pub san: String,
+    // The position after the move.
+    pub position: PositionSnapshot,
+    // What was drawn on the position after the move.
+    pub annotations: BoardAnnotations,
+    // The clocks just after the move, which are put back when later moves are taken back. Moves
+    // loaded from saves written before the clocks were kept with each move don't have them.
+    pub clocks: Option<Clocks>,
+}
+
+// Every move of the game so far, along with the position the game started from.
+#[derive(Resource, Default)]
+pub struct MoveHistory {
+    // This is set once the pieces are set up.
+    pub starting_position: Option<PositionSnapshot>,
+    // What was drawn on the starting position.
+    pub starting_annotations: BoardAnnotations,
+    pub moves: Vec<RecordedMove>,
+    // The number of plies (moves by either side) into the game of the position shown read-only on
+    // the board, or None while the live position is shown.
+    pub viewed_ply: Option<usize>,
+}
+impl MoveHistory {
+    // Starts a new history from the position, forgetting every move.
+    pub fn reset(&mut self, starting_position: PositionSnapshot) {
+        self.starting_position = Some(starting_position);
+        self.starting_annotations = BoardAnnotations::default();
+        self.moves.clear();
+        self.viewed_ply = None;
+    }
+
+    // Returns the position after the number of plies.
+    pub fn get_position(&self, ply: usize) -> &PositionSnapshot {
+        if ply == 0 {
+            self.starting_position
+                .as_ref()
+                .expect("Starting position should be recorded once the pieces are set up")
+        } else {
+            &self.moves[ply - 1].position
+        }
+    }
+
+    // Returns what was drawn on the position after the number of plies. Each position has its own
+    // annotations, so they're cleared from the board by the next move.
+    pub fn get_annotations(&self, ply: usize) -> &BoardAnnotations {
+        if ply == 0 {
+            &self.starting_annotations
+        } else {
+            &self.moves[ply - 1].annotations
+        }
+    }
+
+    pub fn get_annotations_mut(&mut self, ply: usize) -> &mut BoardAnnotations {
+        if ply == 0 {
+            &mut self.starting_annotations
+        } else {
+            &mut self.moves[ply - 1].annotations
+        }
+    }
+
+    // Writes the moves in PGN's movetext format, such as "1. e4 e5 2. Nf3", with any arrows and
+    // marked squares as a comment after the move leading to the position they were drawn on.
+    pub fn write_movetext(&self) -> String {
+        let mut tokens = Vec::new();
+        // Black's moves are only numbered (as "1...") at the start and after a comment.
+        let mut needs_move_number = true;
+        if !self.starting_annotations.is_empty() {
+            tokens.push(format!(
+                "{{{}}}",
+                write_annotations(&self.starting_annotations)
+            ));
+        }
+        // Count plies from white's first move, so a game starting with black to move begins with
+        // "1...".
+        let first_ply = match self.starting_position.as_ref() {
+            Some(position) if position.color_to_move == Color::Black => 1,
+            _ => 0,
+        };
+        for (index, recorded_move) in self.moves.iter().enumerate() {
+            let ply = first_ply + index;
+            let move_number = ply / 2 + 1;
+            if ply % 2 == 0 {
+                tokens.push(format!("{move_number}."));
+            } else if needs_move_number {
+                tokens.push(format!("{move_number}..."));
+            }
+            needs_move_number = false;
+            tokens.push(recorded_move.san.clone());
+            if !recorded_move.annotations.is_empty() {
+                tokens.push(format!(
+                    "{{{}}}",
+                    write_annotations(&recorded_move.annotations)
+                ));
+                needs_move_number = true;
+            }
+        }
+        tokens.join(" ")
+    }
+
+    // Returns the pieces captured by the color in the first number of plies, most valuable first.
+    pub fn get_captured_pieces(&self, color: Color, ply: usize) -> Vec<GamePiece> {
+        let mut captured_pieces: Vec<GamePiece> = self.moves[..ply]
+            .iter()
+            .filter(|recorded_move| recorded_move.move_made.game_piece.color == color)
+            .filter_map(|recorded_move| recorded_move.move_made.captured)
+            .collect();
+        captured_pieces.sort_by_key(|game_piece| std::cmp::Reverse(game_piece.piece.get_value()));
+        captured_pieces
+    }
+
+    // Returns the number of the ply whose position is shown on the board.
+    pub fn get_shown_ply(&self) -> usize {
+        self.viewed_ply.unwrap_or(self.moves.len())
+    }
+}
+
+// The tile of the king of the side to move, if that king is in check.
+#[derive(Resource, Default)]
+pub struct CheckedKingTile(pub Option<TilePos>);
+
 #[derive(Resource)]
 pub struct MustRecalculateLegalMoves(pub bool);
 impl Default for MustRecalculateLegalMoves {
@@ -32,6 +1178,30 @@ impl Default for MustRecalculateLegalMoves {
     }
 }
 
+#[derive(Resource, Default)]
+pub struct BoardOrientation {
+    // Whether black's side of the board is displayed at the bottom of the screen instead of
+    // white's.
+    pub is_flipped: bool,
+    // Whether the board is flipped to face whichever side is to move, for playing on one computer.
+    pub auto_flip: bool,
+}
+impl BoardOrientation {
+    // Converts between a tile's position on the board and the position it's displayed at, which is
+    // mirrored in both directions when the board is flipped. Converting a position twice gives back
+    // the original position.
+    pub fn get_displayed_tile_pos(&self, tile_pos: &TilePos, map_size: &TilemapSize) -> TilePos {
+        if self.is_flipped {
+            TilePos {
+                x: map_size.x - 1 - tile_pos.x,
+                y: map_size.y - 1 - tile_pos.y,
+            }
+        } else {
+            *tile_pos
+        }
+    }
+}
+
 #[derive(Resource)]
 pub struct CursorPos(pub Vec2);
 impl Default for CursorPos {
@@ -42,7 +1212,7 @@ impl Default for CursorPos {
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Clone, Copy, PartialEq, Eq)]
 pub struct RightToCastle {
     pub white_kingside: bool,
     pub white_queenside: bool,
@@ -59,3 +1229,57 @@ impl Default for RightToCastle {
         }
     }
 }
+impl RightToCastle {
+    // Gives a right to castle for each rook that starts in a corner of its side's first rank, as
+    // long as its king starts in the middle column of the same rank (the e-file on an 8-wide board,
+    // or the f-file on a 10-wide board).
+    pub fn from_starting_pieces(
+        starting_pieces: &[(TilePos, GamePiece)],
+        map_size: &TilemapSize,
+    ) -> Self {
+        let has_piece_at = |x: u32, y: u32, piece: Piece, color: Color| {
+            starting_pieces.contains(&(TilePos { x, y }, GamePiece { piece, color }))
+        };
+        let can_castle = |x: u32, color: Color| {
+            let y = if color == Color::White {
+                0
+            } else {
+                map_size.y - 1
+            };
+            has_piece_at(map_size.x / 2, y, Piece::King, color)
+                && has_piece_at(x, y, Piece::Rook, color)
+        };
+
+        RightToCastle {
+            white_kingside: can_castle(map_size.x - 1, Color::White),
+            white_queenside: can_castle(0, Color::White),
+            black_kingside: can_castle(map_size.x - 1, Color::Black),
+            black_queenside: can_castle(0, Color::Black),
+        }
+    }
+
+    // Removes any right to castle that depends on a piece being on the given tile, for when a piece
+    // moves off that tile or is captured on it. Kings start in the middle column (the e-file on an
+    // 8-wide board, or the f-file on a 10-wide board), and rooks start in the corners.
+    pub fn remove_for_tile(&mut self, tile_pos: &TilePos, map_size: &TilemapSize) {
+        let last_column = map_size.x - 1;
+        let king_column = map_size.x / 2;
+        let last_row = map_size.y - 1;
+
+        if tile_pos.y == 0 {
+            if tile_pos.x == 0 || tile_pos.x == king_column {
+                self.white_queenside = false;
+            }
+            if tile_pos.x == last_column || tile_pos.x == king_column {
+                self.white_kingside = false;
+            }
+        } else if tile_pos.y == last_row {
+            if tile_pos.x == 0 || tile_pos.x == king_column {
+                self.black_queenside = false;
+            }
+            if tile_pos.x == last_column || tile_pos.x == king_column {
+                self.black_kingside = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use crate::components::{Color, Piece};
+use crate::events::{MoveMade, SpecialMove};
+use crate::notation::{
+    parse_annotations, parse_coordinate_move, parse_fen, write_annotations, write_coordinate_move,
+    write_fen,
+};
+use crate::resources::{
+    BoardAnnotations, Clocks, NewGameSettings, Opponent, PositionSnapshot, RecordedMove, Variant,
+};
+use bevy_ecs_tilemap::prelude::*;
+
+// The version written at the top of save files. Lines this version doesn't know are skipped, so
+// adding a line doesn't need a new version. The version only goes up when an existing line changes
+// meaning, and older saves are then read with the meaning they were written with.
+pub const SAVE_FORMAT_VERSION: u32 = 1;
+
+const SAVE_FORMAT_HEADER: &str = "bevy-chess save";
+
+// Everything needed to continue a game where it was left off.
+pub struct SavedGame {
+    pub variant: Variant,
+    pub game_tags: Vec<(String, String)>,
+    pub starting_position: PositionSnapshot,
+    pub starting_annotations: BoardAnnotations,
+    pub moves: Vec<RecordedMove>,
+    pub clocks: Clocks,
+    pub new_game_settings: NewGameSettings,
+    pub local_player: Option<Color>,
+    pub is_board_flipped: bool,
+    pub auto_flip: bool,
+}
+
+impl SavedGame {
+    // Returns the position the game was left in.
+    pub fn get_current_position(&self) -> &PositionSnapshot {
+        self.moves
+            .last()
+            .map_or(&self.starting_position, |recorded_move| {
+                &recorded_move.position
+            })
+    }
+}
+
+// Writes a position in FEN notation, numbering the moves by how many plies into the game it is.
+// Moves that don't capture or move a pawn aren't counted, so the halfmove clock is always 0.
+fn write_position(position: &PositionSnapshot, map_size: &TilemapSize, ply: usize) -> String {
+    write_fen(
+        &position.pieces,
+        map_size,
+        position.color_to_move,
+        &position.right_to_castle,
+        0,
+        ply as u32 / 2 + 1,
+    )
+}
+
+fn write_color(color: Option<Color>, none: &str) -> String {
+    color.map_or(none.to_string(), |color| color.get_name().to_lowercase())
+}
+
+fn parse_color(value: &str, none: &str) -> Result<Option<Color>, String> {
+    if value == none {
+        Ok(None)
+    } else {
+        value.parse().map(Some)
+    }
+}
+
+// Writes the game as lines of a name followed by its value, such as "variant Standard", starting
+// with the format's version. Each move is written in coordinate notation (such as "e2e4") along
+// with its SAN and the position after it, and in timed games the clocks after it.
+pub fn write_saved_game(saved_game: &SavedGame) -> String {
+    let map_size = saved_game.variant.get_map_size();
+    let mut lines = vec![
+        format!("{SAVE_FORMAT_HEADER} {SAVE_FORMAT_VERSION}"),
+        format!("variant {}", saved_game.variant.get_name()),
+    ];
+    for (name, value) in &saved_game.game_tags {
+        lines.push(format!("tag {name} {value}"));
+    }
+
+    lines.push(format!(
+        "start {}",
+        write_position(&saved_game.starting_position, &map_size, 0)
+    ));
+    if !saved_game.starting_annotations.is_empty() {
+        lines.push(format!(
+            "annotations 0 {}",
+            write_annotations(&saved_game.starting_annotations)
+        ));
+    }
+    for (index, recorded_move) in saved_game.moves.iter().enumerate() {
+        let ply = index + 1;
+        let move_made = &recorded_move.move_made;
+        lines.push(format!(
+            "move {} {} {}",
+            write_coordinate_move(&move_made.from, &move_made.to),
+            recorded_move.san,
+            write_position(&recorded_move.position, &map_size, ply)
+        ));
+        if !recorded_move.annotations.is_empty() {
+            lines.push(format!(
+                "annotations {ply} {}",
+                write_annotations(&recorded_move.annotations)
+            ));
+        }
+        if let Some(clocks) = recorded_move
+            .clocks
+            .filter(|clocks| clocks.time_control.is_some())
+        {
+            lines.push(format!(
+                "move-clocks {ply} {} {}",
+                clocks.white_seconds, clocks.black_seconds
+            ));
+        }
+    }
+
+    let clocks = &saved_game.clocks;
+    lines.push(match clocks.time_control {
+        Some(time_control) => format!(
+            "clocks {} {} {}",
+            time_control.get_name(),
+            clocks.white_seconds,
+            clocks.black_seconds
+        ),
+        None => "clocks untimed".to_string(),
+    });
+    let new_game_settings = &saved_game.new_game_settings;
+    lines.push(format!(
+        "opponent {}",
+        match new_game_settings.opponent {
+            Opponent::Local => "local",
+            Opponent::Remote => "remote",
+        }
+    ));
+    lines.push(format!(
+        "play-as {}",
+        write_color(new_game_settings.color, "random")
+    ));
+    lines.push(format!(
+        "local-player {}",
+        write_color(saved_game.local_player, "none")
+    ));
+    lines.push(format!("board-flipped {}", saved_game.is_board_flipped));
+    lines.push(format!("auto-flip {}", saved_game.auto_flip));
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+// Parses a game written by write_saved_game(), by this or an older version of the game. Anything
+// other than the starting position that's missing is left as it is for a new game.
+pub fn parse_saved_game(text: &str) -> Result<SavedGame, String> {
+    let mut lines = text.lines();
+    let version = lines
+        .next()
+        .and_then(|line| line.strip_prefix(SAVE_FORMAT_HEADER))
+        .and_then(|version| version.trim().parse::<u32>().ok())
+        .ok_or("Not a saved game")?;
+    if version > SAVE_FORMAT_VERSION {
+        return Err(format!(
+            "Saved by a newer version of the game (format {version}, but only up to \
+             {SAVE_FORMAT_VERSION} can be read)"
+        ));
+    }
+
+    let mut variant = Variant::default();
+    let mut game_tags = Vec::new();
+    let mut starting_position = None;
+    let mut moves: Vec<RecordedMove> = Vec::new();
+    let mut annotations = Vec::new();
+    let mut move_clocks = Vec::new();
+    let mut clocks = Clocks::default();
+    let mut new_game_settings = NewGameSettings::default();
+    let mut local_player = None;
+    let mut is_board_flipped = false;
+    let mut auto_flip = false;
+
+    for line in lines {
+        let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+        let map_size = variant.get_map_size();
+        match name {
+            "variant" => {
+                // The variant decides the board's size, so it must come before any position.
+                if starting_position.is_some() {
+                    return Err("The variant must be given before the starting position".into());
+                }
+                variant = value.parse()?;
+            }
+            "tag" => {
+                let (tag_name, tag_value) = value.split_once(' ').unwrap_or((value, ""));
+                game_tags.push((tag_name.to_string(), tag_value.to_string()));
+            }
+            "start" => starting_position = Some(parse_fen(value, &map_size)?),
+            "move" => {
+                let previous_position = moves
+                    .last()
+                    .map(|recorded_move| &recorded_move.position)
+                    .or(starting_position.as_ref())
+                    .ok_or("Moves must come after the starting position")?;
+                let mut fields = value.splitn(3, ' ');
+                let (Some(coordinates), Some(san), Some(fen)) =
+                    (fields.next(), fields.next(), fields.next())
+                else {
+                    return Err(format!("Move \"{value}\" needs coordinates, SAN and FEN"));
+                };
+                let move_made = parse_move_made(coordinates, previous_position, &map_size)?;
+                moves.push(RecordedMove {
+                    move_made,
+                    san: san.to_string(),
+                    position: parse_fen(fen, &map_size)?,
+                    annotations: BoardAnnotations::default(),
+                    clocks: None,
+                });
+            }
+            "annotations" => {
+                let (ply, comment) = value.split_once(' ').unwrap_or((value, ""));
+                let ply: usize = ply
+                    .parse()
+                    .map_err(|_| format!("Invalid ply \"{ply}\" for annotations"))?;
+                annotations.push((ply, parse_annotations(comment)));
+            }
+            "move-clocks" => {
+                let (ply, seconds) = value.split_once(' ').unwrap_or((value, ""));
+                let ply: usize = ply
+                    .parse()
+                    .map_err(|_| format!("Invalid ply \"{ply}\" for move clocks"))?;
+                move_clocks.push((ply, seconds.to_string()));
+            }
+            "clocks" => clocks = parse_clocks(value)?,
+            "opponent" => {
+                new_game_settings.opponent = match value {
+                    "local" => Opponent::Local,
+                    "remote" => Opponent::Remote,
+                    _ => return Err(format!("Unknown opponent \"{value}\"")),
+                }
+            }
+            "play-as" => new_game_settings.color = parse_color(value, "random")?,
+            "local-player" => local_player = parse_color(value, "none")?,
+            "board-flipped" => {
+                is_board_flipped = value
+                    .parse()
+                    .map_err(|_| format!("Invalid board-flipped \"{value}\""))?
+            }
+            "auto-flip" => {
+                auto_flip = value
+                    .parse()
+                    .map_err(|_| format!("Invalid auto-flip \"{value}\""))?
+            }
+            _ => {}
+        }
+    }
+
+    // Only the starting position is checked, as the positions after it were reached by legal moves.
+    let starting_position = starting_position.ok_or("Missing the starting position")?;
+    starting_position
+        .validate(&variant)
+        .map_err(|error| format!("Invalid starting position: {error}"))?;
+    let mut starting_annotations = BoardAnnotations::default();
+    for (ply, ply_annotations) in annotations {
+        match ply {
+            0 => starting_annotations = ply_annotations,
+            _ => {
+                moves
+                    .get_mut(ply - 1)
+                    .ok_or(format!("Annotations for ply {ply}, which wasn't played"))?
+                    .annotations = ply_annotations
+            }
+        }
+    }
+    // The clocks after each move are only written for timed games, whose time control comes later.
+    if let Some(time_control) = clocks.time_control {
+        for (ply, seconds) in move_clocks {
+            let recorded_move = ply
+                .checked_sub(1)
+                .and_then(|index| moves.get_mut(index))
+                .ok_or(format!("Clocks for ply {ply}, which wasn't played"))?;
+            recorded_move.clocks = Some(parse_clocks(&format!(
+                "{} {seconds}",
+                time_control.get_name()
+            ))?);
+        }
+    }
+    new_game_settings.time_control = clocks.time_control;
+
+    Ok(SavedGame {
+        variant,
+        game_tags,
+        starting_position,
+        starting_annotations,
+        moves,
+        clocks,
+        new_game_settings,
+        local_player,
+        is_board_flipped,
+        auto_flip,
+    })
+}
+
+// Parses "untimed", or a time control followed by the seconds left for white and black, such as
+// "5+3 287.5 290".
+fn parse_clocks(value: &str) -> Result<Clocks, String> {
+    if value == "untimed" {
+        return Ok(Clocks::new(None));
+    }
+    let fields: Vec<&str> = value.split(' ').collect();
+    let [time_control, white_seconds, black_seconds] = fields[..] else {
+        return Err(format!("Invalid clocks \"{value}\""));
+    };
+    let parse_seconds = |seconds: &str| {
+        seconds
+            .parse()
+            .map_err(|_| format!("Invalid clock time \"{seconds}\""))
+    };
+    Ok(Clocks {
+        time_control: Some(time_control.parse()?),
+        white_seconds: parse_seconds(white_seconds)?,
+        black_seconds: parse_seconds(black_seconds)?,
+    })
+}
+
+// Works out the move made from its coordinates (such as "e2e4") and the position before it. A king
+// moving more than one tile is castling, in which the rook in the corner it moved towards jumps to
+// its other side.
+fn parse_move_made(
+    coordinates: &str,
+    previous_position: &PositionSnapshot,
+    map_size: &TilemapSize,
+) -> Result<MoveMade, String> {
+    let (from, to) =
+        parse_coordinate_move(coordinates).ok_or(format!("Invalid move \"{coordinates}\""))?;
+    let get_game_piece_at_position = |tile_pos: &TilePos| {
+        previous_position
+            .pieces
+            .iter()
+            .find_map(|(other_tile_pos, game_piece)| {
+                (other_tile_pos == tile_pos).then_some(*game_piece)
+            })
+    };
+    let game_piece = get_game_piece_at_position(&from)
+        .ok_or(format!("Move \"{coordinates}\" starts from an empty tile"))?;
+
+    let mut special_move = None;
+    if game_piece.piece == Piece::King && from.x.abs_diff(to.x) > 1 {
+        let (rook_from_x, rook_to_x) = if to.x < from.x {
+            (0, to.x + 1)
+        } else {
+            (map_size.x - 1, to.x - 1)
+        };
+        special_move = Some(SpecialMove::Castling {
+            rook_from: TilePos {
+                x: rook_from_x,
+                y: to.y,
+            },
+            rook_to: TilePos {
+                x: rook_to_x,
+                y: to.y,
+            },
+        });
+    }
+
+    Ok(MoveMade {
+        from,
+        to,
+        game_piece,
+        captured: get_game_piece_at_position(&to),
+        special_move,
+    })
+}
@@ -0,0 +1,58 @@
+use crate::resources::UserSettings;
+use ron::ser::PrettyConfig;
+use ron::Value;
+use std::path::PathBuf;
+
+const SETTINGS_FILE_NAME: &str = "settings.ron";
+
+// Returns where the settings file is kept in the user's config directory, such as
+// ~/.config/bevy-chess/settings.ron on Linux, or None if there's no config directory to be found.
+pub fn get_settings_path() -> Option<PathBuf> {
+    let env_path = |name: &str| std::env::var_os(name).map(PathBuf::from);
+    let config_dir = if cfg!(target_os = "windows") {
+        env_path("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_path("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_path("XDG_CONFIG_HOME").or_else(|| env_path("HOME").map(|home| home.join(".config")))
+    }?;
+    Some(config_dir.join("bevy-chess").join(SETTINGS_FILE_NAME))
+}
+
+// Writes the settings in RON, like the board themes and piece sets, with each setting on its own
+// line.
+pub fn write_settings(settings: &UserSettings) -> String {
+    let text = ron::ser::to_string_pretty(settings, PrettyConfig::new())
+        .expect("Settings should always be writable");
+    format!("// Settings for bevy-chess, which are reloaded when this file changes.\n{text}\n")
+}
+
+// Parses settings written by write_settings(), which may have been edited by hand. Along with the
+// settings, returns the names of any settings that aren't known, which are ignored.
+pub fn parse_settings(text: &str) -> Result<(UserSettings, Vec<String>), String> {
+    let mut settings: UserSettings = ron::from_str(text).map_err(|error| error.to_string())?;
+    settings.board_scale = settings.board_scale.clamp(0.25, 4.0);
+    settings.volume = settings.volume.clamp(0.0, 1.0);
+    settings.animation_duration = settings.animation_duration.max(0.0);
+
+    // The known names are those written for the default settings.
+    let get_names = |value: Value| match value {
+        Value::Map(map) => map
+            .keys()
+            .filter_map(|key| match key {
+                Value::String(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    let known_names = get_names(
+        ron::from_str(&write_settings(&UserSettings::default()))
+            .expect("Default settings should parse"),
+    );
+    let unknown_names = get_names(ron::from_str(text).map_err(|error| error.to_string())?)
+        .into_iter()
+        .filter(|name| !known_names.contains(name))
+        .collect();
+    Ok((settings, unknown_names))
+}
@@ -1,3 +1,21 @@
+pub mod actions;
+pub mod animation;
+pub mod annotations;
+pub mod captures;
+pub mod clock;
+pub mod coordinates;
+pub mod editor;
+pub mod game_state;
+pub mod history;
 pub mod input;
+pub mod menu;
+pub mod move_list;
+pub mod network;
+pub mod orientation;
+pub mod premove;
+pub mod save;
+pub mod settings;
 pub mod setup;
+pub mod sound;
+pub mod theme;
 pub mod update;
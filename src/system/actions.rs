@@ -0,0 +1,254 @@
+use crate::components::{GamePiece, MenuButton};
+use crate::events::{
+    GameAction, GameActionRequested, GameEndReason, GameEnded, GameResult, MovesUndone,
+    OfferAnswered, UndoRequested,
+};
+use crate::resources::{
+    AppState, ColorToMove, LastMove, LocalPlayer, MoveHistory, MustRecalculateLegalMoves,
+    NetworkGame, NewGameSettings, OfferPolicy, Opponent, PendingOffer, Premoves, RightToCastle,
+    SelectedPiece, SelectedPieceOriginalPosition,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+// Resigns, offers a draw or asks for a takeback from the pause menu, then goes back to the game.
+// The side played here acts, or when both sides are played here, the side to move (or for a
+// takeback, the side that just moved). The offer panel's buttons answer the waiting offer.
+pub fn handle_game_action_buttons(
+    local_player: Res<LocalPlayer>,
+    color_to_move: Res<ColorToMove>,
+    pending_offer: Res<PendingOffer>,
+    mut next_app_state: ResMut<NextState<AppState>>,
+    mut game_action_events: EventWriter<GameActionRequested>,
+    mut offer_answered_events: EventWriter<OfferAnswered>,
+    menu_button_q: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+) {
+    for (interaction, menu_button) in &menu_button_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let action = match menu_button {
+            MenuButton::Resign => GameAction::Resign,
+            MenuButton::OfferDraw => GameAction::OfferDraw,
+            MenuButton::RequestTakeback => GameAction::RequestTakeback,
+            MenuButton::AcceptOffer | MenuButton::DeclineOffer => {
+                if let Some(offer) = pending_offer.0 {
+                    offer_answered_events.send(OfferAnswered {
+                        color: offer.color.opposite(),
+                        action: offer.action,
+                        is_accepted: *menu_button == MenuButton::AcceptOffer,
+                    });
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        let color = local_player.0.unwrap_or(match action {
+            GameAction::RequestTakeback => color_to_move.0.opposite(),
+            _ => color_to_move.0,
+        });
+        game_action_events.send(GameActionRequested { color, action });
+        next_app_state.set(AppState::Playing);
+    }
+}
+
+// Ends the game when a side resigns, and keeps a draw offer or takeback request until the other
+// side answers it. A side can't ask to take back a move before making one. While an offer waits,
+// the same side can't make another, and an offer from the other side is declined for it, except
+// that two draw offers agree to a draw.
+pub fn handle_game_actions(
+    mut game_action_events: EventReader<GameActionRequested>,
+    mut pending_offer: ResMut<PendingOffer>,
+    move_history: Res<MoveHistory>,
+    mut game_ended_events: EventWriter<GameEnded>,
+    mut offer_answered_events: EventWriter<OfferAnswered>,
+) {
+    for game_action in game_action_events.read() {
+        let color = game_action.color;
+        match (game_action.action, pending_offer.0) {
+            (GameAction::Resign, _) => {
+                info!("{}", game_action.get_description());
+                game_ended_events.send(GameEnded {
+                    result: GameResult::Win(color.opposite()),
+                    reason: GameEndReason::Resignation,
+                });
+            }
+            (_, Some(offer)) if offer.color == color => {
+                warn!(
+                    "Ignoring the request, as {} is still waiting for an answer: {}",
+                    color.get_name(),
+                    game_action.get_description()
+                );
+            }
+            (GameAction::OfferDraw, Some(offer)) if offer.action == GameAction::OfferDraw => {
+                info!("Both sides offered a draw");
+                pending_offer.0 = None;
+                game_ended_events.send(GameEnded {
+                    result: GameResult::Draw,
+                    reason: GameEndReason::Agreement,
+                });
+            }
+            (action, Some(offer)) => {
+                info!(
+                    "Declining the request, as {} is waiting for an answer: {}",
+                    offer.color.get_name(),
+                    game_action.get_description()
+                );
+                offer_answered_events.send(OfferAnswered {
+                    color: offer.color,
+                    action,
+                    is_accepted: false,
+                });
+            }
+            (GameAction::RequestTakeback, None)
+                if !move_history
+                    .moves
+                    .iter()
+                    .any(|recorded_move| recorded_move.move_made.game_piece.color == color) =>
+            {
+                warn!("{} has no move to take back", color.get_name());
+            }
+            (_, None) => {
+                info!("{}", game_action.get_description());
+                pending_offer.0 = Some(*game_action);
+            }
+        }
+    }
+}
+
+// Answers offers made to a remote opponent that isn't another player, such as an engine, by the
+// OfferPolicy. A player over the network answers for themselves.
+pub fn answer_offers_by_policy(
+    pending_offer: Res<PendingOffer>,
+    local_player: Res<LocalPlayer>,
+    new_game_settings: Res<NewGameSettings>,
+    offer_policy: Res<OfferPolicy>,
+    network_game: Option<Res<NetworkGame>>,
+    mut offer_answered_events: EventWriter<OfferAnswered>,
+) {
+    let Some(offer) = pending_offer.0 else {
+        return;
+    };
+    let has_network_player = network_game.is_some_and(|network_game| network_game.peer.is_some());
+    if local_player.0 != Some(offer.color)
+        || new_game_settings.opponent != Opponent::Remote
+        || has_network_player
+    {
+        return;
+    }
+    let is_accepted = match offer.action {
+        GameAction::OfferDraw => offer_policy.accepts_draws,
+        GameAction::RequestTakeback => offer_policy.accepts_takebacks,
+        GameAction::Resign => return,
+    };
+    offer_answered_events.send(OfferAnswered {
+        color: offer.color.opposite(),
+        action: offer.action,
+        is_accepted,
+    });
+}
+
+// Settles the waiting offer once the other side answers it. An accepted draw offer ends the game,
+// and an accepted takeback request takes back the requesting side's last move along with every
+// move made since.
+pub fn resolve_offers(
+    mut offer_answered_events: EventReader<OfferAnswered>,
+    mut pending_offer: ResMut<PendingOffer>,
+    move_history: Res<MoveHistory>,
+    mut game_ended_events: EventWriter<GameEnded>,
+    mut undo_requested_events: EventWriter<UndoRequested>,
+) {
+    for offer_answered in offer_answered_events.read() {
+        let Some(offer) = pending_offer.0 else {
+            continue;
+        };
+        if offer_answered.color == offer.color || offer_answered.action != offer.action {
+            continue;
+        }
+        pending_offer.0 = None;
+        if !offer_answered.is_accepted {
+            info!("{} declined", offer_answered.color.get_name());
+            continue;
+        }
+        info!("{} accepted", offer_answered.color.get_name());
+        match offer.action {
+            GameAction::OfferDraw => {
+                game_ended_events.send(GameEnded {
+                    result: GameResult::Draw,
+                    reason: GameEndReason::Agreement,
+                });
+            }
+            GameAction::RequestTakeback => {
+                if let Some(index) = move_history.moves.iter().rev().position(|recorded_move| {
+                    recorded_move.move_made.game_piece.color == offer.color
+                }) {
+                    undo_requested_events.send(UndoRequested { plies: index + 1 });
+                }
+            }
+            GameAction::Resign => {}
+        }
+    }
+}
+
+// Takes back the last moves, putting the pieces back as they were before them. Premoves and any
+// carried piece are dropped, as they were meant for the later position.
+pub fn undo_moves(
+    mut commands: Commands,
+    mut undo_requested_events: EventReader<UndoRequested>,
+    mut moves_undone_events: EventWriter<MovesUndone>,
+    mut move_history: ResMut<MoveHistory>,
+    mut color_to_move: ResMut<ColorToMove>,
+    mut right_to_castle: ResMut<RightToCastle>,
+    mut last_move: ResMut<LastMove>,
+    mut must_recalculate_legal_moves: ResMut<MustRecalculateLegalMoves>,
+    mut premoves: ResMut<Premoves>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut selected_piece_original_position: ResMut<SelectedPieceOriginalPosition>,
+    tile_game_piece_q: Query<Entity, With<GamePiece>>,
+    tilemap_q: Query<&TileStorage>,
+) {
+    let plies: usize = undo_requested_events
+        .read()
+        .map(|undo_requested| undo_requested.plies)
+        .sum();
+    if plies == 0 || move_history.starting_position.is_none() {
+        return;
+    }
+    let tilemap = tilemap_q.single();
+
+    let ply = move_history.moves.len().saturating_sub(plies);
+    let undone_moves = move_history
+        .moves
+        .drain(ply..)
+        .rev()
+        .map(|recorded_move| recorded_move.move_made)
+        .collect();
+    move_history.viewed_ply = None;
+    let position = move_history.get_position(ply).clone();
+    for tile_id in &tile_game_piece_q {
+        commands.entity(tile_id).remove::<GamePiece>();
+    }
+    for (tile_pos, game_piece) in &position.pieces {
+        let tile_id = tilemap
+            .get(tile_pos)
+            .expect("Recorded piece should be placed on a tile that exists on the board");
+        commands.entity(tile_id).insert(*game_piece);
+    }
+    *color_to_move = ColorToMove(position.color_to_move);
+    *right_to_castle = position.right_to_castle;
+    *last_move = LastMove(
+        move_history
+            .moves
+            .last()
+            .map(|recorded_move| (recorded_move.move_made.from, recorded_move.move_made.to)),
+    );
+    *must_recalculate_legal_moves = MustRecalculateLegalMoves(true);
+
+    premoves.0.clear();
+    *selected_piece = SelectedPiece(None);
+    *selected_piece_original_position = SelectedPieceOriginalPosition(None);
+    moves_undone_events.send(MovesUndone {
+        moves: undone_moves,
+    });
+    info!("Took back {plies} plies");
+}
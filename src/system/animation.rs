@@ -0,0 +1,250 @@
+use crate::components::{CursorDisplay, GamePiece, Piece, PieceAnimation};
+use crate::events::{MoveMade, MovesUndone, SpecialMove};
+use crate::resources::{
+    BoardOrientation, InGame, MoveAnimation, MoveHistory, PieceSet, SelectedPieceOriginalPosition,
+    Variant,
+};
+use crate::system::orientation::get_tile_center;
+use crate::SCALE;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+// Shows each tile's piece on its sprite. This is the piece in the viewed position while an earlier
+// position is viewed, and otherwise the tile's game piece, unless that piece is being carried or is
+// still being animated towards the tile.
+pub fn sync_piece_sprites(
+    move_history: Res<MoveHistory>,
+    selected_piece_original_position: Res<SelectedPieceOriginalPosition>,
+    changed_game_piece_q: Query<(), Changed<GamePiece>>,
+    mut removed_game_pieces: RemovedComponents<GamePiece>,
+    animation_q: Query<&PieceAnimation>,
+    added_animation_q: Query<(), Added<PieceAnimation>>,
+    mut removed_animations: RemovedComponents<PieceAnimation>,
+    mut tile_q: Query<
+        (
+            &TilePos,
+            Option<&GamePiece>,
+            &mut Handle<Image>,
+            &mut Visibility,
+        ),
+        Without<CursorDisplay>,
+    >,
+    asset_server: Res<AssetServer>,
+    piece_set: Res<PieceSet>,
+) {
+    // Read the removed components every frame, so they aren't seen again on a later frame.
+    let has_removed_game_piece = removed_game_pieces.read().count() > 0;
+    let has_removed_animation = removed_animations.read().count() > 0;
+    if !(move_history.is_changed()
+        || piece_set.is_changed()
+        || selected_piece_original_position.is_changed()
+        || !changed_game_piece_q.is_empty()
+        || has_removed_game_piece
+        || !added_animation_q.is_empty()
+        || has_removed_animation)
+    {
+        return;
+    }
+
+    let viewed_position = move_history
+        .viewed_ply
+        .map(|ply| move_history.get_position(ply));
+    let hidden_tiles: Vec<TilePos> = animation_q
+        .iter()
+        .filter_map(|animation| animation.hidden_tile)
+        .chain(selected_piece_original_position.0)
+        .collect();
+
+    for (tile_pos, live_game_piece, mut handle, mut visibility) in &mut tile_q {
+        let game_piece = match viewed_position {
+            Some(viewed_position) => {
+                viewed_position
+                    .pieces
+                    .iter()
+                    .find_map(|(other_tile_pos, game_piece)| {
+                        (other_tile_pos == tile_pos).then_some(*game_piece)
+                    })
+            }
+            None if hidden_tiles.contains(tile_pos) => None,
+            None => live_game_piece.copied(),
+        };
+        if let Some(game_piece) = game_piece {
+            *handle = asset_server.load(piece_set.get_asset_path(&game_piece));
+            *visibility = Visibility::Visible;
+        } else {
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+fn spawn_piece_animation(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    piece_set: &PieceSet,
+    game_piece: GamePiece,
+    start: Vec2,
+    end: Vec2,
+    hidden_tile: Option<TilePos>,
+    fades_out: bool,
+) {
+    commands.spawn((
+        StateScoped(InGame),
+        PieceAnimation {
+            start,
+            end,
+            elapsed: 0.0,
+            hidden_tile,
+            fades_out,
+        },
+        SpriteBundle {
+            // Captured pieces fade out underneath the moving piece.
+            transform: Transform::from_translation(start.extend(if fades_out { 1.2 } else { 1.5 }))
+                .with_scale(Vec3::splat(SCALE)),
+            texture: asset_server.load(piece_set.get_asset_path(&game_piece)),
+            ..default()
+        },
+    ));
+}
+
+// Slides the moved piece (and the rook when castling) from where it was to where it went, fading
+// out any captured piece. A move being taken back slides the other way, and the captured piece is
+// simply shown again.
+fn spawn_move_animations(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    piece_set: &PieceSet,
+    get_center: impl Fn(&TilePos) -> Vec2,
+    move_made: &MoveMade,
+    is_undone: bool,
+) {
+    let slide = |commands: &mut Commands, game_piece: GamePiece, from: TilePos, to: TilePos| {
+        let (from, to) = if is_undone { (to, from) } else { (from, to) };
+        spawn_piece_animation(
+            commands,
+            asset_server,
+            piece_set,
+            game_piece,
+            get_center(&from),
+            get_center(&to),
+            Some(to),
+            false,
+        );
+    };
+
+    if let Some(captured) = move_made.captured.filter(|_| !is_undone) {
+        let end = get_center(&move_made.to);
+        spawn_piece_animation(
+            commands,
+            asset_server,
+            piece_set,
+            captured,
+            end,
+            end,
+            None,
+            true,
+        );
+    }
+    slide(commands, move_made.game_piece, move_made.from, move_made.to);
+    if let Some(SpecialMove::Castling { rook_from, rook_to }) = move_made.special_move {
+        let rook = GamePiece {
+            piece: Piece::Rook,
+            color: move_made.game_piece.color,
+        };
+        slide(commands, rook, rook_from, rook_to);
+    }
+}
+
+// Starts animating every move, whatever it was made by.
+pub fn animate_moves(
+    mut commands: Commands,
+    move_animation: Res<MoveAnimation>,
+    variant: Res<Variant>,
+    orientation: Res<BoardOrientation>,
+    mut move_made_events: EventReader<MoveMade>,
+    animation_q: Query<Entity, With<PieceAnimation>>,
+    asset_server: Res<AssetServer>,
+    piece_set: Res<PieceSet>,
+) {
+    for move_made in move_made_events.read() {
+        if move_animation.duration <= 0.0 {
+            continue;
+        }
+        // Finish any earlier animations straight away, so they don't overlap.
+        for animation_id in &animation_q {
+            commands.entity(animation_id).despawn();
+        }
+
+        let map_size = variant.get_map_size();
+        spawn_move_animations(
+            &mut commands,
+            &asset_server,
+            &piece_set,
+            |tile_pos| get_tile_center(tile_pos, &map_size, &orientation),
+            move_made,
+            false,
+        );
+    }
+}
+
+// Animates moves being taken back, sliding each piece back to where it came from. Every move taken
+// back at once slides at the same time.
+pub fn animate_undone_moves(
+    mut commands: Commands,
+    move_animation: Res<MoveAnimation>,
+    variant: Res<Variant>,
+    orientation: Res<BoardOrientation>,
+    mut moves_undone_events: EventReader<MovesUndone>,
+    animation_q: Query<Entity, With<PieceAnimation>>,
+    asset_server: Res<AssetServer>,
+    piece_set: Res<PieceSet>,
+) {
+    for moves_undone in moves_undone_events.read() {
+        if move_animation.duration <= 0.0 {
+            continue;
+        }
+        for animation_id in &animation_q {
+            commands.entity(animation_id).despawn();
+        }
+
+        let map_size = variant.get_map_size();
+        for move_made in &moves_undone.moves {
+            spawn_move_animations(
+                &mut commands,
+                &asset_server,
+                &piece_set,
+                |tile_pos| get_tile_center(tile_pos, &map_size, &orientation),
+                move_made,
+                true,
+            );
+        }
+    }
+}
+
+pub fn update_piece_animations(
+    mut commands: Commands,
+    time: Res<Time>,
+    move_animation: Res<MoveAnimation>,
+    mut animation_q: Query<(Entity, &mut PieceAnimation, &mut Transform, &mut Sprite)>,
+) {
+    for (animation_id, mut animation, mut transform, mut sprite) in &mut animation_q {
+        animation.elapsed += time.delta_seconds();
+        let progress = if move_animation.duration > 0.0 {
+            (animation.elapsed / move_animation.duration).min(1.0)
+        } else {
+            1.0
+        };
+        if progress >= 1.0 {
+            commands.entity(animation_id).despawn();
+            continue;
+        }
+
+        // Ease in and out, so the piece starts and stops smoothly.
+        let eased_progress = progress * progress * (3.0 - 2.0 * progress);
+        let position = animation.start.lerp(animation.end, eased_progress);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        if animation.fades_out {
+            sprite.color.set_alpha(1.0 - progress);
+        }
+    }
+}
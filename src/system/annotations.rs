@@ -0,0 +1,130 @@
+use crate::components::{AnnotationSprite, MouseoverHighlight};
+use crate::resources::{AnnotationColor, Arrow, BoardOrientation, InGame, MoveHistory, SquareMark};
+use crate::system::orientation::get_tile_center;
+use crate::{SCALE, SCALED_GRID_SIZE};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+// The width of an arrow's line, and the length and width of its head, in pixels of the board's art.
+const ARROW_WIDTH: f32 = 2.0;
+const ARROWHEAD_SIZE: f32 = 5.0;
+
+// Right-clicking a tile marks it, and right-dragging from one tile to another draws an arrow
+// between them. Doing either again in the same color erases it. The annotations are drawn on the
+// shown position, so they're cleared from the board by the next move.
+pub fn draw_annotations(
+    mouse: Res<ButtonInput<MouseButton>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut move_history: ResMut<MoveHistory>,
+    mut drag_start: Local<Option<TilePos>>,
+    tile_q: Query<&TilePos, With<MouseoverHighlight>>,
+) {
+    let mouseover_tile = tile_q.get_single().ok().copied();
+    if mouse.just_pressed(MouseButton::Right) {
+        *drag_start = mouseover_tile;
+    }
+    if !mouse.just_released(MouseButton::Right) {
+        return;
+    }
+    let (Some(from), Some(to)) = (drag_start.take(), mouseover_tile) else {
+        return;
+    };
+
+    let color = AnnotationColor::from_modifiers(&keyboard);
+    let shown_ply = move_history.get_shown_ply();
+    let annotations = move_history.get_annotations_mut(shown_ply);
+    if from == to {
+        annotations.toggle_square_mark(SquareMark {
+            tile_pos: from,
+            color,
+        });
+    } else {
+        annotations.toggle_arrow(Arrow { from, to, color });
+    }
+}
+
+// Respawns the sprites of the shown position's arrows and marked squares. Marks are drawn under the
+// pieces and arrows over them.
+pub fn update_annotation_sprites(
+    mut commands: Commands,
+    move_history: Res<MoveHistory>,
+    orientation: Res<BoardOrientation>,
+    annotation_sprite_q: Query<Entity, With<AnnotationSprite>>,
+    tilemap_q: Query<&TileStorage>,
+    asset_server: Res<AssetServer>,
+) {
+    if !move_history.is_changed() && !orientation.is_changed() {
+        return;
+    }
+    for annotation_sprite in &annotation_sprite_q {
+        commands.entity(annotation_sprite).despawn();
+    }
+    let Ok(tilemap) = tilemap_q.get_single() else {
+        return;
+    };
+    if move_history.starting_position.is_none() {
+        return;
+    }
+    let annotations = move_history.get_annotations(move_history.get_shown_ply());
+
+    for square_mark in &annotations.square_marks {
+        let center = get_tile_center(&square_mark.tile_pos, &tilemap.size, &orientation);
+        commands.spawn((
+            SpriteBundle {
+                texture: asset_server.load("square_mark.png"),
+                sprite: Sprite {
+                    color: square_mark.color.get_color().into(),
+                    custom_size: Some(Vec2::new(SCALED_GRID_SIZE.x, SCALED_GRID_SIZE.y)),
+                    ..default()
+                },
+                transform: Transform::from_translation(center.extend(0.8)),
+                ..default()
+            },
+            AnnotationSprite,
+            StateScoped(InGame),
+        ));
+    }
+
+    for arrow in &annotations.arrows {
+        let start = get_tile_center(&arrow.from, &tilemap.size, &orientation);
+        let end = get_tile_center(&arrow.to, &tilemap.size, &orientation);
+        let direction = (end - start).normalize();
+        let rotation = Quat::from_rotation_z(direction.to_angle());
+        let color = Color::from(arrow.color.get_color());
+
+        // The line stops where the head starts, so the two don't overlap and darken each other.
+        let head_length = ARROWHEAD_SIZE * SCALE;
+        let line_end = end - direction * head_length;
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::new(start.distance(line_end), ARROW_WIDTH * SCALE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(((start + line_end) / 2.0).extend(1.8))
+                    .with_rotation(rotation),
+                ..default()
+            },
+            AnnotationSprite,
+            StateScoped(InGame),
+        ));
+        commands.spawn((
+            SpriteBundle {
+                texture: asset_server.load("arrowhead.png"),
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(head_length)),
+                    ..default()
+                },
+                transform: Transform::from_translation(
+                    (end - direction * head_length / 2.0).extend(1.8),
+                )
+                .with_rotation(rotation),
+                ..default()
+            },
+            AnnotationSprite,
+            StateScoped(InGame),
+        ));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::components::{CaptureTray, CaptureTrayPanel, Color};
+use crate::resources::{BoardOrientation, InGame, MoveHistory, PieceSet};
+use bevy::prelude::*;
+
+const CAPTURED_PIECE_SIZE: f32 = 28.0;
+
+pub fn setup_capture_trays(mut commands: Commands) {
+    commands
+        .spawn((
+            CaptureTrayPanel,
+            StateScoped(InGame),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    width: Val::Px(280.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                },
+                background_color: Srgba::new(0.1, 0.1, 0.1, 0.9).into(),
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            // Black's captures are listed first, so they're on top when white is at the bottom of
+            // the board.
+            for color in [Color::Black, Color::White] {
+                panel.spawn((
+                    CaptureTray(color),
+                    NodeBundle {
+                        style: Style {
+                            min_height: Val::Px(CAPTURED_PIECE_SIZE),
+                            flex_wrap: FlexWrap::Wrap,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                ));
+            }
+        });
+}
+
+// Fills each tray with the pieces its color has captured up to the shown position, followed by how
+// much more material that color has on the board, if it's ahead.
+pub fn update_capture_trays(
+    mut commands: Commands,
+    move_history: Res<MoveHistory>,
+    orientation: Res<BoardOrientation>,
+    mut panel_q: Query<&mut Style, With<CaptureTrayPanel>>,
+    tray_q: Query<(Entity, &CaptureTray)>,
+    asset_server: Res<AssetServer>,
+    piece_set: Res<PieceSet>,
+) {
+    if orientation.is_changed() {
+        // Keep each side's captures on its own side of the board.
+        panel_q.single_mut().flex_direction = if orientation.is_flipped {
+            FlexDirection::ColumnReverse
+        } else {
+            FlexDirection::Column
+        };
+    }
+
+    if !(move_history.is_changed() || piece_set.is_changed())
+        || move_history.starting_position.is_none()
+    {
+        return;
+    }
+
+    let shown_ply = move_history.get_shown_ply();
+    let get_material = |color: Color| -> u32 {
+        move_history
+            .get_position(shown_ply)
+            .pieces
+            .iter()
+            .filter(|(_, game_piece)| game_piece.color == color)
+            .map(|(_, game_piece)| game_piece.piece.get_value())
+            .sum()
+    };
+
+    for (tray_id, capture_tray) in &tray_q {
+        let color = capture_tray.0;
+        let material_difference =
+            get_material(color) as i32 - get_material(color.opposite()) as i32;
+
+        commands.entity(tray_id).despawn_descendants();
+        commands.entity(tray_id).with_children(|tray| {
+            for game_piece in move_history.get_captured_pieces(color, shown_ply) {
+                tray.spawn(ImageBundle {
+                    style: Style {
+                        width: Val::Px(CAPTURED_PIECE_SIZE),
+                        height: Val::Px(CAPTURED_PIECE_SIZE),
+                        ..default()
+                    },
+                    image: UiImage::new(asset_server.load(piece_set.get_asset_path(&game_piece))),
+                    ..default()
+                });
+            }
+            if material_difference > 0 {
+                tray.spawn(TextBundle::from_section(
+                    format!("+{material_difference}"),
+                    TextStyle {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                ));
+            }
+        });
+    }
+}
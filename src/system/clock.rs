@@ -0,0 +1,139 @@
+use crate::components::{ClockPanel, ClockText, Color};
+use crate::events::{GameEndReason, GameEnded, GameResult, MoveMade, MovesUndone};
+use crate::resources::{Clocks, ColorToMove, InGame, MoveHistory, NetworkGame, NewGameSettings};
+use bevy::prelude::*;
+
+// The clock of a side with less time than this is shown in red.
+pub const LOW_TIME_SECONDS: f32 = 10.0;
+const CLOCK_TEXT_COLOR: Srgba = Srgba::rgb(0.9, 0.9, 0.9);
+const LOW_TIME_TEXT_COLOR: Srgba = Srgba::rgb(0.95, 0.3, 0.3);
+
+pub fn setup_clocks(new_game_settings: Res<NewGameSettings>, mut commands: Commands) {
+    commands.insert_resource(Clocks::new(new_game_settings.time_control));
+}
+
+// Runs down the clock of the side to move, and adds the increment to a side's clock after it moves.
+// A side whose clock runs out loses, though in a game joined over the network it's the host's clock
+// that decides.
+pub fn update_clocks(
+    time: Res<Time>,
+    color_to_move: Res<ColorToMove>,
+    network_game: Option<Res<NetworkGame>>,
+    mut clocks: ResMut<Clocks>,
+    mut move_made_events: EventReader<MoveMade>,
+    mut game_ended_events: EventWriter<GameEnded>,
+) {
+    let Some(time_control) = clocks.time_control else {
+        move_made_events.clear();
+        return;
+    };
+    for move_made in move_made_events.read() {
+        *clocks.get_seconds_mut(move_made.game_piece.color) +=
+            time_control.increment_seconds as f32;
+    }
+
+    let seconds = clocks.get_seconds_mut(color_to_move.0);
+    if *seconds <= 0.0 {
+        return;
+    }
+    *seconds = (*seconds - time.delta_seconds()).max(0.0);
+    let leaves_game_end_to_host =
+        network_game.is_some_and(|network_game| network_game.leaves_game_end_to_host());
+    if *seconds == 0.0 && !leaves_game_end_to_host {
+        info!("Game ended by timeout");
+        game_ended_events.send(GameEnded {
+            result: GameResult::Win(color_to_move.0.opposite()),
+            reason: GameEndReason::Timeout,
+        });
+    }
+}
+
+// Gives each side back the time it had in the position moves were taken back to. Moves loaded from
+// older saves don't have their clocks, which are then left as they are.
+pub fn rewind_clocks(
+    move_history: Res<MoveHistory>,
+    mut clocks: ResMut<Clocks>,
+    mut moves_undone_events: EventReader<MovesUndone>,
+) {
+    if moves_undone_events.read().count() == 0 {
+        return;
+    }
+    let restored_clocks = match move_history.moves.last() {
+        Some(recorded_move) => recorded_move.clocks,
+        None => Some(Clocks::new(clocks.time_control)),
+    };
+    if let Some(restored_clocks) = restored_clocks {
+        *clocks = restored_clocks;
+    }
+}
+
+pub fn setup_clock_panel(mut commands: Commands) {
+    commands
+        .spawn((
+            ClockPanel,
+            StateScoped(InGame),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(4.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    display: Display::None,
+                    ..default()
+                },
+                background_color: Srgba::new(0.1, 0.1, 0.1, 0.9).into(),
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            for color in [Color::Black, Color::White] {
+                panel.spawn((
+                    ClockText(color),
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 28.0,
+                            color: CLOCK_TEXT_COLOR.into(),
+                            ..default()
+                        },
+                    ),
+                ));
+            }
+        });
+}
+
+// Shows the time left for each side as minutes and seconds, or tenths of a second when it's nearly
+// run out.
+pub fn update_clock_panel(
+    clocks: Res<Clocks>,
+    mut clock_panel_q: Query<&mut Style, With<ClockPanel>>,
+    mut clock_text_q: Query<(&mut Text, &ClockText)>,
+) {
+    if !clocks.is_changed() {
+        return;
+    }
+    for mut style in &mut clock_panel_q {
+        style.display = if clocks.time_control.is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+    }
+    for (mut text, clock_text) in &mut clock_text_q {
+        let seconds = clocks.get_seconds(clock_text.0);
+        let time = if seconds < LOW_TIME_SECONDS {
+            format!("{seconds:.1}")
+        } else {
+            let whole_seconds = seconds.ceil() as u32;
+            format!("{}:{:02}", whole_seconds / 60, whole_seconds % 60)
+        };
+        text.sections[0].value = format!("{} {time}", clock_text.0.get_name());
+        text.sections[0].style.color = if seconds < LOW_TIME_SECONDS {
+            LOW_TIME_TEXT_COLOR.into()
+        } else {
+            CLOCK_TEXT_COLOR.into()
+        };
+    }
+}
@@ -0,0 +1,130 @@
+use crate::components::{CoordinateLabel, CoordinateLabelKind};
+use crate::notation::{get_file_letter, get_rank_number, write_square};
+use crate::resources::{BoardOrientation, CoordinateLabels, InGame, Variant};
+use crate::system::orientation::{get_board_offset, get_tile_center};
+use crate::SCALED_GRID_SIZE;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+const EDGE_LABEL_COLOR: Srgba = Srgba::rgb(0.9, 0.77, 0.56);
+const EDGE_LABEL_FONT_SIZE: f32 = 28.0;
+const TILE_LABEL_COLOR: Srgba = Srgba::new(0.0, 0.0, 0.0, 0.6);
+const TILE_LABEL_FONT_SIZE: f32 = 16.0;
+
+// Returns the world position of the label's center. File labels sit below the board's displayed
+// bottom row and rank labels left of its displayed left column, so that they follow their tiles
+// when the board is flipped. Tile labels sit in the bottom-left corner of their tile.
+fn get_coordinate_label_pos(
+    label: &CoordinateLabel,
+    map_size: &TilemapSize,
+    orientation: &BoardOrientation,
+) -> Vec2 {
+    let tile_center = get_tile_center(&label.tile_pos, map_size, orientation);
+    let board_offset = get_board_offset(map_size);
+    match label.kind {
+        CoordinateLabelKind::File => Vec2 {
+            x: tile_center.x,
+            y: board_offset.y - SCALED_GRID_SIZE.y * 0.8,
+        },
+        CoordinateLabelKind::Rank => Vec2 {
+            x: board_offset.x - SCALED_GRID_SIZE.x * 0.8,
+            y: tile_center.y,
+        },
+        CoordinateLabelKind::Tile => Vec2 {
+            x: tile_center.x - SCALED_GRID_SIZE.x * 0.3,
+            y: tile_center.y - SCALED_GRID_SIZE.y * 0.35,
+        },
+    }
+}
+
+pub fn setup_coordinate_labels(mut commands: Commands, variant: Res<Variant>) {
+    let map_size = variant.get_map_size();
+
+    let mut labels = Vec::new();
+    for x in 0..map_size.x {
+        labels.push((
+            CoordinateLabel {
+                tile_pos: TilePos { x, y: 0 },
+                kind: CoordinateLabelKind::File,
+            },
+            get_file_letter(x).to_string(),
+        ));
+    }
+    for y in 0..map_size.y {
+        labels.push((
+            CoordinateLabel {
+                tile_pos: TilePos { x: 0, y },
+                kind: CoordinateLabelKind::Rank,
+            },
+            get_rank_number(y).to_string(),
+        ));
+    }
+    for x in 0..map_size.x {
+        for y in 0..map_size.y {
+            let tile_pos = TilePos { x, y };
+            labels.push((
+                CoordinateLabel {
+                    tile_pos,
+                    kind: CoordinateLabelKind::Tile,
+                },
+                write_square(&tile_pos),
+            ));
+        }
+    }
+
+    // The labels are placed by update_coordinate_labels() once the board's orientation is known.
+    for (label, text) in labels {
+        let (color, font_size) = if label.kind == CoordinateLabelKind::Tile {
+            (TILE_LABEL_COLOR, TILE_LABEL_FONT_SIZE)
+        } else {
+            (EDGE_LABEL_COLOR, EDGE_LABEL_FONT_SIZE)
+        };
+        commands.spawn((
+            label,
+            StateScoped(InGame),
+            Text2dBundle {
+                text: Text::from_section(
+                    text,
+                    TextStyle {
+                        font_size,
+                        color: color.into(),
+                        ..default()
+                    },
+                ),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ));
+    }
+}
+
+pub fn toggle_coordinate_labels(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut coordinate_labels: ResMut<CoordinateLabels>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyC) {
+        coordinate_labels.show_in_tiles = !coordinate_labels.show_in_tiles;
+    }
+}
+
+pub fn update_coordinate_labels(
+    variant: Res<Variant>,
+    orientation: Res<BoardOrientation>,
+    coordinate_labels: Res<CoordinateLabels>,
+    mut label_q: Query<(&CoordinateLabel, &mut Transform, &mut Visibility)>,
+) {
+    if orientation.is_changed() || coordinate_labels.is_changed() {
+        let map_size = variant.get_map_size();
+        for (label, mut transform, mut visibility) in &mut label_q {
+            let label_pos = get_coordinate_label_pos(label, &map_size, &orientation);
+            // Tile labels are drawn above the tiles but below the pieces.
+            transform.translation = label_pos.extend(0.5);
+            *visibility =
+                if label.kind != CoordinateLabelKind::Tile || coordinate_labels.show_in_tiles {
+                    Visibility::Visible
+                } else {
+                    Visibility::Hidden
+                };
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use crate::components::{
+    Color, EditorButton, EditorPanel, EditorStatusText, GamePiece, LegalMoves, MouseoverHighlight,
+    PaletteButton, Piece, PieceImage,
+};
+use crate::notation::write_fen;
+use crate::resources::{
+    ColorToMove, GameTags, InGame, LastMove, MoveHistory, MustRecalculateLegalMoves, PieceSet,
+    PositionEditor, PositionSnapshot, Premoves, RightToCastle, SelectedPiece, Variant,
+};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+const PALETTE_PIECES: [Piece; 8] = [
+    Piece::King,
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+    Piece::Archbishop,
+    Piece::Chancellor,
+];
+const EDITOR_BUTTONS: [EditorButton; 6] = [
+    EditorButton::ColorToMove,
+    EditorButton::WhiteKingside,
+    EditorButton::WhiteQueenside,
+    EditorButton::BlackKingside,
+    EditorButton::BlackQueenside,
+    EditorButton::PlayFromHere,
+];
+pub const BUTTON_COLOR: Srgba = Srgba::rgb(0.25, 0.25, 0.25);
+pub const SELECTED_BUTTON_COLOR: Srgba = Srgba::rgb(0.35, 0.6, 0.35);
+
+pub fn is_editing(position_editor: Res<PositionEditor>) -> bool {
+    position_editor.is_active
+}
+
+pub fn setup_editor_panel(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    piece_set: Res<PieceSet>,
+) {
+    let text_style = TextStyle {
+        font_size: 20.0,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            EditorPanel,
+            StateScoped(InGame),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(10.0),
+                    right: Val::Px(10.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                },
+                background_color: Srgba::new(0.1, 0.1, 0.1, 0.9).into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            panel.spawn(TextBundle::from_section(
+                "Position editor",
+                text_style.clone(),
+            ));
+
+            // One row of palette buttons for each color.
+            for color in [Color::White, Color::Black] {
+                panel
+                    .spawn(NodeBundle {
+                        style: Style {
+                            column_gap: Val::Px(4.0),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        for piece in PALETTE_PIECES {
+                            let game_piece = GamePiece { piece, color };
+                            row.spawn((
+                                PaletteButton(Some(game_piece)),
+                                ButtonBundle {
+                                    style: Style {
+                                        width: Val::Px(36.0),
+                                        height: Val::Px(36.0),
+                                        padding: UiRect::all(Val::Px(4.0)),
+                                        ..default()
+                                    },
+                                    background_color: BUTTON_COLOR.into(),
+                                    ..default()
+                                },
+                            ))
+                            .with_children(|button| {
+                                button.spawn((
+                                    PieceImage(game_piece),
+                                    ImageBundle {
+                                        style: Style {
+                                            width: Val::Percent(100.0),
+                                            height: Val::Percent(100.0),
+                                            ..default()
+                                        },
+                                        image: UiImage::new(
+                                            asset_server
+                                                .load(piece_set.get_asset_path(&game_piece)),
+                                        ),
+                                        ..default()
+                                    },
+                                ));
+                            });
+                        }
+                    });
+            }
+
+            panel
+                .spawn((
+                    PaletteButton(None),
+                    ButtonBundle {
+                        style: Style {
+                            padding: UiRect::all(Val::Px(4.0)),
+                            ..default()
+                        },
+                        background_color: BUTTON_COLOR.into(),
+                        ..default()
+                    },
+                ))
+                .with_children(|button| {
+                    button.spawn(TextBundle::from_section(
+                        "Erase (or right-click)",
+                        text_style.clone(),
+                    ));
+                });
+
+            // The labels of these buttons are filled in by update_editor_panel(), as they change
+            // when the buttons are pressed.
+            for editor_button in EDITOR_BUTTONS {
+                panel
+                    .spawn((
+                        editor_button,
+                        ButtonBundle {
+                            style: Style {
+                                padding: UiRect::all(Val::Px(4.0)),
+                                ..default()
+                            },
+                            background_color: BUTTON_COLOR.into(),
+                            ..default()
+                        },
+                    ))
+                    .with_children(|button| {
+                        button.spawn(TextBundle::from_section("", text_style.clone()));
+                    });
+            }
+
+            panel.spawn((EditorStatusText, TextBundle::from_section("", text_style)));
+        });
+}
+
+pub fn toggle_position_editor(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    selected_piece: Res<SelectedPiece>,
+    move_history: Res<MoveHistory>,
+    mut position_editor: ResMut<PositionEditor>,
+    mut premoves: ResMut<Premoves>,
+    tile_legal_moves_q: Query<Entity, With<LegalMoves>>,
+) {
+    // The editor can only be opened while no piece is being carried and the live position is
+    // shown. It's closed with the "Play from here" button instead of this key, so the position is
+    // always checked before playing.
+    if keyboard.just_pressed(KeyCode::KeyE)
+        && !position_editor.is_active
+        && selected_piece.0.is_none()
+        && move_history.viewed_ply.is_none()
+    {
+        position_editor.is_active = true;
+        // Premoves were planned for the position before it was edited.
+        premoves.0.clear();
+
+        // The legal moves will be out of date once the position is edited, so remove them.
+        for tile_id in &tile_legal_moves_q {
+            commands.entity(tile_id).remove::<LegalMoves>();
+        }
+    }
+}
+
+pub fn edit_tile(
+    mut commands: Commands,
+    position_editor: Res<PositionEditor>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    tile_q: Query<Entity, With<MouseoverHighlight>>,
+) {
+    // Left-clicking places the palette piece (or removes the piece if the eraser is selected), and
+    // right-clicking always removes the piece.
+    let game_piece = if mouse.just_pressed(MouseButton::Left) {
+        position_editor.palette_piece
+    } else if mouse.just_pressed(MouseButton::Right) {
+        None
+    } else {
+        return;
+    };
+
+    // Get the current mouseover tile, or skip if the cursor is not over a tile.
+    if let Ok(tile_id) = tile_q.get_single() {
+        if let Some(game_piece) = game_piece {
+            commands.entity(tile_id).insert(game_piece);
+        } else {
+            commands.entity(tile_id).remove::<GamePiece>();
+        }
+    }
+}
+
+pub fn handle_editor_buttons(
+    mut position_editor: ResMut<PositionEditor>,
+    variant: Res<Variant>,
+    mut color_to_move: ResMut<ColorToMove>,
+    mut right_to_castle: ResMut<RightToCastle>,
+    mut must_recalculate_legal_moves: ResMut<MustRecalculateLegalMoves>,
+    mut game_tags: ResMut<GameTags>,
+    mut last_move: ResMut<LastMove>,
+    mut move_history: ResMut<MoveHistory>,
+    palette_button_q: Query<(&Interaction, &PaletteButton), Changed<Interaction>>,
+    editor_button_q: Query<(&Interaction, &EditorButton), Changed<Interaction>>,
+    mut status_text_q: Query<&mut Text, With<EditorStatusText>>,
+    tile_game_piece_q: Query<(&TilePos, &GamePiece)>,
+    tilemap_q: Query<&TileStorage>,
+) {
+    for (interaction, palette_button) in &palette_button_q {
+        if *interaction == Interaction::Pressed {
+            position_editor.palette_piece = palette_button.0;
+        }
+    }
+
+    for (interaction, editor_button) in &editor_button_q {
+        if *interaction == Interaction::Pressed {
+            match editor_button {
+                EditorButton::ColorToMove => color_to_move.switch(),
+                EditorButton::WhiteKingside => {
+                    right_to_castle.white_kingside = !right_to_castle.white_kingside
+                }
+                EditorButton::WhiteQueenside => {
+                    right_to_castle.white_queenside = !right_to_castle.white_queenside
+                }
+                EditorButton::BlackKingside => {
+                    right_to_castle.black_kingside = !right_to_castle.black_kingside
+                }
+                EditorButton::BlackQueenside => {
+                    right_to_castle.black_queenside = !right_to_castle.black_queenside
+                }
+                EditorButton::PlayFromHere => {
+                    let tilemap = tilemap_q.single();
+                    let pieces: Vec<(TilePos, GamePiece)> = tile_game_piece_q
+                        .iter()
+                        .map(|(tile_pos, game_piece)| (*tile_pos, *game_piece))
+                        .collect();
+                    let position = PositionSnapshot {
+                        pieces,
+                        color_to_move: color_to_move.0,
+                        right_to_castle: *right_to_castle,
+                    };
+                    let mut status_text = status_text_q.single_mut();
+
+                    match position.validate(&variant) {
+                        Ok(()) => {
+                            status_text.sections[0].value.clear();
+                            position_editor.is_active = false;
+                            *last_move = LastMove(None);
+
+                            // The game now starts from the edited position, which no longer has
+                            // any odds that were given.
+                            game_tags.remove("Odds");
+                            game_tags.set("SetUp", "1".to_string());
+                            game_tags.set(
+                                "FEN",
+                                write_fen(
+                                    &position.pieces,
+                                    &tilemap.size,
+                                    position.color_to_move,
+                                    &position.right_to_castle,
+                                    0,
+                                    1,
+                                ),
+                            );
+
+                            move_history.reset(position);
+
+                            *must_recalculate_legal_moves = MustRecalculateLegalMoves(true);
+                        }
+                        Err(error) => status_text.sections[0].value = error,
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn update_editor_panel(
+    position_editor: Res<PositionEditor>,
+    color_to_move: Res<ColorToMove>,
+    right_to_castle: Res<RightToCastle>,
+    mut panel_q: Query<&mut Visibility, With<EditorPanel>>,
+    mut palette_button_q: Query<(&PaletteButton, &mut BackgroundColor)>,
+    editor_button_q: Query<(&EditorButton, &Children)>,
+    mut text_q: Query<&mut Text>,
+) {
+    // Only update the panel when something it displays has changed, to avoid needlessly
+    // recalculating the layout of its text.
+    if !(position_editor.is_changed() || color_to_move.is_changed() || right_to_castle.is_changed())
+    {
+        return;
+    }
+
+    *panel_q.single_mut() = if position_editor.is_active {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+
+    for (palette_button, mut background_color) in &mut palette_button_q {
+        *background_color = if palette_button.0 == position_editor.palette_piece {
+            SELECTED_BUTTON_COLOR.into()
+        } else {
+            BUTTON_COLOR.into()
+        };
+    }
+
+    let yes_or_no = |has_right: bool| if has_right { "yes" } else { "no" };
+    for (editor_button, children) in &editor_button_q {
+        let label = match editor_button {
+            EditorButton::ColorToMove => format!("{} to move", color_to_move.0.get_name()),
+            EditorButton::WhiteKingside => format!(
+                "White can castle kingside: {}",
+                yes_or_no(right_to_castle.white_kingside)
+            ),
+            EditorButton::WhiteQueenside => format!(
+                "White can castle queenside: {}",
+                yes_or_no(right_to_castle.white_queenside)
+            ),
+            EditorButton::BlackKingside => format!(
+                "Black can castle kingside: {}",
+                yes_or_no(right_to_castle.black_kingside)
+            ),
+            EditorButton::BlackQueenside => format!(
+                "Black can castle queenside: {}",
+                yes_or_no(right_to_castle.black_queenside)
+            ),
+            EditorButton::PlayFromHere => "Play from here".to_string(),
+        };
+        for child in children {
+            if let Ok(mut text) = text_q.get_mut(*child) {
+                text.sections[0].value.clone_from(&label);
+            }
+        }
+    }
+}
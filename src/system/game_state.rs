@@ -0,0 +1,74 @@
+use crate::events::GameEnded;
+use crate::resources::{
+    AppState, CheckedKingTile, ColorToMove, GameOutcome, GameTags, LastMove, MoveHistory,
+    MustRecalculateLegalMoves, NetworkGame, PendingOffer, PositionEditor, Premoves, SelectedPiece,
+    SelectedPieceOriginalPosition,
+};
+use bevy::prelude::*;
+
+// Escape pauses the game, and resumes it again while it's paused.
+pub fn toggle_pause(
+    app_state: Res<State<AppState>>,
+    mut next_app_state: ResMut<NextState<AppState>>,
+) {
+    match app_state.get() {
+        AppState::Playing => next_app_state.set(AppState::Paused),
+        AppState::Paused => next_app_state.set(AppState::Playing),
+        _ => {}
+    }
+}
+
+// Records how the game ended in its Result and Termination tags, logs the finished game as PGN,
+// and moves on to the game over screen. A game joined over the network ends once the host says how
+// it ended, whatever was seen here.
+pub fn end_game(
+    mut game_ended_events: EventReader<GameEnded>,
+    network_game: Option<ResMut<NetworkGame>>,
+    mut game_outcome: ResMut<GameOutcome>,
+    mut game_tags: ResMut<GameTags>,
+    move_history: Res<MoveHistory>,
+    mut next_app_state: ResMut<NextState<AppState>>,
+) {
+    let game_ended =
+        match network_game.filter(|network_game| network_game.leaves_game_end_to_host()) {
+            Some(mut network_game) => {
+                game_ended_events.clear();
+                network_game.game_ended_by_host.take()
+            }
+            None => game_ended_events.read().next().copied(),
+        };
+    if let Some(game_ended) = game_ended {
+        *game_outcome = GameOutcome(Some(game_ended));
+        game_tags.set("Result", game_ended.result.to_pgn().to_string());
+        game_tags.set("Termination", game_ended.get_description());
+        let movetext = format!(
+            "{} {}",
+            move_history.write_movetext(),
+            game_ended.result.to_pgn()
+        );
+        info!(
+            "Game over:\n{}\n{}",
+            game_tags.to_pgn(),
+            movetext.trim_start()
+        );
+        next_app_state.set(AppState::GameOver);
+    }
+}
+
+// Puts the game's state back to how it was before the game was set up, so the next game starts
+// afresh. The game's entities are despawned by being scoped to the InGame state.
+pub fn teardown_game(mut commands: Commands, mut position_editor: ResMut<PositionEditor>) {
+    commands.insert_resource(SelectedPiece::default());
+    commands.insert_resource(SelectedPieceOriginalPosition::default());
+    commands.insert_resource(ColorToMove::default());
+    commands.insert_resource(MustRecalculateLegalMoves::default());
+    commands.insert_resource(GameTags::default());
+    commands.insert_resource(LastMove::default());
+    commands.insert_resource(CheckedKingTile::default());
+    commands.insert_resource(MoveHistory::default());
+    commands.insert_resource(Premoves::default());
+    commands.insert_resource(GameOutcome::default());
+    commands.insert_resource(PendingOffer::default());
+    // The editor's palette piece is kept for the next time it's opened.
+    position_editor.is_active = false;
+}
@@ -0,0 +1,85 @@
+use crate::components::GamePiece;
+use crate::events::{GameEndReason, GameEnded, KingInCheck, MoveMade};
+use crate::notation::write_san;
+use crate::resources::{
+    BoardAnnotations, Clocks, ColorToMove, MoveHistory, PositionSnapshot, RecordedMove,
+    RightToCastle, Variant,
+};
+use crate::system::update::find_all_legal_moves;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+// A run condition for systems that change the game, which can't run while an earlier position is
+// being viewed.
+pub fn is_live(move_history: Res<MoveHistory>) -> bool {
+    move_history.viewed_ply.is_none()
+}
+
+// Records each move along with the position after it. This must run after the legal moves are
+// recalculated, so the move can be marked as check or checkmate.
+pub fn record_move(
+    mut move_history: ResMut<MoveHistory>,
+    variant: Res<Variant>,
+    color_to_move: Res<ColorToMove>,
+    right_to_castle: Res<RightToCastle>,
+    clocks: Res<Clocks>,
+    mut move_made_events: EventReader<MoveMade>,
+    mut king_in_check_events: EventReader<KingInCheck>,
+    mut game_ended_events: EventReader<GameEnded>,
+    tile_game_piece_q: Query<(&TilePos, &GamePiece)>,
+    tilemap_q: Query<&TileStorage>,
+) {
+    let is_check = king_in_check_events.read().count() > 0;
+    let is_checkmate = game_ended_events
+        .read()
+        .any(|game_ended| game_ended.reason == GameEndReason::Checkmate);
+
+    for move_made in move_made_events.read() {
+        let tilemap = tilemap_q.single();
+        let previous_position = move_history.get_position(move_history.moves.len());
+
+        // Find the other pieces of the same kind that could also have moved to the destination.
+        let get_game_piece_at_position = |tile_pos: &TilePos| {
+            previous_position
+                .pieces
+                .iter()
+                .find_map(|(other_tile_pos, game_piece)| {
+                    (other_tile_pos == tile_pos).then_some(*game_piece)
+                })
+        };
+        let ambiguous_origins: Vec<TilePos> = find_all_legal_moves(
+            move_made.game_piece.color,
+            &variant,
+            &previous_position.right_to_castle,
+            &tilemap.size,
+            get_game_piece_at_position,
+        )
+        .into_iter()
+        .filter(|(tile_pos, legal_moves)| {
+            *tile_pos != move_made.from
+                && get_game_piece_at_position(tile_pos) == Some(move_made.game_piece)
+                && legal_moves
+                    .iter()
+                    .any(|legal_move| legal_move.destination == move_made.to)
+        })
+        .map(|(tile_pos, _)| tile_pos)
+        .collect();
+
+        let san = write_san(move_made, &ambiguous_origins, is_check, is_checkmate);
+        let position = PositionSnapshot {
+            pieces: tile_game_piece_q
+                .iter()
+                .map(|(tile_pos, game_piece)| (*tile_pos, *game_piece))
+                .collect(),
+            color_to_move: color_to_move.0,
+            right_to_castle: *right_to_castle,
+        };
+        move_history.moves.push(RecordedMove {
+            move_made: *move_made,
+            san,
+            position,
+            annotations: BoardAnnotations::default(),
+            clocks: Some(*clocks),
+        });
+    }
+}
@@ -1,19 +1,41 @@
-use crate::resources::CursorPos;
+use crate::resources::{CursorPos, LegalMoveHints};
 use bevy::prelude::*;
 
 pub fn update_cursor_pos(
-    camera_q: Query<(&GlobalTransform, &Camera)>,
+    camera_q: Query<(&GlobalTransform, &Camera, Ref<OrthographicProjection>)>,
     mut cursor_moved_events: EventReader<CursorMoved>,
+    mut window_cursor_pos: Local<Option<Vec2>>,
     mut cursor_pos: ResMut<CursorPos>,
 ) {
-    for cursor_moved in cursor_moved_events.read() {
-        // To get the mouse's world position, we have to transform its window position by
-        // any transforms on the camera. This is done by projecting the cursor position into
-        // camera space (world space).
-        for (cam_t, cam) in camera_q.iter() {
-            if let Some(pos) = cam.viewport_to_world_2d(cam_t, cursor_moved.position) {
-                *cursor_pos = CursorPos(pos);
-            }
+    // The world under the cursor also changes when the camera is zoomed, such as when the window is
+    // resized, so the cursor's world position is worked out again then even if it hasn't moved.
+    if let Some(cursor_moved) = cursor_moved_events.read().last() {
+        *window_cursor_pos = Some(cursor_moved.position);
+    } else if !camera_q
+        .iter()
+        .any(|(_, _, projection)| projection.is_changed())
+    {
+        return;
+    }
+    let Some(window_cursor_pos) = *window_cursor_pos else {
+        return;
+    };
+
+    // To get the mouse's world position, we have to transform its window position by
+    // any transforms on the camera. This is done by projecting the cursor position into
+    // camera space (world space).
+    for (cam_t, cam, _) in camera_q.iter() {
+        if let Some(pos) = cam.viewport_to_world_2d(cam_t, window_cursor_pos) {
+            *cursor_pos = CursorPos(pos);
         }
     }
 }
+
+pub fn toggle_legal_move_hints(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut legal_move_hints: ResMut<LegalMoveHints>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyH) {
+        legal_move_hints.is_enabled = !legal_move_hints.is_enabled;
+    }
+}
@@ -0,0 +1,551 @@
+use crate::components::{Color, MenuButton, OfferPanel, SettingText};
+use crate::notation::parse_placement;
+use crate::resources::{
+    AppState, BoardOrientation, GameOutcome, InGame, InputMode, LocalPlayer, NetworkGame,
+    NewGameSettings, Odds, Opponent, PendingOffer, ResumedGame, SaveFile, StartingPlacement,
+    ThemeCatalog, TimeControl, UserSettings, Variant, TIME_CONTROLS, VARIANTS,
+};
+use crate::system::editor::BUTTON_COLOR;
+use crate::system::save::{has_saved_game, load_saved_game};
+use bevy::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TITLE_FONT_SIZE: f32 = 48.0;
+const MENU_FONT_SIZE: f32 = 24.0;
+
+// The choices offered on the settings screen.
+const BOARD_SCALES: [f32; 5] = [0.5, 0.75, 1.0, 1.25, 1.5];
+const VOLUMES: [f32; 4] = [0.25, 0.5, 0.75, 1.0];
+const ANIMATION_SPEEDS: [(f32, &str); 4] =
+    [(0.0, "Off"), (0.1, "Fast"), (0.2, "Normal"), (0.4, "Slow")];
+
+// Spawns a column of menu items in the middle of the screen, which is despawned when leaving the
+// state.
+fn spawn_menu_screen(
+    commands: &mut Commands,
+    state: AppState,
+    background_color: Srgba,
+    spawn_children: impl FnOnce(&mut ChildBuilder),
+) {
+    commands
+        .spawn((
+            StateScoped(state),
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(10.0),
+                    ..default()
+                },
+                background_color: background_color.into(),
+                // Draw over the game's panels.
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+        ))
+        .with_children(spawn_children);
+}
+
+fn spawn_title(parent: &mut ChildBuilder, title: &str) {
+    parent.spawn(TextBundle::from_section(
+        title,
+        TextStyle {
+            font_size: TITLE_FONT_SIZE,
+            ..default()
+        },
+    ));
+}
+
+// Spawns a button with the label. Buttons for settings show the current choice instead, which is
+// kept up to date by update_setting_texts.
+fn spawn_menu_button(parent: &mut ChildBuilder, menu_button: MenuButton, label: &str) {
+    parent
+        .spawn((
+            menu_button,
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(320.0),
+                    justify_content: JustifyContent::Center,
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+        ))
+        .with_children(|button| {
+            let mut text = button.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font_size: MENU_FONT_SIZE,
+                    ..default()
+                },
+            ));
+            if !matches!(
+                menu_button,
+                MenuButton::ContinueGame
+                    | MenuButton::NewGame
+                    | MenuButton::Quit
+                    | MenuButton::Start
+                    | MenuButton::BackToMainMenu
+                    | MenuButton::Resume
+                    | MenuButton::Settings
+                    | MenuButton::Resign
+                    | MenuButton::OfferDraw
+                    | MenuButton::RequestTakeback
+                    | MenuButton::AcceptOffer
+                    | MenuButton::DeclineOffer
+            ) {
+                text.insert(SettingText(menu_button));
+            }
+        });
+}
+
+pub fn setup_main_menu(mut commands: Commands, save_file: Res<SaveFile>) {
+    let has_saved_game = has_saved_game(&save_file);
+    spawn_menu_screen(
+        &mut commands,
+        AppState::MainMenu,
+        Srgba::rgb(0.12, 0.1, 0.09),
+        |screen| {
+            spawn_title(screen, "Bevy Chess");
+            if has_saved_game {
+                spawn_menu_button(screen, MenuButton::ContinueGame, "Continue last game");
+            }
+            spawn_menu_button(screen, MenuButton::NewGame, "New game");
+            spawn_menu_button(screen, MenuButton::Settings, "Settings");
+            spawn_menu_button(screen, MenuButton::Quit, "Quit");
+        },
+    );
+}
+
+pub fn setup_new_game_screen(mut commands: Commands) {
+    spawn_menu_screen(
+        &mut commands,
+        AppState::NewGameSetup,
+        Srgba::rgb(0.12, 0.1, 0.09),
+        |screen| {
+            spawn_title(screen, "New game");
+            spawn_menu_button(screen, MenuButton::Opponent, "");
+            spawn_menu_button(screen, MenuButton::Variant, "");
+            spawn_menu_button(screen, MenuButton::TimeControl, "");
+            spawn_menu_button(screen, MenuButton::Color, "");
+            spawn_menu_button(screen, MenuButton::Start, "Start");
+            spawn_menu_button(screen, MenuButton::BackToMainMenu, "Back");
+        },
+    );
+}
+
+pub fn setup_settings_screen(mut commands: Commands) {
+    spawn_menu_screen(
+        &mut commands,
+        AppState::Settings,
+        Srgba::rgb(0.12, 0.1, 0.09),
+        |screen| {
+            spawn_title(screen, "Settings");
+            for menu_button in [
+                MenuButton::BoardScale,
+                MenuButton::Volume,
+                MenuButton::Mute,
+                MenuButton::InputMode,
+                MenuButton::LegalMoveHints,
+                MenuButton::TileNames,
+                MenuButton::AnimationSpeed,
+                MenuButton::DefaultTimeControl,
+                MenuButton::BoardTheme,
+                MenuButton::PieceSet,
+            ] {
+                spawn_menu_button(screen, menu_button, "");
+            }
+            spawn_menu_button(screen, MenuButton::BackToMainMenu, "Back");
+        },
+    );
+}
+
+// The pause menu, which also offers to resign, offer a draw or ask for a takeback, unless the game
+// is only being watched.
+pub fn setup_pause_menu(mut commands: Commands, network_game: Option<Res<NetworkGame>>) {
+    let is_spectating = network_game.is_some_and(|network_game| network_game.is_spectating);
+    spawn_menu_screen(
+        &mut commands,
+        AppState::Paused,
+        Srgba::new(0.0, 0.0, 0.0, 0.7),
+        |screen| {
+            spawn_title(screen, "Paused");
+            spawn_menu_button(screen, MenuButton::Resume, "Resume");
+            if !is_spectating {
+                spawn_menu_button(screen, MenuButton::Resign, "Resign");
+                spawn_menu_button(screen, MenuButton::OfferDraw, "Offer draw");
+                spawn_menu_button(screen, MenuButton::RequestTakeback, "Ask to take back");
+            }
+            spawn_menu_button(screen, MenuButton::NewGame, "New game");
+            spawn_menu_button(screen, MenuButton::BackToMainMenu, "Main menu");
+        },
+    );
+}
+
+// Shows how the game ended above the board, which stays visible so the game can be looked over.
+pub fn setup_game_over_panel(mut commands: Commands, game_outcome: Res<GameOutcome>) {
+    let title = game_outcome
+        .0
+        .map_or("Game over".to_string(), |game_ended| {
+            game_ended.get_description()
+        });
+
+    commands
+        .spawn((
+            StateScoped(AppState::GameOver),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(10.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+        ))
+        .with_children(|row| {
+            row.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                },
+                background_color: Srgba::new(0.1, 0.1, 0.1, 0.9).into(),
+                ..default()
+            })
+            .with_children(|panel| {
+                panel.spawn(TextBundle::from_section(
+                    title,
+                    TextStyle {
+                        font_size: MENU_FONT_SIZE,
+                        ..default()
+                    },
+                ));
+                spawn_menu_button(panel, MenuButton::NewGame, "New game");
+                spawn_menu_button(panel, MenuButton::BackToMainMenu, "Main menu");
+            });
+        });
+}
+
+// Shows the draw offer or takeback request waiting for an answer above the board, with buttons to
+// accept or decline it when it's for a side played here to answer.
+pub fn update_offer_panel(
+    mut commands: Commands,
+    pending_offer: Res<PendingOffer>,
+    local_player: Res<LocalPlayer>,
+    offer_panel_q: Query<Entity, With<OfferPanel>>,
+) {
+    for offer_panel in &offer_panel_q {
+        commands.entity(offer_panel).despawn_recursive();
+    }
+    let Some(offer) = pending_offer.0 else {
+        return;
+    };
+    let can_answer = local_player.0 != Some(offer.color);
+    let mut text = offer.get_description();
+    if !can_answer {
+        text.push_str(", waiting for an answer");
+    }
+
+    commands
+        .spawn((
+            OfferPanel,
+            StateScoped(InGame),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(10.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                z_index: ZIndex::Global(5),
+                ..default()
+            },
+        ))
+        .with_children(|row| {
+            row.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                },
+                background_color: Srgba::new(0.1, 0.1, 0.1, 0.9).into(),
+                ..default()
+            })
+            .with_children(|panel| {
+                panel.spawn(TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font_size: MENU_FONT_SIZE,
+                        ..default()
+                    },
+                ));
+                if can_answer {
+                    spawn_menu_button(panel, MenuButton::AcceptOffer, "Accept");
+                    spawn_menu_button(panel, MenuButton::DeclineOffer, "Decline");
+                }
+            });
+        });
+}
+
+fn get_on_off(is_on: bool) -> &'static str {
+    if is_on {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+fn get_time_control_name(time_control: Option<TimeControl>) -> String {
+    time_control.map_or("Untimed".to_string(), |time_control| {
+        time_control.get_name()
+    })
+}
+
+pub fn update_setting_texts(
+    new_game_settings: Res<NewGameSettings>,
+    variant: Res<Variant>,
+    user_settings: Res<UserSettings>,
+    theme_catalog: Res<ThemeCatalog>,
+    added_text_q: Query<(), Added<SettingText>>,
+    mut text_q: Query<(&mut Text, &SettingText)>,
+) {
+    if !new_game_settings.is_changed()
+        && !variant.is_changed()
+        && !user_settings.is_changed()
+        && !theme_catalog.is_changed()
+        && added_text_q.is_empty()
+    {
+        return;
+    }
+    for (mut text, setting_text) in &mut text_q {
+        text.sections[0].value = match setting_text.0 {
+            MenuButton::Opponent => {
+                format!("Opponent: {}", new_game_settings.opponent.get_name())
+            }
+            MenuButton::Variant => format!("Variant: {}", variant.get_name()),
+            MenuButton::TimeControl => format!(
+                "Time control: {}",
+                get_time_control_name(new_game_settings.time_control)
+            ),
+            MenuButton::Color => match new_game_settings.color {
+                Some(color) => format!("Play as: {}", color.get_name()),
+                None => "Play as: Random".to_string(),
+            },
+            MenuButton::BoardScale => {
+                format!("Board size: {:.0}%", user_settings.board_scale * 100.0)
+            }
+            MenuButton::Volume => format!("Volume: {:.0}%", user_settings.volume * 100.0),
+            MenuButton::Mute => format!("Sound: {}", get_on_off(!user_settings.is_muted)),
+            MenuButton::InputMode => {
+                format!("Moving pieces: {}", user_settings.input_mode.get_name())
+            }
+            MenuButton::LegalMoveHints => format!(
+                "Move hints: {}",
+                get_on_off(user_settings.show_legal_move_hints)
+            ),
+            MenuButton::TileNames => {
+                format!("Tile names: {}", get_on_off(user_settings.show_tile_names))
+            }
+            MenuButton::AnimationSpeed => {
+                let duration = user_settings.animation_duration;
+                match ANIMATION_SPEEDS
+                    .iter()
+                    .find(|(other, _)| *other == duration)
+                {
+                    Some((_, name)) => format!("Animations: {name}"),
+                    None => format!("Animations: {duration}s"),
+                }
+            }
+            MenuButton::DefaultTimeControl => format!(
+                "Default time control: {}",
+                get_time_control_name(user_settings.default_time_control)
+            ),
+            MenuButton::BoardTheme => {
+                let name = theme_catalog
+                    .board_themes
+                    .iter()
+                    .find(|board_theme| board_theme.id == user_settings.board_theme)
+                    .map_or(&user_settings.board_theme, |board_theme| &board_theme.name);
+                format!("Board theme: {name}")
+            }
+            MenuButton::PieceSet => {
+                let name = theme_catalog
+                    .piece_sets
+                    .iter()
+                    .find(|piece_set| piece_set.id == user_settings.piece_set)
+                    .map_or(&user_settings.piece_set, |piece_set| &piece_set.name);
+                format!("Pieces: {name}")
+            }
+            _ => continue,
+        };
+    }
+}
+
+pub fn handle_menu_buttons(
+    mut next_app_state: ResMut<NextState<AppState>>,
+    mut new_game_settings: ResMut<NewGameSettings>,
+    mut variant: ResMut<Variant>,
+    mut starting_placement: ResMut<StartingPlacement>,
+    mut odds: ResMut<Odds>,
+    mut local_player: ResMut<LocalPlayer>,
+    mut orientation: ResMut<BoardOrientation>,
+    mut resumed_game: ResMut<ResumedGame>,
+    save_file: Res<SaveFile>,
+    mut user_settings: ResMut<UserSettings>,
+    theme_catalog: Res<ThemeCatalog>,
+    mut app_exit_events: EventWriter<AppExit>,
+    menu_button_q: Query<(&Interaction, &MenuButton), Changed<Interaction>>,
+) {
+    for (interaction, menu_button) in &menu_button_q {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match menu_button {
+            MenuButton::ContinueGame => match load_saved_game(&save_file) {
+                Ok(saved_game) => {
+                    *variant = saved_game.variant;
+                    *starting_placement =
+                        StartingPlacement(variant.get_starting_placement().to_string());
+                    *new_game_settings = saved_game.new_game_settings;
+                    *local_player = LocalPlayer(saved_game.local_player);
+                    orientation.is_flipped = saved_game.is_board_flipped;
+                    orientation.auto_flip = saved_game.auto_flip;
+                    *resumed_game = ResumedGame(Some(saved_game));
+                    next_app_state.set(AppState::Playing);
+                }
+                Err(error) => warn!("Couldn't continue the saved game: {error}"),
+            },
+            MenuButton::NewGame => next_app_state.set(AppState::NewGameSetup),
+            MenuButton::Quit => {
+                app_exit_events.send(AppExit::Success);
+            }
+            MenuButton::Opponent => {
+                new_game_settings.opponent = match new_game_settings.opponent {
+                    Opponent::Local => Opponent::Remote,
+                    Opponent::Remote => Opponent::Local,
+                };
+            }
+            MenuButton::Variant => {
+                *variant = get_next_choice(&VARIANTS, &variant);
+                *starting_placement =
+                    StartingPlacement(variant.get_starting_placement().to_string());
+            }
+            MenuButton::TimeControl => {
+                new_game_settings.time_control =
+                    get_next_choice(&TIME_CONTROLS, &new_game_settings.time_control);
+            }
+            MenuButton::Color => {
+                new_game_settings.color = match new_game_settings.color {
+                    Some(Color::White) => Some(Color::Black),
+                    Some(Color::Black) => None,
+                    None => Some(Color::White),
+                };
+            }
+            MenuButton::Start => {
+                // Odds given on the command line can't be given in a variant without the piece.
+                if let Some((handicap, giver)) = odds.0 {
+                    let map_size = variant.get_map_size();
+                    if let Err(error) = parse_placement(&starting_placement.0, &map_size)
+                        .and_then(|mut pieces| handicap.remove_from(&mut pieces, giver, &map_size))
+                    {
+                        warn!("Playing without odds: {error}");
+                        *odds = Odds(None);
+                    }
+                }
+                let color = new_game_settings.color.unwrap_or_else(get_random_color);
+                *local_player = LocalPlayer(match new_game_settings.opponent {
+                    Opponent::Local => None,
+                    Opponent::Remote => Some(color),
+                });
+                orientation.is_flipped = color == Color::Black;
+                next_app_state.set(AppState::Playing);
+            }
+            MenuButton::BackToMainMenu => next_app_state.set(AppState::MainMenu),
+            MenuButton::Resume => next_app_state.set(AppState::Playing),
+            MenuButton::Settings => next_app_state.set(AppState::Settings),
+            MenuButton::BoardScale => {
+                user_settings.board_scale =
+                    get_next_choice(&BOARD_SCALES, &user_settings.board_scale);
+            }
+            MenuButton::Volume => {
+                user_settings.volume = get_next_choice(&VOLUMES, &user_settings.volume);
+            }
+            MenuButton::Mute => user_settings.is_muted = !user_settings.is_muted,
+            MenuButton::InputMode => {
+                user_settings.input_mode = match user_settings.input_mode {
+                    InputMode::Click => InputMode::DragAndDrop,
+                    InputMode::DragAndDrop => InputMode::Click,
+                };
+            }
+            MenuButton::LegalMoveHints => {
+                user_settings.show_legal_move_hints = !user_settings.show_legal_move_hints;
+            }
+            MenuButton::TileNames => {
+                user_settings.show_tile_names = !user_settings.show_tile_names;
+            }
+            MenuButton::AnimationSpeed => {
+                let durations = ANIMATION_SPEEDS.map(|(duration, _)| duration);
+                user_settings.animation_duration =
+                    get_next_choice(&durations, &user_settings.animation_duration);
+            }
+            MenuButton::DefaultTimeControl => {
+                user_settings.default_time_control =
+                    get_next_choice(&TIME_CONTROLS, &user_settings.default_time_control);
+            }
+            MenuButton::BoardTheme => {
+                let ids: Vec<String> = theme_catalog
+                    .board_themes
+                    .iter()
+                    .map(|board_theme| board_theme.id.clone())
+                    .collect();
+                user_settings.board_theme = get_next_choice(&ids, &user_settings.board_theme);
+            }
+            MenuButton::PieceSet => {
+                let ids: Vec<String> = theme_catalog
+                    .piece_sets
+                    .iter()
+                    .map(|piece_set| piece_set.id.clone())
+                    .collect();
+                user_settings.piece_set = get_next_choice(&ids, &user_settings.piece_set);
+            }
+            // Handled by handle_game_action_buttons.
+            MenuButton::Resign
+            | MenuButton::OfferDraw
+            | MenuButton::RequestTakeback
+            | MenuButton::AcceptOffer
+            | MenuButton::DeclineOffer => {}
+        }
+    }
+}
+
+// Returns the choice after the current one, going back to the first after the last. A current
+// choice that isn't one of them (such as one set in the settings file) moves to the first.
+fn get_next_choice<T: Clone + PartialEq>(choices: &[T], current: &T) -> T {
+    let index = choices.iter().position(|other| other == current);
+    choices[index.map_or(0, |index| (index + 1) % choices.len())].clone()
+}
+
+// Picks a color using the clock, which is random enough for deciding who plays white.
+fn get_random_color() -> Color {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.subsec_nanos());
+    if nanos.is_multiple_of(2) {
+        Color::White
+    } else {
+        Color::Black
+    }
+}
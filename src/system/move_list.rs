@@ -0,0 +1,188 @@
+use crate::components::{BackToLiveButton, Color, MoveButton, MoveListEntries};
+use crate::resources::{InGame, MoveHistory, SelectedPiece};
+use crate::system::editor::{BUTTON_COLOR, SELECTED_BUTTON_COLOR};
+use bevy::prelude::*;
+
+// Only the most recent moves up to the shown one fit in the panel.
+const MAX_MOVE_LIST_ROWS: usize = 20;
+
+pub fn setup_move_list_panel(mut commands: Commands) {
+    let text_style = TextStyle {
+        font_size: 20.0,
+        ..default()
+    };
+
+    commands
+        .spawn((
+            StateScoped(InGame),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    width: Val::Px(220.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                },
+                background_color: Srgba::new(0.1, 0.1, 0.1, 0.9).into(),
+                ..default()
+            },
+        ))
+        .with_children(|panel| {
+            panel.spawn(TextBundle::from_section("Moves", text_style.clone()));
+            panel.spawn((
+                MoveListEntries,
+                NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(2.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+            ));
+            panel
+                .spawn((
+                    BackToLiveButton,
+                    ButtonBundle {
+                        style: Style {
+                            display: Display::None,
+                            padding: UiRect::all(Val::Px(4.0)),
+                            ..default()
+                        },
+                        background_color: BUTTON_COLOR.into(),
+                        ..default()
+                    },
+                ))
+                .with_children(|button| {
+                    button.spawn(TextBundle::from_section("Back to live", text_style));
+                });
+        });
+}
+
+// Rebuilds the move list, numbering each white move and black reply on their own row and
+// highlighting the move whose position is shown.
+pub fn update_move_list(
+    mut commands: Commands,
+    move_history: Res<MoveHistory>,
+    entries_q: Query<Entity, With<MoveListEntries>>,
+    mut back_to_live_q: Query<&mut Style, With<BackToLiveButton>>,
+) {
+    if !move_history.is_changed() {
+        return;
+    }
+    let Some(starting_position) = &move_history.starting_position else {
+        return;
+    };
+
+    let text_style = TextStyle {
+        font_size: 18.0,
+        ..default()
+    };
+    let shown_ply = move_history.get_shown_ply();
+
+    // Moves are grouped in rows of a white move and black's reply. A game starting with black to
+    // move leaves the first row's white move empty.
+    let first_ply_offset = if starting_position.color_to_move == Color::Black {
+        1
+    } else {
+        0
+    };
+    let row_count = (move_history.moves.len() + first_ply_offset).div_ceil(2);
+    let shown_row = (shown_ply + first_ply_offset).saturating_sub(1) / 2;
+    let first_row = (shown_row + 1).saturating_sub(MAX_MOVE_LIST_ROWS);
+    let last_row = (first_row + MAX_MOVE_LIST_ROWS).min(row_count);
+
+    let entries_id = entries_q.single();
+    commands.entity(entries_id).despawn_descendants();
+    commands.entity(entries_id).with_children(|entries| {
+        for row in first_row..last_row {
+            entries
+                .spawn(NodeBundle {
+                    style: Style {
+                        column_gap: Val::Px(6.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|row_node| {
+                    row_node.spawn(TextBundle::from_section(
+                        format!("{}.", row + 1),
+                        text_style.clone(),
+                    ));
+                    for column in 0..2 {
+                        // The first move is ply 1, so ply 0 is the empty white move before black's
+                        // first move.
+                        let ply = row * 2 + column + 1 - first_ply_offset;
+                        if ply == 0 {
+                            row_node.spawn(TextBundle::from_section("...", text_style.clone()));
+                            continue;
+                        }
+                        let Some(recorded_move) = move_history.moves.get(ply - 1) else {
+                            continue;
+                        };
+                        let color = if ply == shown_ply {
+                            SELECTED_BUTTON_COLOR
+                        } else {
+                            BUTTON_COLOR
+                        };
+                        row_node
+                            .spawn((
+                                MoveButton(ply),
+                                ButtonBundle {
+                                    style: Style {
+                                        padding: UiRect::horizontal(Val::Px(4.0)),
+                                        ..default()
+                                    },
+                                    background_color: color.into(),
+                                    ..default()
+                                },
+                            ))
+                            .with_children(|button| {
+                                button.spawn(TextBundle::from_section(
+                                    recorded_move.san.clone(),
+                                    text_style.clone(),
+                                ));
+                            });
+                    }
+                });
+        }
+    });
+
+    let mut back_to_live_style = back_to_live_q.single_mut();
+    back_to_live_style.display = if move_history.viewed_ply.is_some() {
+        Display::Flex
+    } else {
+        Display::None
+    };
+}
+
+pub fn handle_move_list_buttons(
+    mut move_history: ResMut<MoveHistory>,
+    selected_piece: Res<SelectedPiece>,
+    move_button_q: Query<(&Interaction, &MoveButton), Changed<Interaction>>,
+    back_to_live_q: Query<&Interaction, (Changed<Interaction>, With<BackToLiveButton>)>,
+) {
+    // Earlier positions can't be viewed while a piece is carried.
+    if selected_piece.0.is_some() {
+        return;
+    }
+
+    for (interaction, move_button) in &move_button_q {
+        if *interaction == Interaction::Pressed {
+            // Viewing the latest move is the same as viewing the live position.
+            move_history.viewed_ply = if move_button.0 == move_history.moves.len() {
+                None
+            } else {
+                Some(move_button.0)
+            };
+        }
+    }
+    for interaction in &back_to_live_q {
+        if *interaction == Interaction::Pressed {
+            move_history.viewed_ply = None;
+        }
+    }
+}
@@ -0,0 +1,646 @@
+use crate::components::{Color, GamePiece, LegalMoves};
+use crate::events::{
+    GameAction, GameActionRequested, GameEnded, MoveMade, MoveRequested, OfferAnswered,
+    UndoRequested,
+};
+use crate::network::{NetworkMessage, NetworkPeer, PeerRole, PeerState, PROTOCOL_VERSION};
+use crate::notation::{parse_fen, write_coordinate_move, write_fen};
+use crate::resources::{
+    AppState, BoardAnnotations, BoardOrientation, Clocks, LocalPlayer, MoveHistory,
+    MustRecalculateLegalMoves, NetworkGame, NewGameSettings, Opponent, PendingOffer, ResumedGame,
+    StartingPlacement, Variant,
+};
+use crate::save::SavedGame;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+// Sends a message to the other player, hanging up if it can't be sent.
+fn send_message(network_game: &mut NetworkGame, message: &NetworkMessage) {
+    let Some(peer) = &mut network_game.peer else {
+        return;
+    };
+    if let Err(error) = peer.send(message) {
+        warn!("Lost the connection to the other player: {error}");
+        network_game.peer = None;
+    }
+}
+
+// Sends a message to every spectator, dropping any that can't be reached.
+fn send_to_spectators(network_game: &mut NetworkGame, message: &NetworkMessage) {
+    network_game.spectators.retain_mut(|spectator| {
+        spectator
+            .send(message)
+            .inspect_err(|error| warn!("Lost the connection to a spectator: {error}"))
+            .is_ok()
+    });
+}
+
+fn set_peer_state(network_game: &mut NetworkGame, state: PeerState) {
+    if let Some(peer) = &mut network_game.peer {
+        peer.state = state;
+    }
+}
+
+// Runs while only watching a hosted game, when neither color can be moved here.
+pub fn is_spectating(network_game: Option<Res<NetworkGame>>) -> bool {
+    network_game.is_some_and(|network_game| network_game.is_spectating)
+}
+
+// Runs while the other side is played somewhere else, such as on another computer, or while
+// watching a game. Changes made to the board here wouldn't reach the other side, so the games would
+// no longer match.
+pub fn has_remote_opponent(
+    network_game: Option<Res<NetworkGame>>,
+    new_game_settings: Res<NewGameSettings>,
+) -> bool {
+    new_game_settings.opponent == Opponent::Remote
+        || network_game.is_some_and(|network_game| network_game.peer.is_some())
+}
+
+// Lets the other player and spectators connect once a game against a remote opponent has been
+// started here. They wait in arrivals until they say which they are.
+pub fn accept_network_connections(
+    mut network_game: ResMut<NetworkGame>,
+    local_player: Res<LocalPlayer>,
+    move_history: Res<MoveHistory>,
+) {
+    if local_player.0.is_none() || move_history.starting_position.is_none() {
+        return;
+    }
+    let network_game = &mut *network_game;
+    let Some(listener) = &network_game.listener else {
+        return;
+    };
+    loop {
+        match listener.accept() {
+            Ok((stream, address)) => match NetworkPeer::new(stream) {
+                Ok(peer) => {
+                    info!("{address} connected");
+                    network_game.arrivals.push(peer);
+                }
+                Err(error) => warn!("Couldn't accept {address}: {error}"),
+            },
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => return,
+            Err(error) => {
+                warn!("Couldn't accept a connection: {error}");
+                return;
+            }
+        }
+    }
+}
+
+// Reads the messages sent by the other player and any new arrivals, to be handled in order by
+// greet_network_peers, handle_network_messages and play_network_moves. Spectators are only read to
+// notice when they leave, as nothing they send is acted on. Anything that couldn't be sent to the
+// other player or spectators yet is sent first.
+pub fn receive_network_messages(mut network_game: ResMut<NetworkGame>) {
+    if let Some(peer) = &mut network_game.peer {
+        match peer
+            .receive()
+            .and_then(|warnings| peer.flush().map(|()| warnings))
+        {
+            Ok(warnings) => {
+                for warning in warnings {
+                    warn!("Skipping a message from the other player: {warning}");
+                }
+            }
+            // The last messages sent before the connection was closed are handled before letting
+            // it go, such as a reason for being turned away.
+            Err(_) if !peer.incoming.is_empty() => {}
+            Err(error) => {
+                warn!("Lost the connection to the other player: {error}");
+                network_game.peer = None;
+            }
+        }
+    }
+    network_game
+        .arrivals
+        .retain_mut(|arrival| match arrival.receive() {
+            Ok(warnings) => {
+                for warning in warnings {
+                    warn!("Skipping a message from a new connection: {warning}");
+                }
+                true
+            }
+            Err(error) => {
+                warn!("Lost a new connection: {error}");
+                false
+            }
+        });
+    network_game.spectators.retain_mut(|spectator| {
+        match spectator.flush().and_then(|()| spectator.receive()) {
+            Ok(_) => {
+                spectator.incoming.clear();
+                true
+            }
+            Err(error) => {
+                info!("A spectator left: {error}");
+                false
+            }
+        }
+    });
+}
+
+// Answers the Hello of each new connection to a hosted game, welcoming it as the other player if
+// that seat is free, or as a spectator. Connections speaking another version of the protocol, or
+// wanting to play once the game already has two players, are turned away.
+pub fn greet_network_peers(
+    mut network_game: ResMut<NetworkGame>,
+    local_player: Res<LocalPlayer>,
+    variant: Res<Variant>,
+    clocks: Res<Clocks>,
+    move_history: Res<MoveHistory>,
+) {
+    let Some(local_color) = local_player.0 else {
+        return;
+    };
+    for mut arrival in std::mem::take(&mut network_game.arrivals) {
+        let Some(message) = arrival.incoming.pop_front() else {
+            network_game.arrivals.push(arrival);
+            continue;
+        };
+        let color = match message {
+            NetworkMessage::Hello { version, .. } if version != PROTOCOL_VERSION => {
+                turn_away(
+                    arrival,
+                    format!(
+                        "This game speaks version {PROTOCOL_VERSION} of the protocol, not {version}"
+                    ),
+                );
+                continue;
+            }
+            NetworkMessage::Hello {
+                role: PeerRole::Player,
+                ..
+            } if network_game.peer.is_some() => {
+                turn_away(arrival, "This game already has two players".to_string());
+                continue;
+            }
+            NetworkMessage::Hello {
+                role: PeerRole::Player,
+                ..
+            } => Some(local_color.opposite()),
+            NetworkMessage::Hello {
+                role: PeerRole::Spectator,
+                ..
+            } => None,
+            message => {
+                warn!("Hanging up on a new connection that sent {message:?} first");
+                continue;
+            }
+        };
+        let is_paused = network_game.is_paused;
+        if let Err(error) = welcome_network_peer(
+            &mut arrival,
+            color,
+            &variant,
+            &clocks,
+            &move_history,
+            is_paused,
+        ) {
+            warn!("Lost a new connection: {error}");
+            continue;
+        }
+        match color {
+            Some(color) => {
+                info!("The other player joined, playing {}", color.get_name());
+                network_game.peer = Some(arrival);
+            }
+            None => {
+                info!("A spectator joined");
+                network_game.spectators.push(arrival);
+            }
+        }
+    }
+}
+
+// Tells a new connection why it can't join, then hangs up.
+fn turn_away(mut arrival: NetworkPeer, reason: String) {
+    warn!("Turning away a new connection: {reason}");
+    // It's hung up on either way, so there's nothing to do if the reason can't be sent.
+    let _ = arrival.send(&NetworkMessage::Reject(reason));
+}
+
+// Whether the message is a resignation, an offer or an answer to one, or for spectators a takeback
+// or the end of the game. These are handled by handle_network_actions.
+fn is_game_action(message: &NetworkMessage) -> bool {
+    matches!(
+        message,
+        NetworkMessage::Resign
+            | NetworkMessage::OfferDraw
+            | NetworkMessage::AcceptDraw
+            | NetworkMessage::DeclineDraw
+            | NetworkMessage::RequestTakeback
+            | NetworkMessage::AcceptTakeback
+            | NetworkMessage::DeclineTakeback
+            | NetworkMessage::Undo { .. }
+            | NetworkMessage::GameOver(_)
+    )
+}
+
+// Handles every message from the other player (or the host, when spectating) other than moves and
+// game actions, which are handled in turn by play_network_moves and handle_network_actions.
+// Messages are handled in the order they were sent, so this stops at the next move or game action
+// until it's been handled.
+pub fn handle_network_messages(
+    mut network_game: ResMut<NetworkGame>,
+    mut local_player: ResMut<LocalPlayer>,
+    mut variant: ResMut<Variant>,
+    mut starting_placement: ResMut<StartingPlacement>,
+    mut new_game_settings: ResMut<NewGameSettings>,
+    mut orientation: ResMut<BoardOrientation>,
+    mut resumed_game: ResMut<ResumedGame>,
+    mut clocks: ResMut<Clocks>,
+    app_state: Res<State<AppState>>,
+    mut next_app_state: ResMut<NextState<AppState>>,
+) {
+    loop {
+        let is_hosting = network_game.is_hosting();
+        let is_paused = *app_state.get() == AppState::Paused && network_game.is_paused;
+        let Some(peer) = &mut network_game.peer else {
+            return;
+        };
+        if is_paused && peer.incoming.front().is_some_and(is_game_action) {
+            // The other side resumed the game to make it.
+            network_game.is_paused = false;
+            next_app_state.set(AppState::Playing);
+            return;
+        }
+        if peer.incoming.front().is_none_or(|message| {
+            matches!(message, NetworkMessage::Move { .. }) || is_game_action(message)
+        }) {
+            return;
+        }
+        let Some(message) = peer.incoming.pop_front() else {
+            return;
+        };
+
+        match message {
+            NetworkMessage::Welcome {
+                color,
+                variant: welcome_variant,
+                time_control,
+                starting_fen,
+            } if !is_hosting => {
+                let starting_position = parse_fen(&starting_fen, &welcome_variant.get_map_size())
+                    .and_then(|starting_position| {
+                        starting_position.validate(&welcome_variant)?;
+                        Ok(starting_position)
+                    });
+                let starting_position = match starting_position {
+                    Ok(starting_position) => starting_position,
+                    Err(error) => {
+                        warn!("Couldn't join the game: {error}");
+                        network_game.peer = None;
+                        continue;
+                    }
+                };
+                match color {
+                    Some(color) => info!("Joined the game, playing {}", color.get_name()),
+                    None => info!("Watching the game"),
+                }
+                set_peer_state(&mut network_game, PeerState::Syncing);
+
+                // The game is set up like a saved game being continued, from its starting position.
+                // The moves played so far follow.
+                *new_game_settings = NewGameSettings {
+                    opponent: Opponent::Remote,
+                    time_control,
+                    color,
+                };
+                *variant = welcome_variant;
+                *starting_placement =
+                    StartingPlacement(variant.get_starting_placement().to_string());
+                *local_player = LocalPlayer(color);
+                orientation.is_flipped = color == Some(Color::Black);
+                *resumed_game = ResumedGame(Some(SavedGame {
+                    variant: welcome_variant,
+                    game_tags: Vec::new(),
+                    starting_position,
+                    starting_annotations: BoardAnnotations::default(),
+                    moves: Vec::new(),
+                    clocks: Clocks::new(time_control),
+                    new_game_settings: *new_game_settings,
+                    local_player: color,
+                    is_board_flipped: orientation.is_flipped,
+                    auto_flip: false,
+                }));
+                next_app_state.set(AppState::Playing);
+                // The rest waits until the game is set up, so it isn't replaced by the new game.
+                return;
+            }
+            NetworkMessage::Reject(reason) if !is_hosting => {
+                warn!("Couldn't join the game: {reason}");
+                network_game.peer = None;
+            }
+            NetworkMessage::Synced => set_peer_state(&mut network_game, PeerState::Playing),
+            NetworkMessage::Clock {
+                white_seconds,
+                black_seconds,
+            } => {
+                clocks.white_seconds = white_seconds;
+                clocks.black_seconds = black_seconds;
+            }
+            // Each waits for the game to be paused or resumed before the next message is handled.
+            NetworkMessage::Pause if *app_state.get() == AppState::Playing => {
+                network_game.is_paused = true;
+                next_app_state.set(AppState::Paused);
+                return;
+            }
+            NetworkMessage::Resume if *app_state.get() == AppState::Paused => {
+                network_game.is_paused = false;
+                next_app_state.set(AppState::Playing);
+                return;
+            }
+            // Both sides paused or resumed at once, or the game has ended.
+            NetworkMessage::Pause | NetworkMessage::Resume => {}
+            message => warn!("Ignoring unexpected message from the other player: {message:?}"),
+        }
+    }
+}
+
+// Handles the other player's resignations, offers and answers to offers, the host's word on how
+// the game ended, and for spectators the host's takebacks, in the order they were sent. They wait
+// while the game is paused here, to be acted on once it's resumed.
+pub fn handle_network_actions(
+    mut network_game: ResMut<NetworkGame>,
+    local_player: Res<LocalPlayer>,
+    mut game_action_events: EventWriter<GameActionRequested>,
+    mut offer_answered_events: EventWriter<OfferAnswered>,
+    mut undo_requested_events: EventWriter<UndoRequested>,
+) {
+    let network_game = &mut *network_game;
+    let is_spectating = network_game.is_spectating;
+    let is_hosting = network_game.is_hosting();
+    let Some(peer) = &mut network_game.peer else {
+        return;
+    };
+    while peer.incoming.front().is_some_and(is_game_action) {
+        let Some(message) = peer.incoming.pop_front() else {
+            return;
+        };
+        let other_color = local_player.0.map(|color| color.opposite());
+        let (action, answer) = match message {
+            NetworkMessage::Resign => (Some(GameAction::Resign), None),
+            NetworkMessage::OfferDraw => (Some(GameAction::OfferDraw), None),
+            NetworkMessage::RequestTakeback => (Some(GameAction::RequestTakeback), None),
+            NetworkMessage::AcceptDraw => (None, Some((GameAction::OfferDraw, true))),
+            NetworkMessage::DeclineDraw => (None, Some((GameAction::OfferDraw, false))),
+            NetworkMessage::AcceptTakeback => (None, Some((GameAction::RequestTakeback, true))),
+            NetworkMessage::DeclineTakeback => (None, Some((GameAction::RequestTakeback, false))),
+            NetworkMessage::Undo { plies } if is_spectating => {
+                undo_requested_events.send(UndoRequested { plies });
+                continue;
+            }
+            NetworkMessage::GameOver(game_ended) if !is_hosting => {
+                network_game.game_ended_by_host = Some(game_ended);
+                continue;
+            }
+            message => {
+                warn!("Ignoring unexpected message from the other player: {message:?}");
+                continue;
+            }
+        };
+        let Some(color) = other_color else {
+            warn!("Ignoring {message:?}, as neither side is played here");
+            continue;
+        };
+        if let Some(action) = action {
+            game_action_events.send(GameActionRequested { color, action });
+        }
+        if let Some((action, is_accepted)) = answer {
+            offer_answered_events.send(OfferAnswered {
+                color,
+                action,
+                is_accepted,
+            });
+        }
+    }
+}
+
+// Sends the game to a player or spectator who just joined: their color (none for a spectator) and
+// the starting position, then every move played so far and the clocks, and whether it's paused.
+fn welcome_network_peer(
+    peer: &mut NetworkPeer,
+    color: Option<Color>,
+    variant: &Variant,
+    clocks: &Clocks,
+    move_history: &MoveHistory,
+    is_paused: bool,
+) -> Result<(), String> {
+    let Some(starting_position) = &move_history.starting_position else {
+        return Err("The game hasn't started".to_string());
+    };
+    let starting_fen = write_fen(
+        &starting_position.pieces,
+        &variant.get_map_size(),
+        starting_position.color_to_move,
+        &starting_position.right_to_castle,
+        0,
+        1,
+    );
+
+    let mut messages = vec![NetworkMessage::Welcome {
+        color,
+        variant: *variant,
+        time_control: clocks.time_control,
+        starting_fen,
+    }];
+    for recorded_move in &move_history.moves {
+        messages.push(NetworkMessage::Move {
+            from: recorded_move.move_made.from,
+            to: recorded_move.move_made.to,
+        });
+    }
+    messages.push(NetworkMessage::Clock {
+        white_seconds: clocks.white_seconds,
+        black_seconds: clocks.black_seconds,
+    });
+    messages.push(NetworkMessage::Synced);
+    if is_paused {
+        messages.push(NetworkMessage::Pause);
+    }
+    for message in &messages {
+        peer.send(message)?;
+    }
+    peer.state = PeerState::Playing;
+    Ok(())
+}
+
+// Plays the next move sent by the other player, once the legal moves are known for the position
+// it's played in. The move must be one of the legal moves found by recalculate_legal_moves, and
+// once the games are in step it must be the other player's own piece (either player's when
+// spectating). Otherwise the games no longer match, so the connection is closed.
+pub fn play_network_moves(
+    mut network_game: ResMut<NetworkGame>,
+    local_player: Res<LocalPlayer>,
+    must_recalculate_legal_moves: Res<MustRecalculateLegalMoves>,
+    tile_q: Query<(&TilePos, Option<&GamePiece>, Option<&LegalMoves>)>,
+    mut move_requested_events: EventWriter<MoveRequested>,
+) {
+    if must_recalculate_legal_moves.0 {
+        return;
+    }
+    let Some(peer) = &mut network_game.peer else {
+        return;
+    };
+    let Some(&NetworkMessage::Move { from, to }) = peer.incoming.front() else {
+        return;
+    };
+    peer.incoming.pop_front();
+
+    let moved_piece = tile_q
+        .iter()
+        .find(|(tile_pos, _, _)| **tile_pos == from)
+        .and_then(|(_, game_piece, legal_moves)| Some((*game_piece?, legal_moves?)));
+    let is_legal = moved_piece.is_some_and(|(game_piece, legal_moves)| {
+        (peer.state != PeerState::Playing || Some(game_piece.color) != local_player.0)
+            && legal_moves
+                .0
+                .iter()
+                .any(|legal_move| legal_move.destination == to)
+    });
+    if !is_legal {
+        warn!(
+            "Hanging up, as the other player sent an illegal move: {}",
+            write_coordinate_move(&from, &to)
+        );
+        network_game.peer = None;
+        return;
+    }
+    move_requested_events.send(MoveRequested { from, to });
+}
+
+// Sends the moves made here to the other player, along with the clocks after them. Spectators are
+// sent every move, whichever player made it.
+pub fn send_network_moves(
+    mut network_game: ResMut<NetworkGame>,
+    local_player: Res<LocalPlayer>,
+    clocks: Res<Clocks>,
+    mut move_made_events: EventReader<MoveMade>,
+) {
+    for move_made in move_made_events.read() {
+        let messages = [
+            NetworkMessage::Move {
+                from: move_made.from,
+                to: move_made.to,
+            },
+            NetworkMessage::Clock {
+                white_seconds: clocks.white_seconds,
+                black_seconds: clocks.black_seconds,
+            },
+        ];
+        let is_playing = network_game
+            .peer
+            .as_ref()
+            .is_some_and(|peer| peer.state == PeerState::Playing);
+        for message in &messages {
+            if is_playing && Some(move_made.game_piece.color) == local_player.0 {
+                send_message(&mut network_game, message);
+            }
+            send_to_spectators(&mut network_game, message);
+        }
+    }
+}
+
+// Sends the resignations, offers and answers to offers made here to the other player, and the
+// takebacks to spectators. When hosting, how the game ended is sent to both. An offer is only sent
+// once it's waiting for an answer, as one made while another is waiting isn't.
+pub fn send_network_actions(
+    mut network_game: ResMut<NetworkGame>,
+    local_player: Res<LocalPlayer>,
+    pending_offer: Res<PendingOffer>,
+    mut game_action_events: EventReader<GameActionRequested>,
+    mut offer_answered_events: EventReader<OfferAnswered>,
+    mut undo_requested_events: EventReader<UndoRequested>,
+    mut game_ended_events: EventReader<GameEnded>,
+) {
+    let Some(local_color) = local_player.0 else {
+        return;
+    };
+    let mut messages = Vec::new();
+    if game_action_events.read().any(|game_action| {
+        game_action.color == local_color && game_action.action == GameAction::Resign
+    }) {
+        messages.push(NetworkMessage::Resign);
+    }
+    if let Some(offer) = pending_offer.0.filter(|offer| offer.color == local_color) {
+        if pending_offer.is_changed() {
+            messages.push(match offer.action {
+                GameAction::OfferDraw => NetworkMessage::OfferDraw,
+                _ => NetworkMessage::RequestTakeback,
+            });
+        }
+    }
+    for offer_answered in offer_answered_events.read() {
+        if offer_answered.color != local_color {
+            continue;
+        }
+        messages.push(match (offer_answered.action, offer_answered.is_accepted) {
+            (GameAction::OfferDraw, true) => NetworkMessage::AcceptDraw,
+            (GameAction::OfferDraw, false) => NetworkMessage::DeclineDraw,
+            (_, true) => NetworkMessage::AcceptTakeback,
+            (_, false) => NetworkMessage::DeclineTakeback,
+        });
+    }
+    for message in &messages {
+        send_message(&mut network_game, message);
+    }
+
+    for undo_requested in undo_requested_events.read() {
+        send_to_spectators(
+            &mut network_game,
+            &NetworkMessage::Undo {
+                plies: undo_requested.plies,
+            },
+        );
+    }
+    for game_ended in game_ended_events.read() {
+        if network_game.is_hosting() {
+            let message = NetworkMessage::GameOver(*game_ended);
+            send_message(&mut network_game, &message);
+            send_to_spectators(&mut network_game, &message);
+        }
+    }
+}
+
+// Tells the other player and spectators when the game is paused or resumed here, so the clocks stop
+// on every side. A pause or resume that came from the other player is only passed on to spectators,
+// and one made while spectating isn't sent at all.
+pub fn send_network_pauses(
+    app_state: Res<State<AppState>>,
+    mut network_game: ResMut<NetworkGame>,
+    mut was_paused: Local<bool>,
+) {
+    let is_paused = *app_state.get() == AppState::Paused;
+    if is_paused == *was_paused {
+        return;
+    }
+    *was_paused = is_paused;
+    if !is_paused && *app_state.get() != AppState::Playing {
+        // The game was left or ended while paused.
+        network_game.is_paused = false;
+        return;
+    }
+    let message = match is_paused {
+        true => NetworkMessage::Pause,
+        false => NetworkMessage::Resume,
+    };
+    if is_paused != network_game.is_paused && !network_game.is_spectating {
+        send_message(&mut network_game, &message);
+    }
+    network_game.is_paused = is_paused;
+    send_to_spectators(&mut network_game, &message);
+}
+
+// Hangs up on everyone when leaving the game. A host keeps listening, so the next game can be
+// joined and watched too.
+pub fn leave_network_game(mut network_game: ResMut<NetworkGame>) {
+    if network_game.peer.take().is_some() {
+        info!("Left the network game");
+    }
+    network_game.arrivals.clear();
+    network_game.spectators.clear();
+    network_game.is_spectating = false;
+    network_game.game_ended_by_host = None;
+}
@@ -0,0 +1,133 @@
+use crate::components::{Color, CursorDisplay};
+use crate::resources::{BoardOrientation, BoardScale, ColorToMove, Variant};
+use crate::{MAP_TYPE, SCALE, SCALED_GRID_SIZE};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_ecs_tilemap::prelude::*;
+use std::f32::consts::PI;
+
+// The width kept clear on each side of the board for the panels beside it (the move list on the
+// left, and the clocks and captured pieces on the right). Both sides are kept clear so the board
+// stays in the middle of the window.
+const SIDE_PANEL_WIDTH: f32 = 300.0;
+
+// Returns how much the board must be zoomed to fit in a window of the given size between the side
+// panels, along with the coordinate labels a tile's width beyond each of its edges. When the window
+// is too narrow for the panels, the board is given at least half of its width and goes under them.
+pub fn get_fitting_zoom(window_size: Vec2, map_size: &TilemapSize) -> f32 {
+    let board_size = Vec2::new(
+        (map_size.x + 2) as f32 * SCALED_GRID_SIZE.x,
+        (map_size.y + 2) as f32 * SCALED_GRID_SIZE.y,
+    );
+    let available_size = Vec2::new(
+        (window_size.x - 2.0 * SIDE_PANEL_WIDTH).max(window_size.x / 2.0),
+        window_size.y,
+    );
+    (available_size / board_size).min_element()
+}
+
+// Zooms the camera so the board fits the window, and then by the board's chosen scale. Only the
+// world is zoomed, so the menus and panels stay the same size. Without a window (such as when
+// running headlessly) the board is only zoomed by its scale.
+pub fn fit_board_to_window(
+    board_scale: Res<BoardScale>,
+    variant: Res<Variant>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    mut projection_q: Query<&mut OrthographicProjection, With<Camera2d>>,
+) {
+    let fitting_zoom = window_q.get_single().map_or(1.0, |window| {
+        get_fitting_zoom(window.size(), &variant.get_map_size())
+    });
+    for mut projection in &mut projection_q {
+        projection.scale = 1.0 / (fitting_zoom * board_scale.0);
+    }
+}
+
+// Returns the position of the bottom-left tile's center relative to the center of the screen, such
+// that the board is centered on the screen.
+pub fn get_board_offset(map_size: &TilemapSize) -> Vec2 {
+    get_tilemap_center_transform(map_size, &SCALED_GRID_SIZE, &MAP_TYPE, 0.0)
+        .translation
+        .truncate()
+}
+
+pub fn get_tilemap_transform(map_size: &TilemapSize, orientation: &BoardOrientation) -> Transform {
+    let tilemap_transform =
+        get_tilemap_center_transform(map_size, &SCALED_GRID_SIZE, &MAP_TYPE, 0.0)
+            * Transform::from_scale(Vec3::splat(SCALE));
+    if orientation.is_flipped {
+        // The board is centered on the screen, so rotating it half a turn around the center of the
+        // screen mirrors it in both directions.
+        Transform::from_rotation(Quat::from_rotation_z(PI)) * tilemap_transform
+    } else {
+        tilemap_transform
+    }
+}
+
+// Returns the world position of the center of the tile, taking into account whether the board is
+// flipped.
+pub fn get_tile_center(
+    tile_pos: &TilePos,
+    map_size: &TilemapSize,
+    orientation: &BoardOrientation,
+) -> Vec2 {
+    orientation
+        .get_displayed_tile_pos(tile_pos, map_size)
+        .center_in_world(&SCALED_GRID_SIZE, &MAP_TYPE)
+        + get_board_offset(map_size)
+}
+
+// Returns the tile at the world position, taking into account whether the board is flipped, or None
+// if the position isn't over the board.
+pub fn get_tile_at_world_pos(
+    world_pos: Vec2,
+    map_size: &TilemapSize,
+    orientation: &BoardOrientation,
+) -> Option<TilePos> {
+    TilePos::from_world_pos(
+        &(world_pos - get_board_offset(map_size)),
+        map_size,
+        &SCALED_GRID_SIZE,
+        &MAP_TYPE,
+    )
+    .map(|displayed_tile_pos| orientation.get_displayed_tile_pos(&displayed_tile_pos, map_size))
+}
+
+pub fn flip_board(keyboard: Res<ButtonInput<KeyCode>>, mut orientation: ResMut<BoardOrientation>) {
+    // F flips the board, and A toggles automatically flipping the board to face the side to move.
+    if keyboard.just_pressed(KeyCode::KeyF) {
+        orientation.is_flipped = !orientation.is_flipped;
+    }
+    if keyboard.just_pressed(KeyCode::KeyA) {
+        orientation.auto_flip = !orientation.auto_flip;
+    }
+}
+
+pub fn auto_flip_board(color_to_move: Res<ColorToMove>, mut orientation: ResMut<BoardOrientation>) {
+    if orientation.auto_flip && (color_to_move.is_changed() || orientation.is_changed()) {
+        let is_flipped = color_to_move.0 == Color::Black;
+        // Avoid triggering change detection when the orientation is already correct.
+        if orientation.is_flipped != is_flipped {
+            orientation.is_flipped = is_flipped;
+        }
+    }
+}
+
+pub fn apply_board_orientation(
+    orientation: Res<BoardOrientation>,
+    mut tilemap_q: Query<(&mut Transform, &TilemapSize)>,
+    mut tile_q: Query<(&mut Transform, &TilePos), (Without<TilemapSize>, Without<CursorDisplay>)>,
+) {
+    if orientation.is_changed() {
+        for (mut tilemap_transform, map_size) in &mut tilemap_q {
+            *tilemap_transform = get_tilemap_transform(map_size, &orientation);
+
+            // Move the piece sprites on each tile to where the tile is now displayed.
+            for (mut transform, tile_pos) in &mut tile_q {
+                let tile_center = get_tile_center(tile_pos, map_size, &orientation);
+                transform.translation.x = tile_center.x;
+                transform.translation.y = tile_center.y;
+            }
+        }
+    }
+}
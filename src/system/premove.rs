@@ -0,0 +1,193 @@
+use crate::components::{Color, GamePiece, LegalMove, LegalMoves, MouseoverHighlight};
+use crate::events::MoveRequested;
+use crate::resources::{
+    ColorToMove, LocalPlayer, Premove, Premoves, RightToCastle, SelectedPiece,
+    SelectedPieceOriginalPosition, Variant,
+};
+use crate::system::update::find_all_pseudo_legal_moves;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+// A run condition for premoving, which is only done while the side played somewhere else is
+// thinking.
+pub fn is_opponents_turn(local_player: Res<LocalPlayer>, color_to_move: Res<ColorToMove>) -> bool {
+    local_player
+        .0
+        .is_some_and(|local_color| local_color != color_to_move.0)
+}
+
+// Finds where the piece on the given tile could be premoved to, once the earlier premoves have been
+// made. It doesn't matter whether the move would leave the king under attack, as the opponent's
+// move might change that.
+pub fn find_premove_destinations<F: Fn(&TilePos) -> Option<GamePiece>>(
+    premoves: &Premoves,
+    color: Color,
+    from: &TilePos,
+    variant: &Variant,
+    right_to_castle: &RightToCastle,
+    map_size: &TilemapSize,
+    get_game_piece_at_position: F,
+) -> Vec<LegalMove> {
+    let (pieces, right_to_castle) =
+        premoves.get_position_after(right_to_castle, map_size, get_game_piece_at_position);
+    find_all_pseudo_legal_moves(color, variant, &right_to_castle, map_size, |tile_pos| {
+        pieces.get(tile_pos).copied()
+    })
+    .into_iter()
+    .find(|(tile_pos, _)| tile_pos == from)
+    .map(|(_, legal_moves)| legal_moves)
+    .unwrap_or_default()
+}
+
+// Picks up one of the local player's pieces during the opponent's turn. Pieces are picked up from
+// where they'll be after the earlier premoves.
+pub fn pick_up_premove_piece(
+    local_player: Res<LocalPlayer>,
+    premoves: Res<Premoves>,
+    variant: Res<Variant>,
+    right_to_castle: Res<RightToCastle>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut selected_piece_original_position: ResMut<SelectedPieceOriginalPosition>,
+    mut mouse: ResMut<ButtonInput<MouseButton>>,
+    tile_q: Query<&TilePos, With<MouseoverHighlight>>,
+    tile_game_piece_q: Query<&GamePiece>,
+    tilemap_q: Query<&TileStorage>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) || selected_piece.0.is_some() {
+        return;
+    }
+    let (Some(local_color), Ok(tile_pos)) = (local_player.0, tile_q.get_single()) else {
+        return;
+    };
+
+    let tilemap = tilemap_q.single();
+    let get_game_piece_at_position = |tile_pos: &TilePos| {
+        let tile_id = tilemap.get(tile_pos).expect("Tile should exist in tilemap");
+        tile_game_piece_q.get(tile_id).ok().copied()
+    };
+    let (pieces, _) =
+        premoves.get_position_after(&right_to_castle, &tilemap.size, get_game_piece_at_position);
+    let Some(game_piece) = pieces.get(tile_pos).copied() else {
+        return;
+    };
+    if game_piece.color != local_color
+        || find_premove_destinations(
+            &premoves,
+            local_color,
+            tile_pos,
+            &variant,
+            &right_to_castle,
+            &tilemap.size,
+            get_game_piece_at_position,
+        )
+        .is_empty()
+    {
+        return;
+    }
+
+    *selected_piece = SelectedPiece(Some(game_piece));
+    *selected_piece_original_position = SelectedPieceOriginalPosition(Some(*tile_pos));
+    mouse.clear_just_pressed(MouseButton::Left);
+}
+
+// Queues a premove of the carried piece to the tile under the cursor, if the piece could move
+// there. Otherwise the piece is kept on the cursor.
+pub fn queue_premove(
+    local_player: Res<LocalPlayer>,
+    variant: Res<Variant>,
+    right_to_castle: Res<RightToCastle>,
+    mut premoves: ResMut<Premoves>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut selected_piece_original_position: ResMut<SelectedPieceOriginalPosition>,
+    tile_q: Query<&TilePos, With<MouseoverHighlight>>,
+    tile_game_piece_q: Query<&GamePiece>,
+    tilemap_q: Query<&TileStorage>,
+) {
+    let (Some(local_color), Some(original_tile_pos), Ok(tile_pos)) = (
+        local_player.0,
+        selected_piece_original_position.0,
+        tile_q.get_single(),
+    ) else {
+        return;
+    };
+
+    let tilemap = tilemap_q.single();
+    let get_game_piece_at_position = |tile_pos: &TilePos| {
+        let tile_id = tilemap.get(tile_pos).expect("Tile should exist in tilemap");
+        tile_game_piece_q.get(tile_id).ok().copied()
+    };
+    let Some(legal_move) = find_premove_destinations(
+        &premoves,
+        local_color,
+        &original_tile_pos,
+        &variant,
+        &right_to_castle,
+        &tilemap.size,
+        get_game_piece_at_position,
+    )
+    .into_iter()
+    .find(|legal_move| legal_move.destination == *tile_pos) else {
+        return;
+    };
+
+    premoves.0.push(Premove {
+        from: original_tile_pos,
+        to: *tile_pos,
+        is_castling: legal_move.is_castling,
+    });
+
+    *selected_piece = SelectedPiece(None);
+    *selected_piece_original_position = SelectedPieceOriginalPosition(None);
+}
+
+// Cancels every premove, along with a piece being carried to premove it.
+pub fn cancel_premoves(
+    mut premoves: ResMut<Premoves>,
+    local_player: Res<LocalPlayer>,
+    color_to_move: Res<ColorToMove>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut selected_piece_original_position: ResMut<SelectedPieceOriginalPosition>,
+) {
+    premoves.0.clear();
+    if is_opponents_turn(local_player, color_to_move) && selected_piece.0.is_some() {
+        *selected_piece = SelectedPiece(None);
+        *selected_piece_original_position = SelectedPieceOriginalPosition(None);
+    }
+}
+
+// Makes the next premove once it's the local player's turn. If it isn't legal in the position the
+// opponent left, every premove is cancelled, as the later ones were planned around it. This must
+// run after the legal moves are recalculated.
+pub fn play_premove(
+    local_player: Res<LocalPlayer>,
+    color_to_move: Res<ColorToMove>,
+    mut premoves: ResMut<Premoves>,
+    mut move_requested_events: EventWriter<MoveRequested>,
+    tile_legal_moves_q: Query<&LegalMoves>,
+    tilemap_q: Query<&TileStorage>,
+) {
+    if local_player.0 != Some(color_to_move.0) || premoves.0.is_empty() {
+        return;
+    }
+
+    let premove = premoves.0.remove(0);
+    let tilemap = tilemap_q.single();
+    let is_legal_move = tilemap
+        .get(&premove.from)
+        .and_then(|tile_id| tile_legal_moves_q.get(tile_id).ok())
+        .is_some_and(|legal_moves| {
+            legal_moves
+                .0
+                .iter()
+                .any(|legal_move| legal_move.destination == premove.to)
+        });
+    if is_legal_move {
+        move_requested_events.send(MoveRequested {
+            from: premove.from,
+            to: premove.to,
+        });
+    } else {
+        info!("Cancelling premoves, as {premove:?} is no longer legal");
+        premoves.0.clear();
+    }
+}
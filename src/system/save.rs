@@ -0,0 +1,121 @@
+use crate::components::GamePiece;
+use crate::resources::{
+    BoardOrientation, Clocks, ColorToMove, GameOutcome, GameTags, LastMove, LocalPlayer,
+    MoveHistory, NewGameSettings, ResumedGame, RightToCastle, SaveFile, Variant,
+};
+use crate::save::{parse_saved_game, write_saved_game, SavedGame};
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use std::io::ErrorKind;
+
+// Whether there's a saved game to continue.
+pub fn has_saved_game(save_file: &SaveFile) -> bool {
+    save_file.0.as_ref().is_some_and(|path| path.exists())
+}
+
+// Reads the saved game from the save file.
+pub fn load_saved_game(save_file: &SaveFile) -> Result<SavedGame, String> {
+    let path = save_file.0.as_ref().ok_or("Saving games is turned off")?;
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| format!("Couldn't read {}: {error}", path.display()))?;
+    parse_saved_game(&text)
+}
+
+// Saves the game on the board, so it can be continued from the main menu after leaving it or
+// closing the window. The save is deleted once the game is over, as there's nothing left to
+// continue.
+pub fn save_game(
+    save_file: Res<SaveFile>,
+    variant: Res<Variant>,
+    game_tags: Res<GameTags>,
+    move_history: Res<MoveHistory>,
+    clocks: Res<Clocks>,
+    new_game_settings: Res<NewGameSettings>,
+    local_player: Res<LocalPlayer>,
+    orientation: Res<BoardOrientation>,
+    game_outcome: Res<GameOutcome>,
+) {
+    let Some(path) = &save_file.0 else {
+        return;
+    };
+    if game_outcome.0.is_some() {
+        match std::fs::remove_file(path) {
+            Ok(()) => info!("Deleted the saved game, as the game is over"),
+            Err(error) if error.kind() == ErrorKind::NotFound => {}
+            Err(error) => warn!("Couldn't delete {}: {error}", path.display()),
+        }
+        return;
+    }
+    let Some(starting_position) = move_history.starting_position.clone() else {
+        return;
+    };
+
+    let saved_game = SavedGame {
+        variant: *variant,
+        game_tags: game_tags.0.clone(),
+        starting_position,
+        starting_annotations: move_history.starting_annotations.clone(),
+        moves: move_history.moves.clone(),
+        clocks: *clocks,
+        new_game_settings: *new_game_settings,
+        local_player: local_player.0,
+        is_board_flipped: orientation.is_flipped,
+        auto_flip: orientation.auto_flip,
+    };
+    match std::fs::write(path, write_saved_game(&saved_game)) {
+        Ok(()) => info!("Saved the game to {}", path.display()),
+        Err(error) => warn!("Couldn't save the game to {}: {error}", path.display()),
+    }
+}
+
+// Sets up the saved game being continued in place of the new game's starting position. This must
+// run after the pieces and clocks are set up for the new game.
+pub fn resume_saved_game(
+    mut commands: Commands,
+    mut resumed_game: ResMut<ResumedGame>,
+    mut color_to_move: ResMut<ColorToMove>,
+    mut right_to_castle: ResMut<RightToCastle>,
+    mut game_tags: ResMut<GameTags>,
+    mut move_history: ResMut<MoveHistory>,
+    mut clocks: ResMut<Clocks>,
+    mut last_move: ResMut<LastMove>,
+    tile_game_piece_q: Query<Entity, With<GamePiece>>,
+    tilemap_q: Query<&TileStorage>,
+) {
+    let Some(saved_game) = resumed_game.0.take() else {
+        return;
+    };
+    let tilemap = tilemap_q.single();
+
+    let position = saved_game.get_current_position();
+    for tile_id in &tile_game_piece_q {
+        commands.entity(tile_id).remove::<GamePiece>();
+    }
+    for (tile_pos, game_piece) in &position.pieces {
+        let tile_id = tilemap
+            .get(tile_pos)
+            .expect("Saved piece should be placed on a tile that exists on the board");
+        commands.entity(tile_id).insert(*game_piece);
+    }
+    *color_to_move = ColorToMove(position.color_to_move);
+    *right_to_castle = position.right_to_castle;
+
+    *last_move = LastMove(
+        saved_game
+            .moves
+            .last()
+            .map(|recorded_move| (recorded_move.move_made.from, recorded_move.move_made.to)),
+    );
+    *game_tags = GameTags(saved_game.game_tags);
+    *clocks = saved_game.clocks;
+    *move_history = MoveHistory {
+        starting_position: Some(saved_game.starting_position),
+        starting_annotations: saved_game.starting_annotations,
+        moves: saved_game.moves,
+        viewed_ply: None,
+    };
+    info!(
+        "Continuing the saved game after {} moves",
+        move_history.moves.len()
+    );
+}
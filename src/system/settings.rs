@@ -0,0 +1,229 @@
+use crate::resources::{
+    BoardScale, BoardTheme, CoordinateLabels, InputMode, LegalMoveHints, MoveAnimation,
+    NewGameSettings, PieceSet, SettingsFile, SoundSettings, ThemeCatalog, UserSettings,
+};
+use crate::settings::{parse_settings, write_settings};
+use crate::system::theme::get_theme_catalog;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use std::path::Path;
+use std::time::SystemTime;
+
+// The resources that use the player's settings. Each belongs to the plugin that uses it, so a
+// resource is missing when its plugin is disabled, and its settings are then just kept in the file.
+#[derive(SystemParam)]
+pub struct SettingsResources<'w> {
+    board_scale: Option<ResMut<'w, BoardScale>>,
+    sound_settings: Option<ResMut<'w, SoundSettings>>,
+    input_mode: Option<ResMut<'w, InputMode>>,
+    legal_move_hints: Option<ResMut<'w, LegalMoveHints>>,
+    coordinate_labels: Option<ResMut<'w, CoordinateLabels>>,
+    move_animation: Option<ResMut<'w, MoveAnimation>>,
+    new_game_settings: Option<ResMut<'w, NewGameSettings>>,
+    board_theme: Option<ResMut<'w, BoardTheme>>,
+    piece_set: Option<ResMut<'w, PieceSet>>,
+    theme_catalog: Res<'w, ThemeCatalog>,
+}
+
+impl SettingsResources<'_> {
+    // Returns the given settings updated with those in the resources.
+    pub fn get_settings(&self, settings: &UserSettings) -> UserSettings {
+        let mut settings = settings.clone();
+        if let Some(board_scale) = &self.board_scale {
+            settings.board_scale = board_scale.0;
+        }
+        if let Some(sound_settings) = &self.sound_settings {
+            settings.volume = sound_settings.volume;
+            settings.is_muted = sound_settings.is_muted;
+        }
+        if let Some(input_mode) = &self.input_mode {
+            settings.input_mode = **input_mode;
+        }
+        if let Some(legal_move_hints) = &self.legal_move_hints {
+            settings.show_legal_move_hints = legal_move_hints.is_enabled;
+        }
+        if let Some(coordinate_labels) = &self.coordinate_labels {
+            settings.show_tile_names = coordinate_labels.show_in_tiles;
+        }
+        if let Some(move_animation) = &self.move_animation {
+            settings.animation_duration = move_animation.duration;
+        }
+        if let Some(new_game_settings) = &self.new_game_settings {
+            settings.default_time_control = new_game_settings.time_control;
+        }
+        if let Some(board_theme) = &self.board_theme {
+            settings.board_theme.clone_from(&board_theme.id);
+        }
+        if let Some(piece_set) = &self.piece_set {
+            settings.piece_set.clone_from(&piece_set.id);
+        }
+        settings
+    }
+
+    // Puts the settings into the resources using them. Resources that already match are left
+    // alone, so they aren't marked as changed. A board theme or piece set that isn't in the assets
+    // folder is skipped, keeping the current one.
+    pub fn apply(&mut self, settings: &UserSettings) {
+        if let Some(board_scale) = &mut self.board_scale {
+            board_scale.set_if_neq(BoardScale(settings.board_scale));
+        }
+        if let Some(sound_settings) = &mut self.sound_settings {
+            sound_settings.set_if_neq(SoundSettings {
+                volume: settings.volume,
+                is_muted: settings.is_muted,
+            });
+        }
+        if let Some(input_mode) = &mut self.input_mode {
+            input_mode.set_if_neq(settings.input_mode);
+        }
+        if let Some(legal_move_hints) = &mut self.legal_move_hints {
+            legal_move_hints.set_if_neq(LegalMoveHints {
+                is_enabled: settings.show_legal_move_hints,
+            });
+        }
+        if let Some(coordinate_labels) = &mut self.coordinate_labels {
+            coordinate_labels.set_if_neq(CoordinateLabels {
+                show_in_tiles: settings.show_tile_names,
+            });
+        }
+        if let Some(move_animation) = &mut self.move_animation {
+            move_animation.set_if_neq(MoveAnimation {
+                duration: settings.animation_duration,
+            });
+        }
+        if let Some(new_game_settings) = &mut self.new_game_settings {
+            if new_game_settings.time_control != settings.default_time_control {
+                new_game_settings.time_control = settings.default_time_control;
+            }
+        }
+        if let Some(board_theme) = &mut self.board_theme {
+            if board_theme.id != settings.board_theme {
+                match self
+                    .theme_catalog
+                    .board_themes
+                    .iter()
+                    .find(|catalog_theme| catalog_theme.id == settings.board_theme)
+                {
+                    Some(catalog_theme) => **board_theme = catalog_theme.clone(),
+                    None => warn!("There's no board theme named \"{}\"", settings.board_theme),
+                }
+            }
+        }
+        if let Some(piece_set) = &mut self.piece_set {
+            if piece_set.id != settings.piece_set {
+                match self
+                    .theme_catalog
+                    .piece_sets
+                    .iter()
+                    .find(|catalog_set| catalog_set.id == settings.piece_set)
+                {
+                    Some(catalog_set) => **piece_set = catalog_set.clone(),
+                    None => warn!("There's no piece set named \"{}\"", settings.piece_set),
+                }
+            }
+        }
+    }
+}
+
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn read_settings(path: &Path) -> Result<UserSettings, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| format!("Couldn't read {}: {error}", path.display()))?;
+    let (settings, unknown_names) = parse_settings(&text)
+        .map_err(|error| format!("Couldn't read {}: {error}", path.display()))?;
+    for name in unknown_names {
+        warn!("Ignoring unknown setting \"{name}\" in {}", path.display());
+    }
+    Ok(settings)
+}
+
+// Reads the settings file, if there is one. Otherwise the settings start as they are, including any
+// resources inserted before the game's plugins were added.
+pub fn load_settings(
+    mut commands: Commands,
+    mut settings_file: ResMut<SettingsFile>,
+    settings_resources: SettingsResources,
+) {
+    let mut settings = settings_resources.get_settings(&UserSettings::default());
+    if let Some(path) = settings_file.path.clone().filter(|path| path.exists()) {
+        match read_settings(&path) {
+            Ok(loaded_settings) => {
+                info!("Loaded settings from {}", path.display());
+                settings = loaded_settings;
+            }
+            Err(error) => warn!("Using the default settings: {error}"),
+        }
+        settings_file.modified = get_modified_time(&path);
+    }
+    commands.insert_resource(settings);
+}
+
+// Reloads the settings file when it's been changed by something other than the game, such as by
+// editing it by hand. The file is checked every second. The assets folder is looked through again
+// too, in case the file names a board theme or piece set that's just been added.
+pub fn reload_settings(
+    time: Res<Time>,
+    mut settings_file: ResMut<SettingsFile>,
+    mut user_settings: ResMut<UserSettings>,
+    mut theme_catalog: ResMut<ThemeCatalog>,
+) {
+    if !settings_file.check_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Some(path) = settings_file.path.clone() else {
+        return;
+    };
+    let modified = get_modified_time(&path);
+    if modified.is_none() || modified == settings_file.modified {
+        return;
+    }
+    settings_file.modified = modified;
+    match read_settings(&path) {
+        Ok(settings) => {
+            info!("Reloaded settings from {}", path.display());
+            *theme_catalog = get_theme_catalog();
+            user_settings.set_if_neq(settings);
+        }
+        Err(error) => warn!("Keeping the current settings: {error}"),
+    }
+}
+
+pub fn apply_settings(user_settings: Res<UserSettings>, mut settings_resources: SettingsResources) {
+    settings_resources.apply(&user_settings);
+}
+
+// Keeps the settings up to date with changes made while playing, such as turning hints off with H.
+pub fn record_settings(
+    settings_resources: SettingsResources,
+    mut user_settings: ResMut<UserSettings>,
+) {
+    let settings = settings_resources.get_settings(&user_settings);
+    user_settings.set_if_neq(settings);
+}
+
+// Writes the settings file whenever the settings change. The file is left alone when it already
+// holds the same settings, to keep any comments added to it by hand.
+pub fn save_settings(user_settings: Res<UserSettings>, mut settings_file: ResMut<SettingsFile>) {
+    let Some(path) = settings_file.path.clone() else {
+        return;
+    };
+    let saved_settings = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|text| parse_settings(&text).ok());
+    if saved_settings.is_some_and(|(settings, _)| settings == *user_settings) {
+        return;
+    }
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| std::fs::write(&path, write_settings(&user_settings)));
+    match result {
+        Ok(()) => settings_file.modified = get_modified_time(&path),
+        Err(error) => warn!("Couldn't save settings to {}: {error}", path.display()),
+    }
+}
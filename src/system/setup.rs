@@ -1,31 +1,37 @@
-use crate::components::{Color, CursorDisplay, GamePiece, Piece};
-use crate::resources::CursorPos;
-use crate::{GRID_SIZE, MAP_SIZE, MAP_TYPE, SCALE, SCALED_GRID_SIZE, TILE_SIZE};
+use crate::components::CursorDisplay;
+use crate::notation::{parse_placement, write_fen, STANDARD_STARTING_FEN};
+use crate::resources::{
+    BoardOrientation, ColorToMove, CursorPos, GameTags, Handicap, InGame, MoveHistory, Odds,
+    PositionSnapshot, ResumedGame, RightToCastle, StartingPlacement, Variant,
+};
+use crate::system::orientation::{get_tile_center, get_tilemap_transform};
+use crate::{GRID_SIZE, MAP_TYPE, SCALE, TILE_SIZE};
 use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
 
-pub fn setup_board(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn setup_camera(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
+}
 
-    let texture_handle: Handle<Image> = asset_server.load("tiles.png");
-    let tilemap_entity = commands.spawn_empty().id();
-    let mut tile_storage = TileStorage::empty(MAP_SIZE);
-    let tilemap_transform =
-        get_tilemap_center_transform(&MAP_SIZE, &SCALED_GRID_SIZE, &MAP_TYPE, 0.0)
-            * Transform::from_scale(Vec3::splat(SCALE));
-    // Get the offset of the board from the center
-    let tilemat_offset = Vec2 {
-        x: tilemap_transform.translation.x,
-        y: tilemap_transform.translation.y,
-    };
+// Spawns the board's tiles. Their texture is left to the renderer, which gives them the board
+// theme's tiles.
+pub fn setup_board(
+    mut commands: Commands,
+    variant: Res<Variant>,
+    orientation: Res<BoardOrientation>,
+) {
+    let map_size = variant.get_map_size();
+    let tilemap_entity = commands.spawn(StateScoped(InGame)).id();
+    let mut tile_storage = TileStorage::empty(map_size);
+    let tilemap_transform = get_tilemap_transform(&map_size, &orientation);
 
-    for x in 0..MAP_SIZE.x {
-        for y in 0..MAP_SIZE.y {
+    for x in 0..map_size.x {
+        for y in 0..map_size.y {
             let tile_pos = TilePos { x, y };
-            let tile_center =
-                tile_pos.center_in_world(&SCALED_GRID_SIZE, &MAP_TYPE) + tilemat_offset;
+            let tile_center = get_tile_center(&tile_pos, &map_size, &orientation);
             let tile_entity = commands
                 .spawn((
+                    StateScoped(InGame),
                     TileBundle {
                         position: tile_pos,
                         // Create a checkerboard pattern by selecting the light or dark tile depending
@@ -56,9 +62,8 @@ pub fn setup_board(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.entity(tilemap_entity).insert(TilemapBundle {
         grid_size: GRID_SIZE,
         map_type: MAP_TYPE,
-        size: MAP_SIZE,
+        size: map_size,
         storage: tile_storage,
-        texture: TilemapTexture::Single(texture_handle),
         tile_size: TILE_SIZE,
         transform: tilemap_transform,
         ..Default::default()
@@ -68,6 +73,7 @@ pub fn setup_board(mut commands: Commands, asset_server: Res<AssetServer>) {
 pub fn setup_cursor(mut commands: Commands, cursor_pos: Res<CursorPos>) {
     commands.spawn((
         CursorDisplay,
+        StateScoped(InGame),
         SpriteBundle {
             transform: Transform {
                 translation: Vec3 {
@@ -84,166 +90,75 @@ pub fn setup_cursor(mut commands: Commands, cursor_pos: Res<CursorPos>) {
     ));
 }
 
-fn insert_piece(
-    game_piece: GamePiece,
-    commands: &mut Commands,
-    tile_id: Entity,
-    handle: &mut Handle<Image>,
-    visibility: &mut Visibility,
-    asset_server: &Res<AssetServer>,
-) {
-    // Must call to_string() to create an owned copy of the string, as the &str lifetime doesn't
-    // last long enough.
-    let asset_path = game_piece.get_asset_path().to_string();
-    commands.entity(tile_id).insert(GamePiece {
-        piece: game_piece.piece,
-        color: game_piece.color,
-    });
-    *handle = asset_server.load(asset_path);
-    *visibility = Visibility::Visible;
-}
-
 pub fn setup_pieces(
     mut commands: Commands,
-    mut tile_q: Query<(Entity, &TilePos, &mut Handle<Image>, &mut Visibility)>,
-    asset_server: Res<AssetServer>,
+    variant: Res<Variant>,
+    starting_placement: Res<StartingPlacement>,
+    odds: Res<Odds>,
+    resumed_game: Res<ResumedGame>,
+    mut color_to_move: ResMut<ColorToMove>,
+    mut right_to_castle: ResMut<RightToCastle>,
+    mut game_tags: ResMut<GameTags>,
+    mut move_history: ResMut<MoveHistory>,
+    tilemap_q: Query<&TileStorage>,
 ) {
-    // Place the starting pieces on the board.
-    for (tile_id, tile_pos, mut handle, mut visibility) in &mut tile_q {
-        match tile_pos {
-            TilePos { x: 0 | 7, y: 0 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Rook,
-                    color: Color::White,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 1 | 6, y: 0 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Knight,
-                    color: Color::White,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 2 | 5, y: 0 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Bishop,
-                    color: Color::White,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 3, y: 0 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Queen,
-                    color: Color::White,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 4, y: 0 } => insert_piece(
-                GamePiece {
-                    piece: Piece::King,
-                    color: Color::White,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 0..8, y: 1 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Pawn,
-                    color: Color::White,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 0..8, y: 6 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Pawn,
-                    color: Color::Black,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 0 | 7, y: 7 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Rook,
-                    color: Color::Black,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 1 | 6, y: 7 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Knight,
-                    color: Color::Black,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 2 | 5, y: 7 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Bishop,
-                    color: Color::Black,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 3, y: 7 } => insert_piece(
-                GamePiece {
-                    piece: Piece::Queen,
-                    color: Color::Black,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            TilePos { x: 4, y: 7 } => insert_piece(
-                GamePiece {
-                    piece: Piece::King,
-                    color: Color::Black,
-                },
-                &mut commands,
-                tile_id,
-                &mut handle,
-                &mut visibility,
-                &asset_server,
-            ),
-            _ => (),
+    let tilemap = tilemap_q.single();
+    let mut starting_pieces = parse_placement(&starting_placement.0, &tilemap.size)
+        .unwrap_or_else(|error| panic!("Invalid starting placement: {error}"));
+
+    // Remove the pieces given as odds. With pawn and move odds, the side receiving odds also moves
+    // first. A saved game being continued already had any odds given when it started.
+    let mut given_odds = None;
+    if let Some((handicap, giver)) = odds.0.filter(|_| resumed_game.0.is_none()) {
+        match handicap.remove_from(&mut starting_pieces, giver, &tilemap.size) {
+            Ok(()) => {
+                given_odds = odds.0;
+                if handicap == Handicap::PawnAndMove {
+                    *color_to_move = ColorToMove(giver.opposite());
+                }
+            }
+            Err(error) => warn!("Playing without odds: {error}"),
         }
     }
+
+    // Place the starting pieces on the board.
+    for (tile_pos, game_piece) in &starting_pieces {
+        let tile_id = tilemap
+            .get(tile_pos)
+            .expect("Starting piece should be placed on a tile that exists on the board");
+        commands.entity(tile_id).insert(*game_piece);
+    }
+
+    // Castling rights are worked out after odds are given, so a rook given as odds can't castle.
+    *right_to_castle = RightToCastle::from_starting_pieces(&starting_pieces, &tilemap.size);
+
+    move_history.reset(PositionSnapshot {
+        pieces: starting_pieces.clone(),
+        color_to_move: color_to_move.0,
+        right_to_castle: *right_to_castle,
+    });
+
+    // Record how the game was set up.
+    if *variant != Variant::Standard {
+        game_tags.set("Variant", variant.get_name().to_string());
+    }
+    if let Some((handicap, giver)) = given_odds {
+        game_tags.set(
+            "Odds",
+            format!("{} gives {} odds", giver.get_name(), handicap.get_name()),
+        );
+    }
+    let starting_fen = write_fen(
+        &starting_pieces,
+        &tilemap.size,
+        color_to_move.0,
+        &right_to_castle,
+        0,
+        1,
+    );
+    if starting_fen != STANDARD_STARTING_FEN {
+        game_tags.set("SetUp", "1".to_string());
+        game_tags.set("FEN", starting_fen);
+    }
+    info!("Starting game:\n{}", game_tags.to_pgn());
 }
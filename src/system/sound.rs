@@ -0,0 +1,116 @@
+use crate::components::{Color, SoundEffect, SoundEffectSource};
+use crate::events::{GameEnded, KingInCheck, MoveMade, SpecialMove};
+use crate::resources::{Clocks, LocalPlayer, SoundEffects, SoundSettings};
+use crate::system::clock::LOW_TIME_SECONDS;
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use std::time::Duration;
+
+// How much the volume keys change the volume by.
+const VOLUME_STEP: f32 = 0.1;
+
+pub fn setup_sound_effects(mut pitches: ResMut<Assets<Pitch>>, mut commands: Commands) {
+    let mut sound_effects = SoundEffects::default();
+    for sound_effect in [
+        SoundEffect::Move,
+        SoundEffect::Capture,
+        SoundEffect::Castle,
+        SoundEffect::Check,
+        SoundEffect::GameOver,
+        SoundEffect::LowTime,
+    ] {
+        let (frequency, duration) = sound_effect.get_tone();
+        let handle = pitches.add(Pitch::new(frequency, Duration::from_secs_f32(duration)));
+        sound_effects.0.insert(sound_effect, handle);
+    }
+    commands.insert_resource(sound_effects);
+}
+
+// M mutes or unmutes sound effects, and - and = turn them down or up.
+pub fn adjust_sound_settings(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut sound_settings: ResMut<SoundSettings>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyM) {
+        sound_settings.is_muted = !sound_settings.is_muted;
+    }
+    if keyboard.just_pressed(KeyCode::Minus) {
+        sound_settings.volume = (sound_settings.volume - VOLUME_STEP).max(0.0);
+    }
+    if keyboard.just_pressed(KeyCode::Equal) {
+        sound_settings.volume = (sound_settings.volume + VOLUME_STEP).min(1.0);
+    }
+}
+
+// Plays a sound for what happened in the game this frame. Only the most important sound is played,
+// so a move that gives check only plays the check sound. Running low on time is warned about once,
+// when the clock of a side played here drops below LOW_TIME_SECONDS.
+pub fn play_sound_effects(
+    mut move_made_events: EventReader<MoveMade>,
+    mut king_in_check_events: EventReader<KingInCheck>,
+    mut game_ended_events: EventReader<GameEnded>,
+    clocks: Res<Clocks>,
+    local_player: Res<LocalPlayer>,
+    mut low_time_colors: Local<Vec<Color>>,
+    sound_settings: Res<SoundSettings>,
+    sound_effects: Res<SoundEffects>,
+    unplayed_sound_effect_q: Query<Entity, (With<SoundEffectSource>, Without<AudioSink>)>,
+    mut commands: Commands,
+) {
+    let move_sound_effect = move_made_events.read().last().map(|move_made| {
+        match (move_made.special_move, move_made.captured) {
+            (Some(SpecialMove::Castling { .. }), _) => SoundEffect::Castle,
+            (None, Some(_)) => SoundEffect::Capture,
+            (None, None) => SoundEffect::Move,
+        }
+    });
+    // Both sides are played here unless there's a local player.
+    let previous_low_time_colors = std::mem::take(&mut *low_time_colors);
+    if clocks.time_control.is_some() {
+        *low_time_colors = [Color::White, Color::Black]
+            .into_iter()
+            .filter(|color| {
+                local_player
+                    .0
+                    .is_none_or(|local_color| local_color == *color)
+            })
+            .filter(|color| clocks.get_seconds(*color) < LOW_TIME_SECONDS)
+            .collect();
+    }
+    let is_low_on_time = low_time_colors
+        .iter()
+        .any(|color| !previous_low_time_colors.contains(color));
+
+    let sound_effect = if game_ended_events.read().count() > 0 {
+        Some(SoundEffect::GameOver)
+    } else if king_in_check_events.read().count() > 0 {
+        Some(SoundEffect::Check)
+    } else if is_low_on_time {
+        Some(SoundEffect::LowTime)
+    } else {
+        move_sound_effect
+    };
+
+    let Some(sound_effect) = sound_effect else {
+        return;
+    };
+    if sound_settings.is_muted || sound_settings.volume <= 0.0 {
+        return;
+    }
+    let Some(handle) = sound_effects.0.get(&sound_effect) else {
+        return;
+    };
+    // Sounds start playing by the frame after they're spawned, unless there's no audio device (or
+    // no audio plugin at all, such as in tests). Those are never played or despawned, so they're
+    // cleaned up here instead of piling up.
+    for unplayed_sound_effect in unplayed_sound_effect_q.iter() {
+        commands.entity(unplayed_sound_effect).despawn();
+    }
+    commands.spawn((
+        PitchBundle {
+            source: handle.clone(),
+            settings: PlaybackSettings::DESPAWN.with_volume(Volume::new(sound_settings.volume)),
+        },
+        SoundEffectSource(sound_effect),
+    ));
+}
@@ -0,0 +1,44 @@
+use crate::components::PieceImage;
+use crate::resources::{BoardTheme, PieceSet, ThemeCatalog};
+use crate::theme::find_themes;
+use bevy::asset::io::file::FileAssetReader;
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+
+// Looks through the assets folder for board themes and piece sets, warning about any that can't be
+// read.
+pub fn get_theme_catalog() -> ThemeCatalog {
+    let (catalog, errors) = find_themes(&FileAssetReader::get_base_path().join("assets"));
+    for error in errors {
+        warn!("{error}");
+    }
+    catalog
+}
+
+pub fn find_theme_catalog(mut theme_catalog: ResMut<ThemeCatalog>) {
+    *theme_catalog = get_theme_catalog();
+}
+
+// Swaps the board's tiles for the chosen theme's. The tiles' indices are kept up to date by
+// highlight_tile, which already uses the new theme's layout.
+pub fn apply_board_theme(
+    board_theme: Res<BoardTheme>,
+    asset_server: Res<AssetServer>,
+    mut tilemap_q: Query<&mut TilemapTexture>,
+) {
+    for mut tilemap_texture in &mut tilemap_q {
+        *tilemap_texture = TilemapTexture::Single(asset_server.load(board_theme.tiles.clone()));
+    }
+}
+
+// Redraws the pieces shown in the UI, such as the position editor's palette, with the chosen set.
+// The pieces on the board are redrawn by sync_piece_sprites.
+pub fn apply_piece_set(
+    piece_set: Res<PieceSet>,
+    asset_server: Res<AssetServer>,
+    mut piece_image_q: Query<(&mut UiImage, &PieceImage)>,
+) {
+    for (mut ui_image, piece_image) in &mut piece_image_q {
+        ui_image.texture = asset_server.load(piece_set.get_asset_path(&piece_image.0));
+    }
+}
@@ -1,18 +1,24 @@
 use crate::components::{
-    Color, CursorDisplay, GamePiece, LegalMove, LegalMoves, MouseoverHighlight, Piece,
+    Color, CursorDisplay, Direction, GamePiece, LegalMove, LegalMoves, MouseoverHighlight, Piece,
+    DIAGONAL_DIRECTIONS, KNIGHT_DIRECTIONS, ORTHOGONAL_DIRECTIONS,
+};
+use crate::events::{
+    GameEndReason, GameEnded, GameResult, KingInCheck, MoveMade, MoveRequested, SpecialMove,
 };
 use crate::resources::{
-    ColorToMove, CursorPos, MustRecalculateLegalMoves, RightToCastle, SelectedPiece,
-    SelectedPieceOriginalPosition,
+    BoardOrientation, BoardTheme, CheckedKingTile, ColorToMove, CursorPos, InputMode, LastMove,
+    LegalMoveHints, MoveHistory, MustRecalculateLegalMoves, PieceSet, Premoves, RightToCastle,
+    SelectedPiece, SelectedPieceOriginalPosition, Variant,
 };
-use crate::{MAP_SIZE, MAP_TYPE, SCALED_GRID_SIZE};
+use crate::system::orientation::get_tile_at_world_pos;
 use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
 
 pub fn find_mouseover_tile(
     mut commands: Commands,
     cursor_pos: Res<CursorPos>,
-    tilemap_q: Query<(&Transform, &TileStorage)>,
+    orientation: Res<BoardOrientation>,
+    tilemap_q: Query<&TileStorage>,
     tile_q: Query<Entity, With<MouseoverHighlight>>,
 ) {
     // Remove MouseoverHighlight component for any tile that has it. It will be re-added to tiles
@@ -21,16 +27,11 @@ pub fn find_mouseover_tile(
         commands.entity(tile_id).remove::<MouseoverHighlight>();
     }
 
-    let mut cursor_with_offset = cursor_pos.0;
-    for (transform, tile_storage) in &tilemap_q {
-        // Apply the opposite translation that the board has experienced to the cursor position so
-        // the cursor lines up with an untranslated board.
-        cursor_with_offset.x -= transform.translation.x;
-        cursor_with_offset.y -= transform.translation.y;
-
-        // Check if there is a tile at that position
+    for tile_storage in &tilemap_q {
+        // Check if there is a tile at the cursor's position. This accounts for the board being
+        // centered on the screen and possibly flipped.
         if let Some(tile_pos) =
-            TilePos::from_world_pos(&cursor_with_offset, &MAP_SIZE, &SCALED_GRID_SIZE, &MAP_TYPE)
+            get_tile_at_world_pos(cursor_pos.0, &tile_storage.size, &orientation)
         {
             if let Some(tile_id) = tile_storage.get(&tile_pos) {
                 commands.entity(tile_id).insert(MouseoverHighlight());
@@ -39,38 +40,27 @@ pub fn find_mouseover_tile(
     }
 }
 
+// A run condition for putting down a piece being dragged, when the left mouse button is released
+// while dragging and dropping is turned on.
+pub fn is_drag_released(input_mode: Res<InputMode>, mouse: Res<ButtonInput<MouseButton>>) -> bool {
+    *input_mode == InputMode::DragAndDrop && mouse.just_released(MouseButton::Left)
+}
+
 pub fn pick_up_piece(
     mut selected_piece: ResMut<SelectedPiece>,
     mut selected_piece_original_position: ResMut<SelectedPieceOriginalPosition>,
     mut mouse: ResMut<ButtonInput<MouseButton>>,
-    // Must include "Without<CursorDisplay>" to create a disjoint query that doesn't mutably access
-    // the "Visibility" component of an entity twice.
-    mut tile_q: Query<
-        (
-            &TilePos,
-            &mut Visibility,
-            Option<&GamePiece>,
-            Option<&LegalMoves>,
-        ),
-        (With<MouseoverHighlight>, Without<CursorDisplay>),
-    >,
-    mut cursor_q: Query<(&mut Handle<Image>, &mut Visibility), With<CursorDisplay>>,
-    asset_server: Res<AssetServer>,
+    tile_q: Query<(&TilePos, Option<&GamePiece>, Option<&LegalMoves>), With<MouseoverHighlight>>,
 ) {
     if mouse.just_pressed(MouseButton::Left) && selected_piece.0.is_none() {
-        for (tile_pos, mut visibility, game_piece, legal_moves) in &mut tile_q {
+        for (tile_pos, game_piece, legal_moves) in &tile_q {
             if let Some(game_piece) = game_piece {
                 if let Some(legal_moves) = legal_moves {
                     // Do not pick up the piece if it has no legal moves.
                     if !legal_moves.0.is_empty() {
-                        // Get game piece from current tile, and hide it on the tile while it is carried.
+                        // Get game piece from current tile. The renderer hides it on the tile and
+                        // shows it on the cursor while it is carried.
                         *selected_piece = SelectedPiece(Some(*game_piece));
-                        *visibility = Visibility::Hidden;
-
-                        // Display the game piece on the cursor.
-                        let (mut cursor_handle, mut cursor_visibility) = cursor_q.single_mut();
-                        *cursor_handle = asset_server.load(game_piece.get_asset_path().to_string());
-                        *cursor_visibility = Visibility::Visible;
 
                         // Track the piece's original position.
                         *selected_piece_original_position =
@@ -85,294 +75,163 @@ pub fn pick_up_piece(
 }
 
 pub fn put_down_piece(
-    mut commands: Commands,
     mut selected_piece: ResMut<SelectedPiece>,
     mut selected_piece_original_position: ResMut<SelectedPieceOriginalPosition>,
-    mouse: Res<ButtonInput<MouseButton>>,
-    mut color_to_move: ResMut<ColorToMove>,
-    mut must_recalculate_legal_moves: ResMut<MustRecalculateLegalMoves>,
-    mut right_to_castle: ResMut<RightToCastle>,
-    mut tile_q: Query<
-        (Entity, &TilePos, &mut Handle<Image>, &mut Visibility),
-        (With<MouseoverHighlight>, Without<CursorDisplay>),
-    >,
+    mut move_requested_events: EventWriter<MoveRequested>,
+    tile_q: Query<&TilePos, With<MouseoverHighlight>>,
     tile_legal_moves_q: Query<&LegalMoves>,
-    mut tile_sprite_q: Query<
-        (&mut Handle<Image>, &mut Visibility),
-        (Without<MouseoverHighlight>, Without<CursorDisplay>),
-    >,
     tilemap_q: Query<&TileStorage>,
-    mut cursor_q: Query<&mut Visibility, With<CursorDisplay>>,
-    asset_server: Res<AssetServer>,
 ) {
-    if mouse.just_pressed(MouseButton::Left) {
-        // Get the currently-selected piece, or skip if no piece is selected.
-        if let Some(selected_piece_some) = &selected_piece.0 {
-            // Get the position of the original tile of the currently-selected piece (this shouldn't
-            // be None if there is a selected piece).
-            if let Some(selected_piece_original_position_some) = &selected_piece_original_position.0
-            {
-                // Get the entity id of the original tile of the currently-selected piece (this
-                // shouldn't be None if there is a selected piece).
-                let tilemap = tilemap_q.single();
-                if let Some(selected_piece_original_tile) =
-                    tilemap.get(selected_piece_original_position_some)
-                {
-                    // Get the current mouseover tile, or skip if the cursor is not over a tile.
-                    if let Ok((tile_id, tile_pos, mut handle, mut visibility)) =
-                        tile_q.get_single_mut()
-                    {
-                        // Get legal moves from the original tile, or skip if the original tile had
-                        // no legal moves.
-                        if let Ok(legal_moves) =
-                            tile_legal_moves_q.get(selected_piece_original_tile)
-                        {
-                            // Check that the current mouseover tile is a legal move from the
-                            // original tile, otherwise skip.
-                            if let Some(legal_move) = legal_moves
-                                .0
-                                .iter()
-                                .find(|legal_move| legal_move.destination == *tile_pos)
-                            {
-                                // All checks have been made to make sure this is a valid move. All
-                                // code that follows is handling this move.
-
-                                // Remove the GamePiece on the original tile (do this before adding
-                                // the GamePiece from the cursor in case it's on the same tile).
-                                commands
-                                    .entity(selected_piece_original_tile)
-                                    .remove::<GamePiece>();
-
-                                // Add the GamePiece on the tile that the cursor is currently hovering over.
-                                commands.entity(tile_id).insert(*selected_piece_some);
-                                *handle = asset_server
-                                    .load(selected_piece_some.get_asset_path().to_string());
-                                *visibility = Visibility::Visible;
-
-                                // If the move was castling, move the rook too.
-                                if legal_move.is_castling {
-                                    match tile_pos {
-                                        TilePos { x: 2, y: 0 } => {
-                                            let rook_starting_tile_id = tilemap
-                                                .get(&TilePos { x: 0, y: 0 })
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            let rook_ending_tile_id = tilemap
-                                                .get(&TilePos { x: 3, y: 0 })
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            commands
-                                                .entity(rook_starting_tile_id)
-                                                .remove::<GamePiece>();
-                                            commands.entity(rook_ending_tile_id).insert(
-                                                GamePiece {
-                                                    piece: Piece::Rook,
-                                                    color: Color::White,
-                                                },
-                                            );
-
-                                            let (_, mut rook_starting_tile_visibility) =
-                                                tile_sprite_q
-                                                    .get_mut(rook_starting_tile_id)
-                                                    .expect(
-                                                        "Tile literal should exist on 8x8 board",
-                                                    );
-                                            *rook_starting_tile_visibility = Visibility::Hidden;
-
-                                            let (
-                                                mut rook_ending_tile_handle,
-                                                mut rook_ending_tile_visibility,
-                                            ) = tile_sprite_q
-                                                .get_mut(rook_ending_tile_id)
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            *rook_ending_tile_handle = asset_server.load(
-                                                GamePiece {
-                                                    piece: Piece::Rook,
-                                                    color: Color::White,
-                                                }
-                                                .get_asset_path()
-                                                .to_string(),
-                                            );
-                                            *rook_ending_tile_visibility = Visibility::Visible;
-                                        }
-                                        TilePos { x: 6, y: 0 } => {
-                                            let rook_starting_tile_id = tilemap
-                                                .get(&TilePos { x: 7, y: 0 })
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            let rook_ending_tile_id = tilemap
-                                                .get(&TilePos { x: 5, y: 0 })
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            commands
-                                                .entity(rook_starting_tile_id)
-                                                .remove::<GamePiece>();
-                                            commands.entity(rook_ending_tile_id).insert(
-                                                GamePiece {
-                                                    piece: Piece::Rook,
-                                                    color: Color::White,
-                                                },
-                                            );
-
-                                            let (_, mut rook_starting_tile_visibility) =
-                                                tile_sprite_q
-                                                    .get_mut(rook_starting_tile_id)
-                                                    .expect(
-                                                        "Tile literal should exist on 8x8 board",
-                                                    );
-                                            *rook_starting_tile_visibility = Visibility::Hidden;
+    // Get the original position of the currently-selected piece, or skip if no piece is selected.
+    let Some(original_tile_pos) = selected_piece_original_position.0 else {
+        return;
+    };
+    // Get the current mouseover tile, or skip if the cursor is not over a tile.
+    let Ok(tile_pos) = tile_q.get_single() else {
+        return;
+    };
 
-                                            let (
-                                                mut rook_ending_tile_handle,
-                                                mut rook_ending_tile_visibility,
-                                            ) = tile_sprite_q
-                                                .get_mut(rook_ending_tile_id)
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            *rook_ending_tile_handle = asset_server.load(
-                                                GamePiece {
-                                                    piece: Piece::Rook,
-                                                    color: Color::White,
-                                                }
-                                                .get_asset_path()
-                                                .to_string(),
-                                            );
-                                            *rook_ending_tile_visibility = Visibility::Visible;
-                                        }
-                                        TilePos { x: 2, y: 7 } => {
-                                            let rook_starting_tile_id = tilemap
-                                                .get(&TilePos { x: 0, y: 7 })
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            let rook_ending_tile_id = tilemap
-                                                .get(&TilePos { x: 3, y: 7 })
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            commands
-                                                .entity(rook_starting_tile_id)
-                                                .remove::<GamePiece>();
-                                            commands.entity(rook_ending_tile_id).insert(
-                                                GamePiece {
-                                                    piece: Piece::Rook,
-                                                    color: Color::Black,
-                                                },
-                                            );
+    // Check that the current mouseover tile is a legal move from the original tile, otherwise keep
+    // carrying the piece.
+    let tilemap = tilemap_q.single();
+    let original_tile_id = tilemap
+        .get(&original_tile_pos)
+        .expect("Selected piece's original tile should exist on the board");
+    let is_legal_move = tile_legal_moves_q
+        .get(original_tile_id)
+        .is_ok_and(|legal_moves| {
+            legal_moves
+                .0
+                .iter()
+                .any(|legal_move| legal_move.destination == *tile_pos)
+        });
+    if !is_legal_move {
+        return;
+    }
 
-                                            let (_, mut rook_starting_tile_visibility) =
-                                                tile_sprite_q
-                                                    .get_mut(rook_starting_tile_id)
-                                                    .expect(
-                                                        "Tile literal should exist on 8x8 board",
-                                                    );
-                                            *rook_starting_tile_visibility = Visibility::Hidden;
+    move_requested_events.send(MoveRequested {
+        from: original_tile_pos,
+        to: *tile_pos,
+    });
 
-                                            let (
-                                                mut rook_ending_tile_handle,
-                                                mut rook_ending_tile_visibility,
-                                            ) = tile_sprite_q
-                                                .get_mut(rook_ending_tile_id)
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            *rook_ending_tile_handle = asset_server.load(
-                                                GamePiece {
-                                                    piece: Piece::Rook,
-                                                    color: Color::Black,
-                                                }
-                                                .get_asset_path()
-                                                .to_string(),
-                                            );
-                                            *rook_ending_tile_visibility = Visibility::Visible;
-                                        }
-                                        TilePos { x: 6, y: 7 } => {
-                                            let rook_starting_tile_id = tilemap
-                                                .get(&TilePos { x: 7, y: 7 })
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            let rook_ending_tile_id = tilemap
-                                                .get(&TilePos { x: 5, y: 7 })
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            commands
-                                                .entity(rook_starting_tile_id)
-                                                .remove::<GamePiece>();
-                                            commands.entity(rook_ending_tile_id).insert(
-                                                GamePiece {
-                                                    piece: Piece::Rook,
-                                                    color: Color::Black,
-                                                },
-                                            );
+    // Reset the cursor.
+    *selected_piece = SelectedPiece(None);
+    *selected_piece_original_position = SelectedPieceOriginalPosition(None);
+}
 
-                                            let (_, mut rook_starting_tile_visibility) =
-                                                tile_sprite_q
-                                                    .get_mut(rook_starting_tile_id)
-                                                    .expect(
-                                                        "Tile literal should exist on 8x8 board",
-                                                    );
-                                            *rook_starting_tile_visibility = Visibility::Hidden;
+// Makes a requested move if it's one of the legal moves in the current position. Only one move is
+// made each frame, as the legal moves must be recalculated before the next move can be checked.
+pub fn make_move(
+    mut commands: Commands,
+    mut color_to_move: ResMut<ColorToMove>,
+    mut must_recalculate_legal_moves: ResMut<MustRecalculateLegalMoves>,
+    mut right_to_castle: ResMut<RightToCastle>,
+    mut move_requested_events: EventReader<MoveRequested>,
+    mut move_made_events: EventWriter<MoveMade>,
+    tile_game_state_q: Query<(Option<&GamePiece>, Option<&LegalMoves>)>,
+    tilemap_q: Query<&TileStorage>,
+) {
+    let Some(move_requested) = move_requested_events.read().next() else {
+        return;
+    };
+    let tilemap = tilemap_q.single();
+    let (Some(original_tile_id), Some(tile_id)) = (
+        tilemap.get(&move_requested.from),
+        tilemap.get(&move_requested.to),
+    ) else {
+        warn!("Ignoring move off the board: {move_requested:?}");
+        return;
+    };
 
-                                            let (
-                                                mut rook_ending_tile_handle,
-                                                mut rook_ending_tile_visibility,
-                                            ) = tile_sprite_q
-                                                .get_mut(rook_ending_tile_id)
-                                                .expect("Tile literal should exist on 8x8 board");
-                                            *rook_ending_tile_handle = asset_server.load(
-                                                GamePiece {
-                                                    piece: Piece::Rook,
-                                                    color: Color::Black,
-                                                }
-                                                .get_asset_path()
-                                                .to_string(),
-                                            );
-                                            *rook_ending_tile_visibility = Visibility::Visible;
-                                        }
-                                        _ => panic!("Invalid castling move"),
-                                    }
-                                }
+    // Find the legal move matching the request, or ignore the request if there isn't one.
+    let (game_piece, legal_moves) = tile_game_state_q
+        .get(original_tile_id)
+        .expect("Tile should have a game state");
+    let (Some(game_piece), Some(legal_moves)) = (game_piece.copied(), legal_moves) else {
+        warn!("Ignoring move of a piece that can't move: {move_requested:?}");
+        return;
+    };
+    let Some(legal_move) = legal_moves
+        .0
+        .iter()
+        .find(|legal_move| legal_move.destination == move_requested.to)
+    else {
+        warn!("Ignoring illegal move: {move_requested:?}");
+        return;
+    };
+    let (captured, _) = tile_game_state_q
+        .get(tile_id)
+        .expect("Tile should have a game state");
+    let captured = captured.copied();
+
+    // Remove the GamePiece on the original tile (do this before adding the GamePiece to the
+    // destination in case it's on the same tile).
+    commands.entity(original_tile_id).remove::<GamePiece>();
+    commands.entity(tile_id).insert(game_piece);
+
+    // If the move was castling, move the rook too. The king moves towards the rook in the corner,
+    // which moves to the tile on the other side of the king.
+    let mut special_move = None;
+    if legal_move.is_castling {
+        let (rook_starting_x, rook_ending_x) = if move_requested.to.x < move_requested.from.x {
+            (0, move_requested.to.x + 1)
+        } else {
+            (tilemap.size.x - 1, move_requested.to.x - 1)
+        };
+        let rook_from = TilePos {
+            x: rook_starting_x,
+            y: move_requested.to.y,
+        };
+        let rook_to = TilePos {
+            x: rook_ending_x,
+            y: move_requested.to.y,
+        };
+        let rook = GamePiece {
+            piece: Piece::Rook,
+            color: game_piece.color,
+        };
 
-                                // If the original tile was a rook's or king's starting tile, remove the corresponding right to castle.
-                                match selected_piece_original_position_some {
-                                    TilePos { x: 0, y: 0 } => {
-                                        right_to_castle.white_queenside = false
-                                    }
-                                    TilePos { x: 7, y: 0 } => {
-                                        right_to_castle.white_kingside = false
-                                    }
-                                    TilePos { x: 4, y: 0 } => {
-                                        right_to_castle.white_kingside = false;
-                                        right_to_castle.white_queenside = false;
-                                    }
-                                    TilePos { x: 0, y: 7 } => {
-                                        right_to_castle.black_queenside = false
-                                    }
-                                    TilePos { x: 7, y: 7 } => {
-                                        right_to_castle.black_kingside = false
-                                    }
-                                    TilePos { x: 4, y: 7 } => {
-                                        right_to_castle.black_kingside = false;
-                                        right_to_castle.black_queenside = false;
-                                    }
-                                    _ => {}
-                                }
+        let rook_starting_tile_id = tilemap
+            .get(&rook_from)
+            .expect("Castling rook's tile should exist on the board");
+        let rook_ending_tile_id = tilemap
+            .get(&rook_to)
+            .expect("Castling rook's tile should exist on the board");
+        commands.entity(rook_starting_tile_id).remove::<GamePiece>();
+        commands.entity(rook_ending_tile_id).insert(rook);
 
-                                // Remove the game piece sprite from the cursor.
-                                let mut cursor_visibility = cursor_q.single_mut();
-                                *cursor_visibility = Visibility::Hidden;
+        special_move = Some(SpecialMove::Castling { rook_from, rook_to });
+    }
 
-                                // Reset the cursor.
-                                *selected_piece = SelectedPiece(None);
-                                *selected_piece_original_position =
-                                    SelectedPieceOriginalPosition(None);
+    // If the original tile was a rook's or king's starting tile, or a rook was captured on its
+    // starting tile, remove the corresponding right to castle.
+    right_to_castle.remove_for_tile(&move_requested.from, &tilemap.size);
+    right_to_castle.remove_for_tile(&move_requested.to, &tilemap.size);
+
+    move_made_events.send(MoveMade {
+        from: move_requested.from,
+        to: move_requested.to,
+        game_piece,
+        captured,
+        special_move,
+    });
 
-                                // Prepare to calculate the next legal moves.
-                                color_to_move.switch();
-                                *must_recalculate_legal_moves = MustRecalculateLegalMoves(true);
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
+    // Prepare to calculate the next legal moves.
+    color_to_move.switch();
+    *must_recalculate_legal_moves = MustRecalculateLegalMoves(true);
 }
 
 pub fn recalculate_legal_moves(
     mut commands: Commands,
+    variant: Res<Variant>,
     color_to_move: Res<ColorToMove>,
     right_to_castle: Res<RightToCastle>,
     mut must_recalculate_legal_moves: ResMut<MustRecalculateLegalMoves>,
+    mut checked_king_tile: ResMut<CheckedKingTile>,
+    mut king_in_check_events: EventWriter<KingInCheck>,
+    mut game_ended_events: EventWriter<GameEnded>,
     tile_legal_moves_q: Query<Entity, With<LegalMoves>>,
-    tile_game_piece_q: Query<(Entity, &GamePiece, &TilePos)>,
+    tile_game_piece_q: Query<&GamePiece>,
     tilemap_q: Query<&TileStorage>,
 ) {
     if must_recalculate_legal_moves.0 {
@@ -387,354 +246,208 @@ pub fn recalculate_legal_moves(
         // game piece on that square.
         let get_game_piece_at_position = |tile_pos: &TilePos| {
             let adjacent_tile_id = tilemap.get(tile_pos).expect("Tile should exist in tilemap");
-
-            if let Ok((_, other_game_piece, _)) = tile_game_piece_q.get(adjacent_tile_id) {
-                Some(*other_game_piece)
-            } else {
-                None
-            }
+            tile_game_piece_q.get(adjacent_tile_id).ok().copied()
         };
 
-        // Get the tile the king is currently on.
-        let mut king_tile = None;
-        for (_, game_piece, tile_pos) in &tile_game_piece_q {
-            if *game_piece
-                == (GamePiece {
-                    piece: Piece::King,
+        let all_legal_moves = find_all_legal_moves(
+            color_to_move.0,
+            &variant,
+            &right_to_castle,
+            &tilemap.size,
+            get_game_piece_at_position,
+        );
+        let has_piece = !all_legal_moves.is_empty();
+        let mut has_legal_move = false;
+        for (tile_pos, legal_moves) in all_legal_moves {
+            has_legal_move |= !legal_moves.is_empty();
+            let tile_id = tilemap
+                .get(&tile_pos)
+                .expect("Tile should exist in tilemap");
+            commands.entity(tile_id).insert(LegalMoves(legal_moves));
+        }
+
+        let mut is_in_check = false;
+        *checked_king_tile = CheckedKingTile(None);
+        if let Some(king_tile) = find_king_tile(
+            color_to_move.0,
+            &variant,
+            &tilemap.size,
+            get_game_piece_at_position,
+        ) {
+            if is_king_threatened(
+                &king_tile,
+                &color_to_move.0,
+                &tilemap.size,
+                get_game_piece_at_position,
+            ) {
+                is_in_check = true;
+                *checked_king_tile = CheckedKingTile(Some(king_tile));
+                king_in_check_events.send(KingInCheck {
                     color: color_to_move.0,
-                })
-            {
-                king_tile = Some(tile_pos);
-                break;
+                    king_tile,
+                });
             }
         }
+        // The game ends when the side to move can't move. A side without a king can't be
+        // checkmated, so it instead loses when it has no pieces left.
+        if !has_legal_move {
+            let game_ended = if !has_piece {
+                GameEnded {
+                    result: GameResult::Win(color_to_move.0.opposite()),
+                    reason: GameEndReason::AllPiecesCaptured,
+                }
+            } else if is_in_check {
+                GameEnded {
+                    result: GameResult::Win(color_to_move.0.opposite()),
+                    reason: GameEndReason::Checkmate,
+                }
+            } else {
+                GameEnded {
+                    result: GameResult::Draw,
+                    reason: GameEndReason::Stalemate,
+                }
+            };
+            info!(
+                "Game ended by {}: {}",
+                game_ended.reason.get_name(),
+                game_ended.result.to_pgn()
+            );
+            game_ended_events.send(game_ended);
+        }
+
+        *must_recalculate_legal_moves = MustRecalculateLegalMoves(false);
+    }
+}
+
+// Returns the tile of the color's king. Some variants don't give every side a king (such as white
+// in Horde), in which case there is no king whose safety restricts that side's moves.
+pub fn find_king_tile<F: Fn(&TilePos) -> Option<GamePiece>>(
+    color: Color,
+    variant: &Variant,
+    map_size: &TilemapSize,
+    get_game_piece_at_position: F,
+) -> Option<TilePos> {
+    if !variant.has_king(color) {
+        return None;
+    }
+    let king = GamePiece {
+        piece: Piece::King,
+        color,
+    };
+    let king_tile = (0..map_size.x)
+        .flat_map(|x| (0..map_size.y).map(move |y| TilePos { x, y }))
+        .find(|tile_pos| get_game_piece_at_position(tile_pos) == Some(king))
+        .expect("A side with a king should have its king on the board");
+    Some(king_tile)
+}
+
+// Finds the legal moves of each of the color's pieces, given the game piece on each tile. Pieces
+// without any legal moves are still included, with no moves.
+pub fn find_all_legal_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
+    color: Color,
+    variant: &Variant,
+    right_to_castle: &RightToCastle,
+    map_size: &TilemapSize,
+    get_game_piece_at_position: F,
+) -> Vec<(TilePos, Vec<LegalMove>)> {
+    let mut all_legal_moves = find_all_pseudo_legal_moves(
+        color,
+        variant,
+        right_to_castle,
+        map_size,
+        &get_game_piece_at_position,
+    );
+
+    // Remove legal moves that would leave one's own king under attack, if there is one.
+    if let Some(king_tile) = find_king_tile(color, variant, map_size, &get_game_piece_at_position) {
+        for (tile_pos, legal_moves) in &mut all_legal_moves {
+            legal_moves.retain(|legal_move| {
+                !is_king_threatened_after_move(
+                    tile_pos,
+                    &legal_move.destination,
+                    &king_tile,
+                    &color,
+                    map_size,
+                    &get_game_piece_at_position,
+                )
+            });
+        }
+    }
+    all_legal_moves
+}
 
-        for (tile_id, game_piece, tile_pos) in &tile_game_piece_q {
-            if game_piece.color == color_to_move.0 {
-                // The maximum number of legal moves a single piece can have is 27. This occurs when
-                // a queen is placed in the center of the board and is unobstructed in all
-                // directions, where it can move 7 spaces vertically, 7 spaces horizontally, 7
-                // spaces along the long diagonal, and 6 spaces along the short diagonal.
+// Finds the moves each of the color's pieces could make if it didn't matter whether they left
+// their own king under attack.
+pub fn find_all_pseudo_legal_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
+    color: Color,
+    variant: &Variant,
+    right_to_castle: &RightToCastle,
+    map_size: &TilemapSize,
+    get_game_piece_at_position: F,
+) -> Vec<(TilePos, Vec<LegalMove>)> {
+    let mut all_legal_moves = Vec::new();
+    for x in 0..map_size.x {
+        for y in 0..map_size.y {
+            let tile_pos = &TilePos { x, y };
+            let Some(game_piece) = get_game_piece_at_position(tile_pos) else {
+                continue;
+            };
+            if game_piece.color == color {
+                // The maximum number of legal moves a single piece can have on an 8x8 board is 27.
+                // This occurs when a queen is placed in the center of the board and is unobstructed
+                // in all directions, where it can move 7 spaces vertically, 7 spaces horizontally,
+                // 7 spaces along the long diagonal, and 6 spaces along the short diagonal. Larger
+                // boards and fairy pieces can exceed this, in which case the vector will grow.
                 let mut legal_moves = Vec::with_capacity(27);
 
-                match game_piece.piece {
-                    Piece::Pawn => {
-                        find_legal_pawn_moves(
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        // TODO: Handle promotion.
-                        // TODO: Handle en passant.
-                    }
-                    Piece::Rook => {
-                        find_legal_moves_in_direction(
-                            Direction { x: 0, y: 1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: 0 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 0, y: -1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: 0 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                    }
-                    Piece::Knight => {
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: 2 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 2, y: 1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 2, y: -1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: -2 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: -2 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -2, y: -1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -2, y: 1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: 2 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                    }
-                    Piece::Bishop => {
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: 1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: -1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: -1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: 1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                    }
-                    Piece::Queen => {
-                        find_legal_moves_in_direction(
-                            Direction { x: 0, y: 1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: 1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: 0 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: -1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 0, y: -1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: -1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: 0 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: 1 },
-                            true,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                    }
-                    Piece::King => {
-                        find_legal_moves_in_direction(
-                            Direction { x: 0, y: 1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: 1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: 0 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 1, y: -1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: 0, y: -1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: -1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: 0 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                        find_legal_moves_in_direction(
-                            Direction { x: -1, y: 1 },
-                            false,
-                            &mut legal_moves,
-                            tile_pos,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
+                if game_piece.piece == Piece::Pawn {
+                    find_legal_pawn_moves(
+                        &mut legal_moves,
+                        tile_pos,
+                        &color,
+                        |row| variant.can_pawn_double_move(color, row),
+                        map_size,
+                        &get_game_piece_at_position,
+                    );
+                    // TODO: Handle promotion.
+                    // TODO: Handle en passant.
+                }
 
-                        // Handle castling.
-                        find_legal_castling_moves(
-                            &mut legal_moves,
-                            &right_to_castle,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        );
-                    }
+                for movement in game_piece.piece.get_movements() {
+                    find_legal_moves_in_direction(
+                        movement.direction,
+                        movement.slides,
+                        &mut legal_moves,
+                        tile_pos,
+                        &color,
+                        map_size,
+                        &get_game_piece_at_position,
+                    );
                 }
 
-                // Remove legal moves that would leave one's own king under attack. As a failsafe,
-                // skip this process if the king wasn't found on the board.
-                if let Some(king_tile) = king_tile {
-                    legal_moves.retain(|legal_move| {
-                        !is_king_threatened_after_move(
-                            tile_pos,
-                            &legal_move.destination,
-                            king_tile,
-                            &color_to_move.0,
-                            get_game_piece_at_position,
-                        )
-                    });
+                if game_piece.piece == Piece::King {
+                    find_legal_castling_moves(
+                        &mut legal_moves,
+                        right_to_castle,
+                        &color,
+                        map_size,
+                        &get_game_piece_at_position,
+                    );
                 }
 
-                commands.entity(tile_id).insert(LegalMoves(legal_moves));
+                all_legal_moves.push((*tile_pos, legal_moves));
             }
         }
-
-        *must_recalculate_legal_moves = MustRecalculateLegalMoves(false);
     }
-}
-
-struct Direction {
-    x: i32,
-    y: i32,
+    all_legal_moves
 }
 
 fn find_legal_pawn_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
     legal_moves: &mut Vec<LegalMove>,
     position: &TilePos,
     color_to_move: &Color,
+    can_double_move_from_row: impl Fn(u32) -> bool,
+    map_size: &TilemapSize,
     get_game_piece_at_position: F,
 ) {
     let y_direction = if *color_to_move == Color::White {
@@ -748,7 +461,7 @@ fn find_legal_pawn_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
     // TODO: Once promotion is implemented, checking that the pawn isn't on the final rank will be
     // unnecessary.
     if let Some(y_next) = position.y.checked_add_signed(y_direction) {
-        if y_next < MAP_SIZE.y
+        if y_next < map_size.y
             && get_game_piece_at_position(&TilePos {
                 x: position.x,
                 y: y_next,
@@ -763,10 +476,8 @@ fn find_legal_pawn_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
                 is_castling: false,
             });
             // If the pawn could move forward one square, check if it can also move two squares
-            // (it's on its starting rank and the next square is also free).
-            if (*color_to_move == Color::White && position.y == 1)
-                || (*color_to_move == Color::Black && position.y == 6)
-            {
+            // (it's on a rank it can double move from and the next square is also free).
+            if can_double_move_from_row(position.y) {
                 // If a pawn is making a double move from its starting square, it's not possible for
                 // its end position to be off the board, so checking for this isn't necessary.
                 let y_next_next = y_next.wrapping_add_signed(y_direction);
@@ -796,7 +507,7 @@ fn find_legal_pawn_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
         .checked_add_signed(-1)
         .zip(position.y.checked_add_signed(y_direction))
     {
-        if y_next < MAP_SIZE.y
+        if y_next < map_size.y
             && get_game_piece_at_position(&TilePos {
                 x: x_next,
                 y: y_next,
@@ -816,8 +527,8 @@ fn find_legal_pawn_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
     // unnecessary.
     let x_next = position.x + 1;
     if let Some(y_next) = position.y.checked_add_signed(y_direction) {
-        if x_next < MAP_SIZE.x
-            && y_next < MAP_SIZE.y
+        if x_next < map_size.x
+            && y_next < map_size.y
             && get_game_piece_at_position(&TilePos {
                 x: x_next,
                 y: y_next,
@@ -841,6 +552,7 @@ fn find_legal_moves_in_direction<F: Fn(&TilePos) -> Option<GamePiece>>(
     legal_moves: &mut Vec<LegalMove>,
     position: &TilePos,
     color_to_move: &Color,
+    map_size: &TilemapSize,
     get_game_piece_at_position: F,
 ) {
     // Use checked_add_signed() to make sure overflow doesn't occur from going below position 0
@@ -850,8 +562,8 @@ fn find_legal_moves_in_direction<F: Fn(&TilePos) -> Option<GamePiece>>(
         .zip(position.y.checked_add_signed(direction.y))
     {
         // If code reaches this point, no overflow occurred, so only need to check if the new
-        // position isn't past the eighth row or column.
-        if x_next < MAP_SIZE.x && y_next < MAP_SIZE.y {
+        // position isn't past the last row or column.
+        if x_next < map_size.x && y_next < map_size.y {
             match get_game_piece_at_position(&TilePos {
                 x: x_next,
                 y: y_next,
@@ -876,6 +588,7 @@ fn find_legal_moves_in_direction<F: Fn(&TilePos) -> Option<GamePiece>>(
                                 y: y_next,
                             },
                             color_to_move,
+                            map_size,
                             get_game_piece_at_position,
                         );
                     }
@@ -903,12 +616,19 @@ fn find_legal_castling_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
     legal_moves: &mut Vec<LegalMove>,
     right_to_castle: &RightToCastle,
     color_to_move: &Color,
+    map_size: &TilemapSize,
     get_game_piece_at_position: F,
 ) {
     let king_row = if *color_to_move == Color::White {
         0
     } else {
-        MAP_SIZE.y - 1
+        map_size.y - 1
+    };
+    // The king starts in the middle column (the e-file on an 8-wide board, or the f-file on a
+    // 10-wide board), and the rooks start in the corners.
+    let king_column = map_size.x / 2;
+    let is_row_clear = |mut columns: std::ops::Range<u32>| {
+        columns.all(|x| get_game_piece_at_position(&TilePos { x, y: king_row }).is_none())
     };
 
     // It can be assumed the king and rook are still in their starting positions, because if they
@@ -918,25 +638,26 @@ fn find_legal_castling_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
     // every legal move is checked to see if the final arrangement of pieces places the king under
     // attack after the vector of legal moves is constructed.
 
-    // Column 4 is the king column. Columns 5 and 6 have the kingside bishop and knight.
+    // Every tile between the king and the kingside rook must be empty. The king ends up next to the
+    // rook's corner.
     if ((*color_to_move == Color::White && right_to_castle.white_kingside)
         || (*color_to_move == Color::Black && right_to_castle.black_kingside))
-        && get_game_piece_at_position(&TilePos { x: 5, y: king_row }).is_none()
-        && get_game_piece_at_position(&TilePos { x: 6, y: king_row }).is_none()
+        && is_row_clear(king_column + 1..map_size.x - 1)
     {
         legal_moves.push(LegalMove {
-            destination: TilePos { x: 6, y: king_row },
+            destination: TilePos {
+                x: map_size.x - 2,
+                y: king_row,
+            },
             is_castling: true,
         });
     }
 
-    // Column 4 is the king column. Columns 1, 2, and 3 have the queenside knight, bishop, and
-    // queen.
+    // Every tile between the king and the queenside rook must be empty. The king ends up two tiles
+    // away from the rook's corner.
     if ((*color_to_move == Color::White && right_to_castle.white_queenside)
         || (*color_to_move == Color::Black && right_to_castle.black_queenside))
-        && get_game_piece_at_position(&TilePos { x: 1, y: king_row }).is_none()
-        && get_game_piece_at_position(&TilePos { x: 2, y: king_row }).is_none()
-        && get_game_piece_at_position(&TilePos { x: 3, y: king_row }).is_none()
+        && is_row_clear(1..king_column)
     {
         legal_moves.push(LegalMove {
             destination: TilePos { x: 2, y: king_row },
@@ -945,6 +666,24 @@ fn find_legal_castling_moves<F: Fn(&TilePos) -> Option<GamePiece>>(
     }
 }
 
+// Checks if the king on the given tile is currently under attack by an enemy piece. This is the
+// same as checking a "move" that leaves the king where it is.
+pub fn is_king_threatened<F: Fn(&TilePos) -> Option<GamePiece>>(
+    king_tile: &TilePos,
+    king_color: &Color,
+    map_size: &TilemapSize,
+    get_game_piece_at_position: F,
+) -> bool {
+    is_king_threatened_after_move(
+        king_tile,
+        king_tile,
+        king_tile,
+        king_color,
+        map_size,
+        get_game_piece_at_position,
+    )
+}
+
 // Checks if, after moving the piece from the starting tile to the end tile, if the king would be
 // under attack by an enemy piece.
 fn is_king_threatened_after_move<'a, F: Fn(&TilePos) -> Option<GamePiece>>(
@@ -952,6 +691,7 @@ fn is_king_threatened_after_move<'a, F: Fn(&TilePos) -> Option<GamePiece>>(
     end_tile: &'a TilePos,
     mut king_tile: &'a TilePos,
     color_to_move: &Color,
+    map_size: &TilemapSize,
     get_game_piece_at_position: F,
 ) -> bool {
     // If the starting tile and king tile match, that means the king was moved, so update the king
@@ -960,16 +700,11 @@ fn is_king_threatened_after_move<'a, F: Fn(&TilePos) -> Option<GamePiece>>(
         king_tile = end_tile;
     }
 
-    let opposite_color = if *color_to_move == Color::White {
-        Color::Black
-    } else {
-        Color::White
-    };
+    let opposite_color = color_to_move.opposite();
 
-    #[derive(PartialEq, Eq)]
     struct GamePieceInDirectionResult {
         game_piece: GamePiece,
-        is_adjacent: bool,
+        is_single_step: bool,
     }
 
     // Determines where the next game piece in the given direction is, taking into account that
@@ -977,17 +712,17 @@ fn is_king_threatened_after_move<'a, F: Fn(&TilePos) -> Option<GamePiece>>(
     // starting tile to the end tile. Returns an option with None if no piece was found in that
     // direction (or if the end tile was found in that direction which can never contain an
     // attacking piece), or a tuple containing the game piece in that direction and a boolean
-    // indicating if it's immediately adjacent.
+    // indicating if it's only a single step away.
     let get_game_piece_in_direction = |direction: Direction| {
         let mut x = king_tile.x;
         let mut y = king_tile.y;
-        let mut is_adjacent = true;
+        let mut is_single_step = true;
 
         while let Some((x_next, y_next)) = x
             .checked_add_signed(direction.x)
             .zip(y.checked_add_signed(direction.y))
         {
-            if x_next < MAP_SIZE.x && y_next < MAP_SIZE.y {
+            if x_next < map_size.x && y_next < map_size.y {
                 // The next position is on the board, so check what's at that position.
                 let next_tile = TilePos {
                     x: x_next,
@@ -1011,7 +746,7 @@ fn is_king_threatened_after_move<'a, F: Fn(&TilePos) -> Option<GamePiece>>(
                     if let Some(game_piece_at_position) = get_game_piece_at_position(&next_tile) {
                         return Some(GamePieceInDirectionResult {
                             game_piece: game_piece_at_position,
-                            is_adjacent,
+                            is_single_step,
                         });
                     }
                 }
@@ -1020,7 +755,7 @@ fn is_king_threatened_after_move<'a, F: Fn(&TilePos) -> Option<GamePiece>>(
                 // to the next loop iteration.
                 x = x_next;
                 y = y_next;
-                is_adjacent = false;
+                is_single_step = false;
             } else {
                 // x_next or y_next were greater than the board size, so stop looping because the
                 // edge of the board has been reached.
@@ -1034,235 +769,134 @@ fn is_king_threatened_after_move<'a, F: Fn(&TilePos) -> Option<GamePiece>>(
         None
     };
 
-    // Check horizontally and vertically for a rook, queen, or immediately adjacent king of the
-    // opposite color. Stop searching in a direction if any other piece is found, the end tile is
-    // reached (as this means a piece of the same color is being moved there which will block attack
-    // from that direction), or the end of the board is reached.
-    let is_threatened_horizontally_or_vertically =
-        |game_piece_in_direction_result: GamePieceInDirectionResult| {
-            game_piece_in_direction_result.game_piece
-                == GamePiece {
-                    piece: Piece::Rook,
-                    color: opposite_color,
-                }
-                || game_piece_in_direction_result.game_piece
-                    == GamePiece {
-                        piece: Piece::Queen,
-                        color: opposite_color,
-                    }
-                || game_piece_in_direction_result
-                    == GamePieceInDirectionResult {
-                        game_piece: GamePiece {
-                            piece: Piece::King,
-                            color: opposite_color,
-                        },
-                        is_adjacent: true,
-                    }
-        };
-
-    // Check diagonally for a bishop, queen, or immediately adjacent king. If white, also check
-    // upwards diagonals for an immediately adjacent pawn.
-    let is_threatened_diagonally_upwards =
-        |game_piece_in_direction_result: GamePieceInDirectionResult| {
-            game_piece_in_direction_result.game_piece
-                == GamePiece {
-                    piece: Piece::Bishop,
-                    color: opposite_color,
-                }
-                || game_piece_in_direction_result.game_piece
-                    == GamePiece {
-                        piece: Piece::Queen,
-                        color: opposite_color,
-                    }
-                || game_piece_in_direction_result
-                    == GamePieceInDirectionResult {
-                        game_piece: GamePiece {
-                            piece: Piece::King,
-                            color: opposite_color,
-                        },
-                        is_adjacent: true,
-                    }
-                // Only black pawns attack downwards, so only white's king is vulnerable to pawn
-                // attacks from above.
-                || (*color_to_move == Color::White
+    // Look outwards from the king in every direction that any piece can attack along. Only the
+    // first piece found in each direction can be attacking the king from that direction, as it
+    // blocks anything behind it. It threatens the king if it's of the opposite color and can attack
+    // back towards the king, either by sliding or by only needing a single step.
+    ORTHOGONAL_DIRECTIONS
+        .iter()
+        .chain(DIAGONAL_DIRECTIONS.iter())
+        .chain(KNIGHT_DIRECTIONS.iter())
+        .any(|direction| {
+            get_game_piece_in_direction(*direction).is_some_and(|game_piece_in_direction_result| {
+                game_piece_in_direction_result.game_piece.color == opposite_color
                     && game_piece_in_direction_result
-                        == GamePieceInDirectionResult {
-                            game_piece: GamePiece {
-                                piece: Piece::Pawn,
-                                color: opposite_color,
-                            },
-                            is_adjacent: true,
+                        .game_piece
+                        .get_attacks()
+                        .iter()
+                        .any(|attack| {
+                            attack.direction == direction.reverse()
+                                && (attack.slides || game_piece_in_direction_result.is_single_step)
                         })
-        };
-    // Same diagonal check, except for black checking downwards diagonals for an immediately
-    // adjacent pawn.
-    let is_threatened_diagonally_downwards =
-        |game_piece_in_direction_result: GamePieceInDirectionResult| {
-            game_piece_in_direction_result.game_piece
-                == GamePiece {
-                    piece: Piece::Bishop,
-                    color: opposite_color,
-                }
-                || game_piece_in_direction_result.game_piece
-                    == GamePiece {
-                        piece: Piece::Queen,
-                        color: opposite_color,
-                    }
-                || game_piece_in_direction_result
-                    == GamePieceInDirectionResult {
-                        game_piece: GamePiece {
-                            piece: Piece::King,
-                            color: opposite_color,
-                        },
-                        is_adjacent: true,
-                    }
-                // Only white pawns attack upwards, so only black's king is vulnerable to pawn
-                // attacks from below.
-                || (*color_to_move == Color::Black
-                    && game_piece_in_direction_result
-                        == GamePieceInDirectionResult {
-                            game_piece: GamePiece {
-                                piece: Piece::Pawn,
-                                color: opposite_color,
-                            },
-                            is_adjacent: true,
-                        })
-        };
-
-    if get_game_piece_in_direction(Direction { x: 0, y: 1 })
-        .is_some_and(is_threatened_horizontally_or_vertically)
-    {
-        return true;
-    }
-    if get_game_piece_in_direction(Direction { x: 1, y: 0 })
-        .is_some_and(is_threatened_horizontally_or_vertically)
-    {
-        return true;
-    }
-    if get_game_piece_in_direction(Direction { x: 0, y: -1 })
-        .is_some_and(is_threatened_horizontally_or_vertically)
-    {
-        return true;
-    }
-    if get_game_piece_in_direction(Direction { x: -1, y: 0 })
-        .is_some_and(is_threatened_horizontally_or_vertically)
-    {
-        return true;
-    }
-
-    if get_game_piece_in_direction(Direction { x: 1, y: 1 })
-        .is_some_and(is_threatened_diagonally_upwards)
-    {
-        return true;
-    }
-    if get_game_piece_in_direction(Direction { x: -1, y: 1 })
-        .is_some_and(is_threatened_diagonally_upwards)
-    {
-        return true;
-    }
-
-    if get_game_piece_in_direction(Direction { x: 1, y: -1 })
-        .is_some_and(is_threatened_diagonally_downwards)
-    {
-        return true;
-    }
-    if get_game_piece_in_direction(Direction { x: -1, y: -1 })
-        .is_some_and(is_threatened_diagonally_downwards)
-    {
-        return true;
-    }
-
-    // At this point, every possible attack coming horizontally, vertically, and diagonally have
-    // been checked. Knights are the only piece that don't move in these ways, so they must be
-    // checked separately.
-    let is_enemy_knight_in_direction = |direction: Direction| {
-        if let Some((x_next, y_next)) = king_tile
-            .x
-            .checked_add_signed(direction.x)
-            .zip(king_tile.y.checked_add_signed(direction.y))
-        {
-            if x_next < MAP_SIZE.x && y_next < MAP_SIZE.y {
-                let tile_pos = TilePos {
-                    x: x_next,
-                    y: y_next,
-                };
-                // If the end tile is at this position, then that means any knight that could be at
-                // that position was captured, leaving the king safe from attack from that position.
-                if tile_pos == *end_tile {
-                    return false;
-                }
-                if get_game_piece_at_position(&tile_pos)
-                    == Some(GamePiece {
-                        piece: Piece::Knight,
-                        color: opposite_color,
-                    })
-                {
-                    return true;
-                }
-            }
-        }
-        false
-    };
+            })
+        })
+}
 
-    if is_enemy_knight_in_direction(Direction { x: 1, y: 2 }) {
-        return true;
-    }
-    if is_enemy_knight_in_direction(Direction { x: 2, y: 1 }) {
-        return true;
-    }
-    if is_enemy_knight_in_direction(Direction { x: 2, y: -1 }) {
-        return true;
-    }
-    if is_enemy_knight_in_direction(Direction { x: 1, y: -2 }) {
-        return true;
-    }
-    if is_enemy_knight_in_direction(Direction { x: -1, y: -2 }) {
-        return true;
-    }
-    if is_enemy_knight_in_direction(Direction { x: -2, y: -1 }) {
-        return true;
-    }
-    if is_enemy_knight_in_direction(Direction { x: -2, y: 1 }) {
-        return true;
-    }
-    if is_enemy_knight_in_direction(Direction { x: -1, y: 2 }) {
-        return true;
+pub fn record_last_move(
+    mut last_move: ResMut<LastMove>,
+    mut move_made_events: EventReader<MoveMade>,
+) {
+    for move_made in move_made_events.read() {
+        *last_move = LastMove(Some((move_made.from, move_made.to)));
     }
-
-    // This function checks every possible direction an attack could be coming from, so if the end
-    // of this function is reached, the king is not under attack.
-    false
 }
 
 pub fn highlight_tile(
-    tile_highlight_q: Query<(Option<&LegalMoves>, &TilePos), With<MouseoverHighlight>>,
-    mut tile_texture_q: Query<(&mut TileTextureIndex, &TilePos)>,
+    board_theme: Res<BoardTheme>,
+    last_move: Res<LastMove>,
+    move_history: Res<MoveHistory>,
+    checked_king_tile: Res<CheckedKingTile>,
+    legal_move_hints: Res<LegalMoveHints>,
+    premoves: Res<Premoves>,
+    selected_piece_original_position: Res<SelectedPieceOriginalPosition>,
+    tile_highlight_q: Query<&TilePos, With<MouseoverHighlight>>,
+    tile_legal_moves_q: Query<(&TilePos, &LegalMoves)>,
+    mut tile_texture_q: Query<(&mut TileTextureIndex, &TilePos, Option<&GamePiece>)>,
 ) {
-    let mut mouseover_tile = None;
-    // This vector needs no capacity. It will either remain empty or be overwritten by a new vector.
-    let mut legal_move_tiles = Vec::with_capacity(0);
+    let mouseover_tile = tile_highlight_q.get_single().ok();
 
-    if let Ok((legal_moves, tile_pos)) = tile_highlight_q.get_single() {
-        mouseover_tile = Some(tile_pos);
-        if let Some(legal_moves_some) = legal_moves {
-            // Highlighting tiles is only concerned with destination tiles, so map the legal moves
-            // to just a vector of destination tiles.
-            legal_move_tiles = legal_moves_some
-                .0
+    // Mark the legal moves of the carried piece for as long as it's carried, or otherwise of the
+    // piece under the cursor.
+    let mut legal_move_tiles = Vec::with_capacity(0);
+    if legal_move_hints.is_enabled && move_history.viewed_ply.is_none() {
+        if let Some(hinted_tile) = selected_piece_original_position
+            .0
+            .as_ref()
+            .or(mouseover_tile)
+        {
+            if let Some((_, legal_moves)) = tile_legal_moves_q
                 .iter()
-                .map(|legal_move| legal_move.destination)
-                .collect();
+                .find(|(tile_pos, _)| *tile_pos == hinted_tile)
+            {
+                // Highlighting tiles is only concerned with destination tiles, so map the legal
+                // moves to just a vector of destination tiles.
+                legal_move_tiles = legal_moves
+                    .0
+                    .iter()
+                    .map(|legal_move| legal_move.destination)
+                    .collect();
+            }
         }
     }
 
-    for (mut tile_texture_index, tile_pos) in &mut tile_texture_q {
-        if mouseover_tile == Some(tile_pos) {
-            *tile_texture_index = TileTextureIndex((tile_pos.x + tile_pos.y) % 2 + 2);
+    // While an earlier position is viewed, highlight the move leading to it instead, and don't
+    // highlight a king in check in the live position.
+    let (last_move, checked_king_tile) = match move_history.viewed_ply {
+        Some(0) => (None, None),
+        Some(ply) => {
+            let move_made = &move_history.moves[ply - 1].move_made;
+            (Some((move_made.from, move_made.to)), None)
+        }
+        None => (last_move.0, checked_king_tile.0),
+    };
+
+    for (mut tile_texture_index, tile_pos, game_piece) in &mut tile_texture_q {
+        // When a tile has several highlights, the one that matters most for the next action wins:
+        // the mouseover tile, then the legal moves, then the queued premoves, then the king in
+        // check, and finally the tiles of the last move. Legal moves onto another piece are
+        // captures, which are marked differently to quiet moves.
+        let layout = &board_theme.layout;
+        let offset = if mouseover_tile == Some(tile_pos) {
+            layout.mouseover
         } else if legal_move_tiles.contains(tile_pos) {
-            *tile_texture_index = TileTextureIndex((tile_pos.x + tile_pos.y) % 2 + 4);
+            if game_piece.is_some() {
+                layout.capture
+            } else {
+                layout.quiet_move
+            }
+        } else if premoves
+            .0
+            .iter()
+            .any(|premove| premove.from == *tile_pos || premove.to == *tile_pos)
+        {
+            layout.premove
+        } else if checked_king_tile == Some(*tile_pos) {
+            layout.check
+        } else if last_move.is_some_and(|(from, to)| from == *tile_pos || to == *tile_pos) {
+            layout.last_move
         } else {
-            *tile_texture_index = TileTextureIndex((tile_pos.x + tile_pos.y) % 2);
+            layout.plain
+        };
+        *tile_texture_index = TileTextureIndex((tile_pos.x + tile_pos.y) % 2 + offset);
+    }
+}
+
+// Shows the carried piece on the cursor, and hides the cursor while no piece is carried.
+pub fn update_cursor_piece(
+    selected_piece: Res<SelectedPiece>,
+    asset_server: Res<AssetServer>,
+    piece_set: Res<PieceSet>,
+    mut cursor_q: Query<(&mut Handle<Image>, &mut Visibility), With<CursorDisplay>>,
+) {
+    let Ok((mut cursor_handle, mut cursor_visibility)) = cursor_q.get_single_mut() else {
+        return;
+    };
+    match selected_piece.0 {
+        Some(game_piece) => {
+            *cursor_handle = asset_server.load(piece_set.get_asset_path(&game_piece));
+            *cursor_visibility = Visibility::Visible;
         }
+        None => *cursor_visibility = Visibility::Hidden,
     }
 }
 
@@ -0,0 +1,1884 @@
+use crate::components::{
+    AnnotationSprite, Color, EditorButton, EditorStatusText, GamePiece, LegalMoves, MenuButton,
+    Piece, PieceAnimation, SoundEffect, SoundEffectSource,
+};
+use crate::events::{
+    GameEndReason, GameEnded, GameResult, MoveMade, MoveRequested, SpecialMove, UndoRequested,
+};
+use crate::network::{
+    listen, parse_message, write_message, NetworkMessage, NetworkPeer, PeerRole, PeerState,
+    PROTOCOL_VERSION,
+};
+use crate::notation::{parse_annotations, parse_placement, parse_square, write_annotations};
+use crate::resources::{
+    AnnotationColor, AppState, BoardOrientation, BoardScale, BoardTheme, CheckedKingTile, Clocks,
+    ColorToMove, CursorPos, GameOutcome, GameTags, Handicap, InputMode, LastMove, LegalMoveHints,
+    LocalPlayer, MoveAnimation, MoveHistory, NetworkGame, NewGameSettings, Odds, OfferPolicy,
+    Opponent, PendingOffer, PieceSet, PositionEditor, Premoves, RightToCastle, SaveFile,
+    SelectedPiece, SelectedPieceOriginalPosition, SettingsFile, SoundSettings, StartingPlacement,
+    ThemeCatalog, TimeControl, UserSettings, Variant,
+};
+use crate::save::{parse_saved_game, write_saved_game, SAVE_FORMAT_VERSION};
+use crate::settings::{parse_settings, write_settings};
+use crate::system::orientation::get_tile_center;
+use crate::theme::find_themes;
+use crate::{
+    ChessAudioPlugin, ChessInputPlugin, ChessNetworkPlugin, ChessPlugin, ChessRenderingPlugin,
+    ChessSettingsPlugin, ChessUiPlugin,
+};
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::window::{PrimaryWindow, WindowResized, WindowResolution};
+use bevy_ecs_tilemap::prelude::*;
+use std::path::PathBuf;
+
+// Builds the game without a window, renderer or audio, so it can be run by tests. No asset loaders
+// are registered, so the images the game asks for are never actually loaded, but handles to them
+// can still be created.
+fn build_headless_app(variant: Variant, starting_placement: Option<&str>, odds: Odds) -> App {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin))
+        .init_asset::<Image>()
+        .init_resource::<ButtonInput<MouseButton>>()
+        .init_resource::<ButtonInput<KeyCode>>()
+        .add_event::<CursorMoved>()
+        .add_event::<WindowResized>()
+        .insert_resource(variant)
+        .insert_resource(odds)
+        // Tests that save games choose their own file.
+        .insert_resource(SaveFile(None))
+        // Tests never read or write the player's settings.
+        .insert_resource(SettingsFile::new(None));
+    if let Some(starting_placement) = starting_placement {
+        app.insert_resource(StartingPlacement(starting_placement.to_string()));
+    }
+    // Skip the menus and start playing straight away.
+    app.insert_state(AppState::Playing);
+    app.add_plugins(ChessPlugin);
+    // Run the startup systems and calculate the first legal moves.
+    app.update();
+    app
+}
+
+fn get_tile_pos(square: &str) -> TilePos {
+    parse_square(square).expect("Square should be valid")
+}
+
+// Moves the cursor over the tile and clicks the left mouse button, running the game for a frame
+// with the cursor over the tile and then another frame with the button pressed.
+fn click_tile(app: &mut App, tile_pos: TilePos) {
+    let map_size = app.world().resource::<Variant>().get_map_size();
+    let tile_center = get_tile_center(
+        &tile_pos,
+        &map_size,
+        app.world().resource::<BoardOrientation>(),
+    );
+    app.world_mut().resource_mut::<CursorPos>().0 = tile_center;
+    app.update();
+
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .press(MouseButton::Left);
+    app.update();
+
+    // Release the button and clear its state, as the input plugin would between frames.
+    let mut mouse = app.world_mut().resource_mut::<ButtonInput<MouseButton>>();
+    mouse.release(MouseButton::Left);
+    mouse.clear();
+}
+
+// Plays moves given in coordinate notation such as "e2e4", by clicking the piece to pick it up and
+// then clicking the tile to put it down.
+fn play_moves(app: &mut App, moves: &[&str]) {
+    for coordinates in moves {
+        // The destination starts at the second letter, as the origin's rank may have two digits.
+        let split = coordinates
+            .char_indices()
+            .skip(1)
+            .find(|(_, letter)| letter.is_ascii_alphabetic())
+            .map(|(index, _)| index)
+            .expect("Move should have a destination");
+        click_tile(app, get_tile_pos(&coordinates[..split]));
+        click_tile(app, get_tile_pos(&coordinates[split..]));
+    }
+}
+
+// Returns the game piece on the given square, if any.
+fn get_game_piece(app: &mut App, square: &str) -> Option<GamePiece> {
+    let square = get_tile_pos(square);
+    app.world_mut()
+        .query::<(&TilePos, &GamePiece)>()
+        .iter(app.world())
+        .find(|(tile_pos, _)| **tile_pos == square)
+        .map(|(_, game_piece)| *game_piece)
+}
+
+fn white(piece: Piece) -> Option<GamePiece> {
+    Some(GamePiece {
+        piece,
+        color: Color::White,
+    })
+}
+
+fn black(piece: Piece) -> Option<GamePiece> {
+    Some(GamePiece {
+        piece,
+        color: Color::Black,
+    })
+}
+
+#[test]
+fn pawn_moves_two_tiles_from_starting_rank() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["e2e4"]);
+
+    assert_eq!(get_game_piece(&mut app, "e2"), None);
+    assert_eq!(get_game_piece(&mut app, "e4"), white(Piece::Pawn));
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::Black);
+}
+
+#[test]
+fn illegal_move_keeps_piece_selected() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["e2e5"]);
+
+    assert_eq!(get_game_piece(&mut app, "e2"), white(Piece::Pawn));
+    assert_eq!(get_game_piece(&mut app, "e5"), None);
+    assert_eq!(
+        app.world().resource::<SelectedPiece>().0,
+        white(Piece::Pawn)
+    );
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::White);
+}
+
+#[test]
+fn clicking_a_flipped_board_selects_the_tile_shown_there() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    press_key(&mut app, KeyCode::KeyF);
+    assert!(app.world().resource::<BoardOrientation>().is_flipped);
+
+    // Flipped, e2 is shown where d7 is on an unflipped board.
+    let map_size = app.world().resource::<Variant>().get_map_size();
+    app.world_mut().resource_mut::<CursorPos>().0 =
+        get_tile_center(&get_tile_pos("d7"), &map_size, &BoardOrientation::default());
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .press(MouseButton::Left);
+    app.update();
+
+    assert_eq!(
+        app.world().resource::<SelectedPiece>().0,
+        white(Piece::Pawn)
+    );
+    assert_eq!(
+        app.world().resource::<SelectedPieceOriginalPosition>().0,
+        Some(get_tile_pos("e2"))
+    );
+}
+
+#[test]
+fn kingside_castling_moves_rook() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(
+        &mut app,
+        &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6", "e1g1"],
+    );
+
+    assert_eq!(get_game_piece(&mut app, "g1"), white(Piece::King));
+    assert_eq!(get_game_piece(&mut app, "f1"), white(Piece::Rook));
+    assert_eq!(get_game_piece(&mut app, "h1"), None);
+    let right_to_castle = app.world().resource::<RightToCastle>();
+    assert!(!right_to_castle.white_kingside && !right_to_castle.white_queenside);
+    assert!(right_to_castle.black_kingside && right_to_castle.black_queenside);
+}
+
+#[test]
+fn capturing_a_corner_rook_removes_castling_right() {
+    let mut app = build_headless_app(
+        Variant::Standard,
+        Some("r3k2r/8/8/8/8/8/1B6/4K3"),
+        Odds::default(),
+    );
+    play_moves(&mut app, &["b2h8"]);
+
+    assert_eq!(get_game_piece(&mut app, "h8"), white(Piece::Bishop));
+    let right_to_castle = app.world().resource::<RightToCastle>();
+    assert!(!right_to_castle.black_kingside && right_to_castle.black_queenside);
+
+    // Black can't castle with the rook that was captured.
+    play_moves(&mut app, &["e8g8"]);
+    assert_eq!(get_game_piece(&mut app, "e8"), black(Piece::King));
+    assert_eq!(get_game_piece(&mut app, "h8"), white(Piece::Bishop));
+    assert_eq!(get_game_piece(&mut app, "f8"), None);
+}
+
+#[test]
+fn pinned_piece_cannot_move() {
+    let mut app = build_headless_app(
+        Variant::Standard,
+        Some("4k3/4r3/8/8/8/8/4B3/4K3"),
+        Odds::default(),
+    );
+    play_moves(&mut app, &["e2d3"]);
+
+    assert_eq!(get_game_piece(&mut app, "e2"), white(Piece::Bishop));
+    assert_eq!(get_game_piece(&mut app, "d3"), None);
+    assert_eq!(app.world().resource::<SelectedPiece>().0, None);
+}
+
+#[test]
+fn archbishop_moves_like_knight() {
+    let mut app = build_headless_app(Variant::Capablanca, None, Odds::default());
+    play_moves(&mut app, &["c1d3"]);
+
+    assert_eq!(get_game_piece(&mut app, "d3"), white(Piece::Archbishop));
+}
+
+#[test]
+fn horde_pawn_moves_two_tiles_from_first_rank() {
+    let mut app = build_headless_app(Variant::Horde, Some("4k3/8/8/8/8/8/8/P7"), Odds::default());
+    play_moves(&mut app, &["a1a3"]);
+
+    assert_eq!(get_game_piece(&mut app, "a3"), white(Piece::Pawn));
+}
+
+#[test]
+fn rook_odds_removes_castling_right() {
+    let mut app = build_headless_app(
+        Variant::Standard,
+        None,
+        Odds(Some((Handicap::Rook, Color::White))),
+    );
+
+    assert_eq!(get_game_piece(&mut app, "a1"), None);
+    let right_to_castle = app.world().resource::<RightToCastle>();
+    assert!(!right_to_castle.white_queenside && right_to_castle.white_kingside);
+}
+
+#[test]
+fn odds_that_cannot_be_given_are_dropped() {
+    // White has no rook to give in Horde, so the game is played without odds.
+    let mut app = build_headless_app(
+        Variant::Horde,
+        None,
+        Odds(Some((Handicap::Rook, Color::White))),
+    );
+    assert_eq!(get_game_piece(&mut app, "a1"), white(Piece::Pawn));
+    assert!(!app.world().resource::<GameTags>().to_pgn().contains("Odds"));
+
+    // Starting the game from the new game screen drops them.
+    set_app_state(&mut app, AppState::NewGameSetup);
+    press_menu_button(&mut app, MenuButton::Start);
+    assert_eq!(
+        *app.world().resource::<State<AppState>>().get(),
+        AppState::Playing
+    );
+    assert!(app.world().resource::<Odds>().0.is_none());
+}
+
+#[test]
+fn capture_sends_move_made() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["e2e4", "d7d5", "e4d5"]);
+
+    let events = app.world().resource::<Events<MoveMade>>();
+    let move_made = events.get_reader().read(events).last().copied();
+    assert_eq!(
+        move_made,
+        Some(MoveMade {
+            from: get_tile_pos("e4"),
+            to: get_tile_pos("d5"),
+            game_piece: white(Piece::Pawn).unwrap(),
+            captured: Some(GamePiece {
+                piece: Piece::Pawn,
+                color: Color::Black,
+            }),
+            special_move: None,
+        })
+    );
+}
+
+#[test]
+fn checkmate_ends_game() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+
+    let events = app.world().resource::<Events<GameEnded>>();
+    let game_ended = events.get_reader().read(events).last().copied();
+    assert_eq!(
+        game_ended,
+        Some(GameEnded {
+            result: GameResult::Win(Color::Black),
+            reason: GameEndReason::Checkmate,
+        })
+    );
+    assert_eq!(
+        app.world().resource::<CheckedKingTile>().0,
+        Some(get_tile_pos("e1"))
+    );
+    assert_eq!(
+        app.world().resource::<LastMove>().0,
+        Some((get_tile_pos("d8"), get_tile_pos("h4")))
+    );
+}
+
+#[test]
+fn horde_ends_when_all_pieces_captured() {
+    let mut app = build_headless_app(Variant::Horde, Some("8/8/8/1k6/8/P7/8/8"), Odds::default());
+    play_moves(&mut app, &["a3a4", "b5a4"]);
+
+    let events = app.world().resource::<Events<GameEnded>>();
+    let game_ended = events.get_reader().read(events).last().copied();
+    assert_eq!(
+        game_ended,
+        Some(GameEnded {
+            result: GameResult::Win(Color::Black),
+            reason: GameEndReason::AllPiecesCaptured,
+        })
+    );
+}
+
+fn get_sans(app: &App) -> Vec<String> {
+    app.world()
+        .resource::<MoveHistory>()
+        .moves
+        .iter()
+        .map(|recorded_move| recorded_move.san.clone())
+        .collect()
+}
+
+#[test]
+fn moves_are_recorded_in_san() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(
+        &mut app,
+        &[
+            "e2e4", "d7d5", "e4d5", "d8d5", "g1f3", "d5e5", "f1e2", "e5e2",
+        ],
+    );
+
+    assert_eq!(
+        get_sans(&app),
+        ["e4", "d5", "exd5", "Qxd5", "Nf3", "Qe5+", "Be2", "Qxe2+"]
+    );
+}
+
+#[test]
+fn castling_and_ambiguous_moves_are_recorded_in_san() {
+    let mut app = build_headless_app(
+        Variant::Standard,
+        Some("r3k3/8/8/8/8/8/7K/R6R"),
+        Odds::default(),
+    );
+    play_moves(&mut app, &["a1b1", "e8c8"]);
+
+    assert_eq!(get_sans(&app), ["Rab1", "O-O-O"]);
+}
+
+#[test]
+fn earlier_position_is_read_only() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["e2e4", "e7e5"]);
+    app.world_mut().resource_mut::<MoveHistory>().viewed_ply = Some(1);
+    play_moves(&mut app, &["g1f3"]);
+
+    assert_eq!(get_game_piece(&mut app, "f3"), None);
+    assert_eq!(get_sans(&app), ["e4", "e5"]);
+}
+
+#[test]
+fn captured_pieces_are_tracked_per_side() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["e2e4", "d7d5", "e4d5", "d8d5", "b1c3"]);
+
+    let move_history = app.world().resource::<MoveHistory>();
+    let black_pawn = GamePiece {
+        piece: Piece::Pawn,
+        color: Color::Black,
+    };
+    assert_eq!(
+        move_history.get_captured_pieces(Color::White, 5),
+        [black_pawn]
+    );
+    assert_eq!(
+        move_history.get_captured_pieces(Color::Black, 5),
+        [white(Piece::Pawn).unwrap()]
+    );
+    assert_eq!(move_history.get_captured_pieces(Color::Black, 3), []);
+}
+
+// Returns whether the piece sprite on the given square is shown.
+fn is_sprite_visible(app: &mut App, square: &str) -> bool {
+    let square = get_tile_pos(square);
+    app.world_mut()
+        .query::<(&TilePos, &Visibility)>()
+        .iter(app.world())
+        .find(|(tile_pos, _)| **tile_pos == square)
+        .is_some_and(|(_, visibility)| *visibility == Visibility::Visible)
+}
+
+#[test]
+fn requested_move_is_animated() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut().send_event(MoveRequested {
+        from: get_tile_pos("g1"),
+        to: get_tile_pos("f3"),
+    });
+    app.update();
+
+    assert_eq!(get_game_piece(&mut app, "f3"), white(Piece::Knight));
+    let animation_count = app
+        .world_mut()
+        .query::<&PieceAnimation>()
+        .iter(app.world())
+        .count();
+    assert_eq!(animation_count, 1);
+    // The knight is only shown on its destination once it has finished sliding there.
+    assert!(!is_sprite_visible(&mut app, "f3"));
+    assert!(!is_sprite_visible(&mut app, "g1"));
+}
+
+#[test]
+fn rules_are_played_without_the_other_plugins() {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, AssetPlugin::default(), StatesPlugin))
+        .init_resource::<ButtonInput<KeyCode>>()
+        .insert_resource(SaveFile(None))
+        .insert_state(AppState::Playing)
+        .add_plugins(
+            ChessPlugin
+                .build()
+                .disable::<ChessSettingsPlugin>()
+                .disable::<ChessInputPlugin>()
+                .disable::<ChessRenderingPlugin>()
+                .disable::<ChessUiPlugin>()
+                .disable::<ChessAudioPlugin>()
+                .disable::<ChessNetworkPlugin>(),
+        );
+    app.update();
+    app.world_mut().send_event(MoveRequested {
+        from: get_tile_pos("e2"),
+        to: get_tile_pos("e4"),
+    });
+    app.update();
+
+    assert_eq!(get_game_piece(&mut app, "e4"), white(Piece::Pawn));
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::Black);
+    assert!(!app.world().contains_resource::<BoardTheme>());
+    assert!(!app.world().contains_resource::<SoundSettings>());
+    assert!(!app.world().contains_resource::<SettingsFile>());
+}
+
+#[test]
+fn takebacks_are_animated_and_restore_the_clocks() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    *app.world_mut().resource_mut::<Clocks>() = Clocks::new(Some(TimeControl {
+        minutes: 5,
+        increment_seconds: 3,
+    }));
+    play_moves(&mut app, &["e2e4", "e7e5"]);
+    let recorded_clocks = app.world().resource::<MoveHistory>().moves[0]
+        .clocks
+        .expect("Clocks should be recorded with the move");
+    app.world_mut().resource_mut::<Clocks>().white_seconds = 10.0;
+
+    app.world_mut().send_event(UndoRequested { plies: 1 });
+    app.update();
+    let clocks = app.world().resource::<Clocks>();
+    assert_eq!(clocks.white_seconds, recorded_clocks.white_seconds);
+    assert_eq!(clocks.black_seconds, recorded_clocks.black_seconds);
+
+    // The pawn slides back from where it went, and is only shown there once it arrives.
+    assert_eq!(get_game_piece(&mut app, "e7"), black(Piece::Pawn));
+    let map_size = app.world().resource::<Variant>().get_map_size();
+    let orientation = BoardOrientation::default();
+    let animations: Vec<(Vec2, Vec2)> = app
+        .world_mut()
+        .query::<&PieceAnimation>()
+        .iter(app.world())
+        .map(|animation| (animation.start, animation.end))
+        .collect();
+    assert_eq!(
+        animations,
+        [(
+            get_tile_center(&get_tile_pos("e5"), &map_size, &orientation),
+            get_tile_center(&get_tile_pos("e7"), &map_size, &orientation),
+        )]
+    );
+    assert!(!is_sprite_visible(&mut app, "e7"));
+
+    // Taking back every move gives both sides their starting time.
+    app.world_mut().send_event(UndoRequested { plies: 1 });
+    app.update();
+    assert_eq!(app.world().resource::<Clocks>().white_seconds, 300.0);
+}
+
+#[test]
+fn illegal_requested_move_is_ignored() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut().send_event(MoveRequested {
+        from: get_tile_pos("g1"),
+        to: get_tile_pos("g3"),
+    });
+    app.update();
+
+    assert_eq!(get_game_piece(&mut app, "g1"), white(Piece::Knight));
+    assert_eq!(get_game_piece(&mut app, "g3"), None);
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::White);
+}
+
+#[test]
+fn sprites_show_viewed_position() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut().resource_mut::<MoveAnimation>().duration = 0.0;
+    play_moves(&mut app, &["e2e4"]);
+    assert!(is_sprite_visible(&mut app, "e4"));
+    assert!(!is_sprite_visible(&mut app, "e2"));
+
+    app.world_mut().resource_mut::<MoveHistory>().viewed_ply = Some(0);
+    app.update();
+    assert!(!is_sprite_visible(&mut app, "e4"));
+    assert!(is_sprite_visible(&mut app, "e2"));
+}
+
+// Returns the sound effects waiting to be played, which are never played without an audio device.
+fn get_sound_effects(app: &mut App) -> Vec<SoundEffect> {
+    app.world_mut()
+        .query::<&SoundEffectSource>()
+        .iter(app.world())
+        .map(|sound_effect_source| sound_effect_source.0)
+        .collect()
+}
+
+#[test]
+fn moves_play_the_most_important_sound() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["e2e4", "d7d5"]);
+    assert_eq!(get_sound_effects(&mut app), vec![SoundEffect::Move]);
+    play_moves(&mut app, &["e4d5"]);
+    assert_eq!(get_sound_effects(&mut app), vec![SoundEffect::Capture]);
+    play_moves(&mut app, &["e8d7", "d1g4"]);
+    assert_eq!(get_sound_effects(&mut app), vec![SoundEffect::Check]);
+}
+
+#[test]
+fn running_low_on_time_plays_a_warning_once() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.insert_resource(LocalPlayer(Some(Color::White)));
+    *app.world_mut().resource_mut::<Clocks>() = Clocks::new(Some(TimeControl {
+        minutes: 1,
+        increment_seconds: 0,
+    }));
+    app.update();
+    assert!(get_sound_effects(&mut app).is_empty());
+
+    // The opponent running low isn't warned about.
+    app.world_mut().resource_mut::<Clocks>().black_seconds = 9.0;
+    app.update();
+    assert!(get_sound_effects(&mut app).is_empty());
+
+    app.world_mut().resource_mut::<Clocks>().white_seconds = 9.0;
+    app.update();
+    assert_eq!(get_sound_effects(&mut app), vec![SoundEffect::LowTime]);
+    let sound_effect_ids: Vec<Entity> = app
+        .world_mut()
+        .query_filtered::<Entity, With<SoundEffectSource>>()
+        .iter(app.world())
+        .collect();
+    for sound_effect_id in sound_effect_ids {
+        app.world_mut().despawn(sound_effect_id);
+    }
+    app.update();
+    app.update();
+    assert!(get_sound_effects(&mut app).is_empty());
+}
+
+#[test]
+fn muted_moves_are_silent() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut().resource_mut::<SoundSettings>().is_muted = true;
+    play_moves(&mut app, &["e2e4"]);
+    assert!(get_sound_effects(&mut app).is_empty());
+}
+
+// Makes a move for the side that isn't played with the mouse, such as an engine or a network
+// opponent, and runs the game for long enough to make any premove that follows.
+fn play_opponent_move(app: &mut App, coordinates: &str) {
+    app.world_mut().send_event(MoveRequested {
+        from: get_tile_pos(&coordinates[..2]),
+        to: get_tile_pos(&coordinates[2..]),
+    });
+    app.update();
+    app.update();
+}
+
+#[test]
+fn premoves_are_made_after_opponent_moves() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut().resource_mut::<LocalPlayer>().0 = Some(Color::White);
+    play_moves(&mut app, &["e2e4", "g1f3", "f3g5"]);
+    assert_eq!(app.world().resource::<Premoves>().0.len(), 2);
+    assert_eq!(get_game_piece(&mut app, "g1"), white(Piece::Knight));
+
+    play_opponent_move(&mut app, "e7e6");
+    assert_eq!(get_game_piece(&mut app, "f3"), white(Piece::Knight));
+    assert_eq!(app.world().resource::<Premoves>().0.len(), 1);
+
+    play_opponent_move(&mut app, "d7d6");
+    assert_eq!(get_game_piece(&mut app, "g5"), white(Piece::Knight));
+    assert!(app.world().resource::<Premoves>().0.is_empty());
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::Black);
+}
+
+#[test]
+fn illegal_premove_is_cancelled() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut().resource_mut::<LocalPlayer>().0 = Some(Color::White);
+    play_moves(&mut app, &["e2e4", "e4e5"]);
+    play_opponent_move(&mut app, "e7e5");
+
+    assert_eq!(get_game_piece(&mut app, "e4"), white(Piece::Pawn));
+    assert!(app.world().resource::<Premoves>().0.is_empty());
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::White);
+}
+
+#[test]
+fn right_click_cancels_premoves() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut().resource_mut::<LocalPlayer>().0 = Some(Color::White);
+    play_moves(&mut app, &["e2e4", "d2d4"]);
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .press(MouseButton::Right);
+    app.update();
+    assert!(app.world().resource::<Premoves>().0.is_empty());
+
+    play_opponent_move(&mut app, "e7e5");
+    assert_eq!(get_game_piece(&mut app, "d2"), white(Piece::Pawn));
+}
+
+// Presses the right mouse button over one tile and releases it over another, which may be the same
+// tile.
+fn right_drag(app: &mut App, from: &str, to: &str) {
+    let map_size = app.world().resource::<Variant>().get_map_size();
+    let orientation = app.world().resource::<BoardOrientation>();
+    let from_center = get_tile_center(&get_tile_pos(from), &map_size, orientation);
+    let to_center = get_tile_center(&get_tile_pos(to), &map_size, orientation);
+
+    app.world_mut().resource_mut::<CursorPos>().0 = from_center;
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .press(MouseButton::Right);
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .clear();
+
+    app.world_mut().resource_mut::<CursorPos>().0 = to_center;
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .release(MouseButton::Right);
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .clear();
+}
+
+#[test]
+fn annotations_are_drawn_and_cleared_by_the_next_move() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    right_drag(&mut app, "e2", "e4");
+    app.world_mut()
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::ShiftLeft);
+    right_drag(&mut app, "d5", "d5");
+    app.world_mut()
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .release(KeyCode::ShiftLeft);
+
+    let annotations = app.world().resource::<MoveHistory>().get_annotations(0);
+    assert_eq!(
+        write_annotations(annotations),
+        "[%csl Rd5][%cal Ge2e4]".to_string()
+    );
+    let annotation_sprite_count = app
+        .world_mut()
+        .query::<&AnnotationSprite>()
+        .iter(app.world())
+        .count();
+    // The mark is one sprite, and the arrow is a line and a head.
+    assert_eq!(annotation_sprite_count, 3);
+
+    play_moves(&mut app, &["e2e4"]);
+    let annotation_sprite_count = app
+        .world_mut()
+        .query::<&AnnotationSprite>()
+        .iter(app.world())
+        .count();
+    assert_eq!(annotation_sprite_count, 0);
+    assert_eq!(
+        app.world().resource::<MoveHistory>().write_movetext(),
+        "{[%csl Rd5][%cal Ge2e4]} 1. e4"
+    );
+}
+
+#[test]
+fn annotations_are_parsed_from_pgn_comments() {
+    let annotations = parse_annotations("Good move [%cal Ge2e4,Rb1c3] [%csl Yd5,Xe5]");
+    assert_eq!(annotations.arrows.len(), 2);
+    assert_eq!(annotations.arrows[1].from, get_tile_pos("b1"));
+    assert_eq!(annotations.arrows[1].to, get_tile_pos("c3"));
+    assert_eq!(annotations.arrows[1].color, AnnotationColor::Red);
+    // The mark in an unknown color is ignored.
+    assert_eq!(annotations.square_marks.len(), 1);
+    assert_eq!(
+        write_annotations(&annotations),
+        "[%csl Yd5][%cal Ge2e4,Rb1c3]"
+    );
+}
+
+#[test]
+fn placements_with_too_many_empty_tiles_are_rejected() {
+    let map_size = Variant::Standard.get_map_size();
+    let placement = "4k3/8/8/8/8/8/8/4K3";
+    assert!(parse_placement(placement, &map_size).is_ok());
+    // Counts too large to add up, or even to hold, are errors rather than overflowing.
+    for rank in ["99999999999", "1K4294967295", "4294967295K"] {
+        let placement = placement.replacen("8", rank, 1);
+        assert_eq!(
+            parse_placement(&placement, &map_size),
+            Err(format!("Rank \"{rank}\" is wider than the board's 8 tiles"))
+        );
+    }
+}
+
+// Switches to the state and runs the game for a frame, which makes the transition.
+fn set_app_state(app: &mut App, app_state: AppState) {
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(app_state);
+    app.update();
+}
+
+#[test]
+fn game_over_and_new_game_start_afresh() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+    app.update();
+    assert_eq!(
+        *app.world().resource::<State<AppState>>().get(),
+        AppState::GameOver
+    );
+    let game_tags = app.world().resource::<GameTags>();
+    assert!(game_tags.to_pgn().contains("[Result \"0-1\"]"));
+
+    // Leaving the game removes the board.
+    set_app_state(&mut app, AppState::MainMenu);
+    let tilemap_count = app
+        .world_mut()
+        .query::<&TileStorage>()
+        .iter(app.world())
+        .count();
+    assert_eq!(tilemap_count, 0);
+
+    set_app_state(&mut app, AppState::Playing);
+    assert_eq!(get_game_piece(&mut app, "d8"), black(Piece::Queen));
+    assert_eq!(get_game_piece(&mut app, "h4"), None);
+    assert!(app.world().resource::<MoveHistory>().moves.is_empty());
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::White);
+    play_moves(&mut app, &["e2e4"]);
+    assert_eq!(get_game_piece(&mut app, "e4"), white(Piece::Pawn));
+}
+
+fn press_key(app: &mut App, key: KeyCode) {
+    app.world_mut()
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(key);
+    app.update();
+
+    // Release the key and clear its state, as the input plugin would between frames.
+    let mut keyboard = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+    keyboard.release(key);
+    keyboard.clear();
+}
+
+// Places the game piece on the square with the position editor, or removes the piece if it's None.
+fn edit_square(app: &mut App, square: &str, game_piece: Option<GamePiece>) {
+    app.world_mut()
+        .resource_mut::<PositionEditor>()
+        .palette_piece = game_piece;
+    click_tile(app, get_tile_pos(square));
+    app.update();
+}
+
+fn press_editor_button(app: &mut App, editor_button: EditorButton) {
+    let button = app
+        .world_mut()
+        .query::<(Entity, &EditorButton)>()
+        .iter(app.world())
+        .find_map(|(entity, other)| (*other == editor_button).then_some(entity))
+        .expect("Editor button should be on the screen");
+    app.world_mut()
+        .entity_mut(button)
+        .insert(Interaction::Pressed);
+    app.update();
+    app.update();
+}
+
+fn get_editor_status(app: &mut App) -> String {
+    app.world_mut()
+        .query_filtered::<&Text, With<EditorStatusText>>()
+        .single(app.world())
+        .sections[0]
+        .value
+        .clone()
+}
+
+fn get_legal_destinations(app: &mut App, square: &str) -> Vec<TilePos> {
+    let tile_pos = get_tile_pos(square);
+    app.world_mut()
+        .query::<(&TilePos, &LegalMoves)>()
+        .iter(app.world())
+        .find(|(other, _)| **other == tile_pos)
+        .map(|(_, legal_moves)| {
+            legal_moves
+                .0
+                .iter()
+                .map(|legal_move| legal_move.destination)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+fn position_editor_only_plays_from_legal_positions() {
+    let mut app = build_headless_app(
+        Variant::Standard,
+        Some("4k3/8/8/8/8/8/8/4K3"),
+        Odds::default(),
+    );
+    press_key(&mut app, KeyCode::KeyE);
+    assert!(app.world().resource::<PositionEditor>().is_active);
+
+    // Each mistake is reported in turn, and the editor stays open until it's fixed.
+    edit_square(&mut app, "d1", white(Piece::King));
+    press_editor_button(&mut app, EditorButton::PlayFromHere);
+    assert_eq!(
+        get_editor_status(&mut app),
+        "White must have exactly one king"
+    );
+    assert!(app.world().resource::<PositionEditor>().is_active);
+    edit_square(&mut app, "d1", None);
+
+    edit_square(&mut app, "a8", white(Piece::Pawn));
+    press_editor_button(&mut app, EditorButton::PlayFromHere);
+    assert_eq!(
+        get_editor_status(&mut app),
+        "White has a pawn on a back rank"
+    );
+    edit_square(&mut app, "a8", None);
+    edit_square(&mut app, "h8", black(Piece::Pawn));
+    press_editor_button(&mut app, EditorButton::PlayFromHere);
+    assert_eq!(
+        get_editor_status(&mut app),
+        "Black has a pawn on a back rank"
+    );
+    edit_square(&mut app, "h8", None);
+
+    // It's white's move, so black can't already be in check.
+    edit_square(&mut app, "e4", white(Piece::Rook));
+    press_editor_button(&mut app, EditorButton::PlayFromHere);
+    assert_eq!(
+        get_editor_status(&mut app),
+        "Black can't be in check while it's White's move"
+    );
+    assert!(app.world().resource::<PositionEditor>().is_active);
+
+    press_editor_button(&mut app, EditorButton::ColorToMove);
+    press_editor_button(&mut app, EditorButton::PlayFromHere);
+    assert_eq!(get_editor_status(&mut app), "");
+    assert!(!app.world().resource::<PositionEditor>().is_active);
+}
+
+#[test]
+fn playing_from_an_edited_position_starts_a_new_history() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["e2e4", "e7e5"]);
+    press_key(&mut app, KeyCode::KeyE);
+
+    // Clearing the kingside lets white castle, as the king and rook haven't moved.
+    edit_square(&mut app, "f1", None);
+    edit_square(&mut app, "g1", None);
+    press_editor_button(&mut app, EditorButton::PlayFromHere);
+    assert!(!app.world().resource::<PositionEditor>().is_active);
+    app.update();
+
+    let move_history = app.world().resource::<MoveHistory>();
+    assert!(move_history.moves.is_empty());
+    let starting_position = move_history
+        .starting_position
+        .as_ref()
+        .expect("Starting position should be set");
+    assert_eq!(starting_position.color_to_move, Color::White);
+    assert_eq!(starting_position.pieces.len(), 30);
+    let mut king_destinations = get_legal_destinations(&mut app, "e1");
+    king_destinations.sort_by_key(|tile_pos| (tile_pos.x, tile_pos.y));
+    assert_eq!(
+        king_destinations,
+        [get_tile_pos("e2"), get_tile_pos("f1"), get_tile_pos("g1")]
+    );
+    assert!(get_legal_destinations(&mut app, "e7").is_empty());
+
+    play_moves(&mut app, &["e1g1"]);
+    assert_eq!(get_sans(&app), ["O-O"]);
+}
+
+fn get_game_outcome(app: &App) -> Option<GameEnded> {
+    app.world().resource::<GameOutcome>().0
+}
+
+// Pauses the game and presses one of the pause menu's buttons, which goes back to the game.
+fn press_pause_menu_button(app: &mut App, menu_button: MenuButton) {
+    set_app_state(app, AppState::Paused);
+    press_menu_button(app, menu_button);
+    app.update();
+}
+
+#[test]
+fn resigning_ends_the_game_and_records_the_result() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    play_moves(&mut app, &["e2e4"]);
+
+    // Both sides are played here, so the side to move resigns.
+    press_pause_menu_button(&mut app, MenuButton::Resign);
+    assert_eq!(
+        get_game_outcome(&app),
+        Some(GameEnded {
+            result: GameResult::Win(Color::White),
+            reason: GameEndReason::Resignation,
+        })
+    );
+    assert_eq!(
+        *app.world().resource::<State<AppState>>().get(),
+        AppState::GameOver
+    );
+    let game_tags = app.world().resource::<GameTags>().to_pgn();
+    assert!(game_tags.contains("[Result \"1-0\"]"), "{game_tags}");
+    assert!(
+        game_tags.contains("[Termination \"White wins by resignation\"]"),
+        "{game_tags}"
+    );
+}
+
+#[test]
+fn offers_to_an_engine_are_answered_by_its_policy() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.insert_resource(LocalPlayer(Some(Color::White)));
+    app.insert_resource(NewGameSettings {
+        opponent: Opponent::Remote,
+        ..default()
+    });
+    play_moves(&mut app, &["e2e4"]);
+    app.world_mut().send_event(MoveRequested {
+        from: get_tile_pos("e7"),
+        to: get_tile_pos("e5"),
+    });
+    app.update();
+    assert_eq!(get_sans(&app), ["e4", "e5"]);
+
+    // Takebacks are accepted, taking back the engine's reply along with the move asked about.
+    press_pause_menu_button(&mut app, MenuButton::RequestTakeback);
+    app.update();
+    assert!(get_sans(&app).is_empty());
+    assert_eq!(get_game_piece(&mut app, "e2"), white(Piece::Pawn));
+    assert_eq!(get_game_piece(&mut app, "e5"), None);
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::White);
+    assert!(app.world().resource::<PendingOffer>().0.is_none());
+    play_moves(&mut app, &["d2d4"]);
+    assert_eq!(get_sans(&app), ["d4"]);
+
+    // Draws are declined, unless the policy is changed.
+    press_pause_menu_button(&mut app, MenuButton::OfferDraw);
+    assert!(app.world().resource::<PendingOffer>().0.is_none());
+    assert_eq!(get_game_outcome(&app), None);
+    app.world_mut().resource_mut::<OfferPolicy>().accepts_draws = true;
+    press_pause_menu_button(&mut app, MenuButton::OfferDraw);
+    assert_eq!(
+        get_game_outcome(&app),
+        Some(GameEnded {
+            result: GameResult::Draw,
+            reason: GameEndReason::Agreement,
+        })
+    );
+}
+
+#[test]
+fn paused_game_cannot_be_played() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut app, AppState::Paused);
+    play_moves(&mut app, &["e2e4"]);
+    assert_eq!(get_game_piece(&mut app, "e2"), white(Piece::Pawn));
+
+    set_app_state(&mut app, AppState::Playing);
+    play_moves(&mut app, &["e2e4"]);
+    assert_eq!(get_game_piece(&mut app, "e4"), white(Piece::Pawn));
+}
+
+#[test]
+fn running_out_of_time_loses() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    *app.world_mut().resource_mut::<Clocks>() = Clocks {
+        time_control: Some(TimeControl {
+            minutes: 1,
+            increment_seconds: 0,
+        }),
+        white_seconds: 0.001,
+        black_seconds: 60.0,
+    };
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    app.update();
+    app.update();
+
+    let game_outcome = app.world().resource::<GameOutcome>();
+    assert_eq!(
+        game_outcome.0,
+        Some(GameEnded {
+            result: GameResult::Win(Color::Black),
+            reason: GameEndReason::Timeout,
+        })
+    );
+}
+
+// Returns a save file in the temporary directory, which is unique to the test.
+fn get_temp_save_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("bevy_chess_{name}_{}.txt", std::process::id()))
+}
+
+// Presses the menu button on the screen, and runs the game for another frame to make any state
+// transition it asks for.
+fn press_menu_button(app: &mut App, menu_button: MenuButton) {
+    let button = app
+        .world_mut()
+        .query::<(Entity, &MenuButton)>()
+        .iter(app.world())
+        .find_map(|(entity, other)| (*other == menu_button).then_some(entity))
+        .expect("Menu button should be on the screen");
+    app.world_mut()
+        .entity_mut(button)
+        .insert(Interaction::Pressed);
+    app.update();
+    app.update();
+}
+
+#[test]
+fn unfinished_game_is_saved_and_continued() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    let path = get_temp_save_file("continue");
+    app.insert_resource(SaveFile(Some(path.clone())));
+    play_moves(&mut app, &["e2e4", "e7e5", "g1f3"]);
+    right_drag(&mut app, "f3", "e5");
+    app.world_mut().resource_mut::<Clocks>().white_seconds = 42.5;
+
+    // Leaving the game saves it, and the main menu offers to continue it.
+    set_app_state(&mut app, AppState::MainMenu);
+    let text = std::fs::read_to_string(&path).expect("Game should be saved");
+    assert!(text.starts_with(&format!("bevy-chess save {SAVE_FORMAT_VERSION}\n")));
+    assert!(text
+        .contains("move g1f3 Nf3 rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 2"));
+
+    press_menu_button(&mut app, MenuButton::ContinueGame);
+    assert_eq!(
+        *app.world().resource::<State<AppState>>().get(),
+        AppState::Playing
+    );
+    assert_eq!(get_game_piece(&mut app, "g1"), None);
+    assert_eq!(get_game_piece(&mut app, "f3"), white(Piece::Knight));
+    assert_eq!(app.world().resource::<ColorToMove>().0, Color::Black);
+    assert_eq!(get_sans(&app), ["e4", "e5", "Nf3"]);
+    assert_eq!(
+        app.world().resource::<LastMove>().0,
+        Some((get_tile_pos("g1"), get_tile_pos("f3")))
+    );
+    let move_history = app.world().resource::<MoveHistory>();
+    assert_eq!(move_history.get_annotations(3).arrows.len(), 1);
+
+    play_moves(&mut app, &["b8c6"]);
+    assert_eq!(get_sans(&app), ["e4", "e5", "Nf3", "Nc6"]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn saved_game_is_written_back_the_same_and_older_saves_load() {
+    let text = "\
+bevy-chess save 1
+variant Standard
+tag Event Casual game
+start rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1
+move e2e4 e4 rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1
+annotations 1 [%cal Ge4d5]
+move-clocks 1 303 300
+move d7d5 d5 rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2
+move-clocks 2 296 302.5
+move e4d5 exd5 rnbqkbnr/ppp1pppp/8/3P4/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2
+clocks 5+3 287.5 300
+opponent remote
+play-as random
+local-player black
+board-flipped true
+auto-flip false
+";
+    let saved_game = parse_saved_game(text).expect("Save should parse");
+    assert_eq!(saved_game.moves[2].move_made.captured, black(Piece::Pawn));
+    let clocks = saved_game.moves[1].clocks.expect("Move should have clocks");
+    assert_eq!(clocks.time_control, saved_game.clocks.time_control);
+    assert_eq!((clocks.white_seconds, clocks.black_seconds), (296.0, 302.5));
+    // The clocks after the last move weren't saved, as with saves from before they were kept.
+    assert!(saved_game.moves[2].clocks.is_none());
+    assert_eq!(
+        saved_game.get_current_position().color_to_move,
+        Color::Black
+    );
+    assert_eq!(write_saved_game(&saved_game), text);
+
+    // Lines missing from older saves are left as they are for a new game, and lines from newer ones
+    // are skipped.
+    let saved_game = parse_saved_game(
+        "bevy-chess save 1\nstart 8/8/8/4k3/8/8/8/4K2R w K - 0 1\nmove e1g1 O-O 8/8/8/4k3/8/8/8/5RK1 b - - 0 1\nlater-line ignored\n",
+    )
+    .expect("Save without settings should parse");
+    assert_eq!(saved_game.clocks.time_control, None);
+    assert_eq!(saved_game.local_player, None);
+    assert_eq!(
+        saved_game.moves[0].move_made.special_move,
+        Some(SpecialMove::Castling {
+            rook_from: get_tile_pos("h1"),
+            rook_to: get_tile_pos("f1"),
+        })
+    );
+
+    assert!(parse_saved_game("bevy-chess save 99\n").is_err());
+    // Positions the game can't be played from are refused rather than set up.
+    assert!(parse_saved_game("bevy-chess save 1\nstart 8/8/8/8/8/8/8/4K3 w - - 0 1\n").is_err());
+}
+
+#[test]
+fn game_with_a_pawn_on_the_last_rank_is_saved_and_continued() {
+    let mut app = build_headless_app(
+        Variant::Standard,
+        Some("4k3/P7/8/8/8/8/8/4K3"),
+        Odds::default(),
+    );
+    let path = get_temp_save_file("last_rank");
+    app.insert_resource(SaveFile(Some(path.clone())));
+    play_moves(&mut app, &["a7a8"]);
+    assert_eq!(get_game_piece(&mut app, "a8"), white(Piece::Pawn));
+
+    set_app_state(&mut app, AppState::MainMenu);
+    press_menu_button(&mut app, MenuButton::ContinueGame);
+    assert_eq!(
+        *app.world().resource::<State<AppState>>().get(),
+        AppState::Playing
+    );
+    assert_eq!(get_game_piece(&mut app, "a8"), white(Piece::Pawn));
+    assert_eq!(get_sans(&app), ["a8"]);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn save_is_made_on_demand_and_deleted_when_the_game_ends() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    let path = get_temp_save_file("game_over");
+    app.insert_resource(SaveFile(Some(path.clone())));
+    play_moves(&mut app, &["f2f3", "e7e5"]);
+
+    let mut keyboard = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+    keyboard.press(KeyCode::KeyS);
+    app.update();
+    let mut keyboard = app.world_mut().resource_mut::<ButtonInput<KeyCode>>();
+    keyboard.release(KeyCode::KeyS);
+    keyboard.clear();
+    assert!(path.exists());
+
+    play_moves(&mut app, &["g2g4", "d8h4"]);
+    app.update();
+    assert!(!path.exists());
+}
+
+#[test]
+fn settings_are_written_back_the_same_and_hand_edits_are_read() {
+    let settings = UserSettings {
+        board_scale: 1.25,
+        input_mode: InputMode::DragAndDrop,
+        default_time_control: Some(TimeControl {
+            minutes: 5,
+            increment_seconds: 3,
+        }),
+        // Quotes and comment characters in names are written and read back.
+        piece_set: "my \"#1\" set // new".to_string(),
+        ..default()
+    };
+    let text = write_settings(&settings);
+    assert!(text.contains("input_mode: DragAndDrop"));
+    assert_eq!(parse_settings(&text), Ok((settings, Vec::new())));
+
+    let (settings, unknown_names) = parse_settings(
+        "// Bigger board\n(board_scale: 1.5, /* and quieter */ volume: 0.2, colour: \"red\")\n",
+    )
+    .expect("Hand-edited settings should parse");
+    assert_eq!(settings.board_scale, 1.5);
+    assert_eq!(settings.volume, 0.2);
+    assert_eq!(
+        settings.show_legal_move_hints,
+        UserSettings::default().show_legal_move_hints
+    );
+    assert_eq!(unknown_names, vec!["colour".to_string()]);
+    assert!(parse_settings("(muted: maybe)").is_err());
+}
+
+#[test]
+fn settings_are_applied_recorded_and_reloaded() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut()
+        .resource_mut::<UserSettings>()
+        .show_legal_move_hints = false;
+    app.update();
+    assert!(!app.world().resource::<LegalMoveHints>().is_enabled);
+
+    // Changes made while playing are kept in the settings.
+    app.world_mut()
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::KeyH);
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .release_all();
+    assert!(app.world().resource::<UserSettings>().show_legal_move_hints);
+
+    // Changing the settings file reloads it.
+    let path = get_temp_save_file("settings");
+    std::fs::write(&path, "(board_scale: 1.5)\n").expect("Settings should be written");
+    let mut settings_file = SettingsFile::new(Some(path.clone()));
+    settings_file.check_timer = Timer::from_seconds(0.0, TimerMode::Repeating);
+    app.insert_resource(settings_file);
+    app.update();
+    assert_eq!(app.world().resource::<BoardScale>().0, 1.5);
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn pieces_can_be_dragged_and_dropped() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.world_mut().resource_mut::<UserSettings>().input_mode = InputMode::DragAndDrop;
+    app.update();
+
+    let map_size = app.world().resource::<Variant>().get_map_size();
+    let orientation = BoardOrientation::default();
+    app.world_mut().resource_mut::<CursorPos>().0 =
+        get_tile_center(&get_tile_pos("g1"), &map_size, &orientation);
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .press(MouseButton::Left);
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .clear();
+
+    app.world_mut().resource_mut::<CursorPos>().0 =
+        get_tile_center(&get_tile_pos("f3"), &map_size, &orientation);
+    app.update();
+    app.world_mut()
+        .resource_mut::<ButtonInput<MouseButton>>()
+        .release(MouseButton::Left);
+    app.update();
+    app.update();
+    assert_eq!(get_game_piece(&mut app, "f3"), white(Piece::Knight));
+    assert_eq!(get_game_piece(&mut app, "g1"), None);
+}
+
+#[test]
+fn themes_and_piece_sets_are_found_in_the_assets_folder() {
+    let (catalog, errors) = find_themes(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets"));
+    assert!(errors.is_empty(), "{errors:?}");
+    let ids: Vec<&str> = catalog
+        .board_themes
+        .iter()
+        .map(|board_theme| board_theme.id.as_str())
+        .collect();
+    assert_eq!(ids, ["classic", "walnut"]);
+    assert_eq!(catalog.board_themes[0], BoardTheme::default());
+
+    // A piece set dropped into the assets folder only needs to list the images that aren't named
+    // like the classic set's.
+    let assets_path = get_temp_save_file("assets");
+    let set_path = assets_path.join("pieces").join("outline");
+    std::fs::create_dir_all(&set_path).expect("Piece set folder should be created");
+    std::fs::write(
+        set_path.join("piece_set.ron"),
+        "(name: \"Outline\", images: {'n': \"pieces/outline/horse.png\"})",
+    )
+    .expect("Piece set manifest should be written");
+    std::fs::write(set_path.join("king_white.png"), "").expect("Image should be written");
+    std::fs::create_dir_all(assets_path.join("themes")).expect("Themes folder should be created");
+    std::fs::write(assets_path.join("themes").join("broken.ron"), "(name: 3)")
+        .expect("Theme manifest should be written");
+
+    let (catalog, errors) = find_themes(&assets_path);
+    let _ = std::fs::remove_dir_all(&assets_path);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(catalog.board_themes.len(), 1);
+    let piece_set = &catalog.piece_sets[1];
+    assert_eq!(
+        (piece_set.id.as_str(), piece_set.name.as_str()),
+        ("outline", "Outline")
+    );
+    let get_path = |piece, color| piece_set.get_asset_path(&GamePiece { piece, color });
+    assert_eq!(
+        get_path(Piece::Knight, Color::Black),
+        "pieces/outline/horse.png"
+    );
+    assert_eq!(
+        get_path(Piece::King, Color::White),
+        "pieces/outline/king_white.png"
+    );
+    assert_eq!(get_path(Piece::King, Color::Black), "pieces/king_black.png");
+}
+
+#[test]
+fn board_theme_and_piece_set_are_switched_while_playing() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    let get_path = |app: &mut App, handle: Handle<Image>| {
+        app.world()
+            .resource::<AssetServer>()
+            .get_path(&handle)
+            .map(|path| path.to_string())
+    };
+
+    // The settings are applied during a frame, and the board and pieces are redrawn by the next.
+    app.world_mut().resource_mut::<UserSettings>().board_theme = "walnut".to_string();
+    app.update();
+    app.update();
+    assert_eq!(app.world().resource::<BoardTheme>().name, "Walnut");
+    let TilemapTexture::Single(handle) = app
+        .world_mut()
+        .query::<&TilemapTexture>()
+        .single(app.world())
+        .clone()
+    else {
+        panic!("The board should have a single tile atlas");
+    };
+    assert_eq!(
+        get_path(&mut app, handle),
+        Some("themes/walnut_tiles.png".to_string())
+    );
+
+    let outline = PieceSet {
+        id: "outline".to_string(),
+        name: "Outline".to_string(),
+        images: [('N', "pieces/outline/knight.png".to_string())].into(),
+    };
+    app.world_mut()
+        .resource_mut::<ThemeCatalog>()
+        .piece_sets
+        .push(outline);
+    app.world_mut().resource_mut::<UserSettings>().piece_set = "outline".to_string();
+    app.update();
+    app.update();
+    let knight_handle = app
+        .world_mut()
+        .query::<(&TilePos, &Handle<Image>)>()
+        .iter(app.world())
+        .find_map(|(tile_pos, handle)| (*tile_pos == get_tile_pos("g1")).then(|| handle.clone()))
+        .expect("The knight should have a sprite");
+    assert_eq!(
+        get_path(&mut app, knight_handle),
+        Some("pieces/outline/knight.png".to_string())
+    );
+
+    // Settings naming a piece set that isn't there keep the current one.
+    app.world_mut().resource_mut::<UserSettings>().piece_set = "missing".to_string();
+    app.update();
+    assert_eq!(app.world().resource::<PieceSet>().id, "outline");
+}
+
+#[test]
+fn board_is_refitted_when_the_window_is_resized() {
+    let mut app = build_headless_app(Variant::Standard, None, Odds::default());
+    app.update();
+    let get_zoom = |app: &mut App| {
+        1.0 / app
+            .world_mut()
+            .query_filtered::<&OrthographicProjection, With<Camera2d>>()
+            .single(app.world())
+            .scale
+    };
+    // Without a window the board is only zoomed by its scale.
+    assert_eq!(get_zoom(&mut app), 1.0);
+
+    let resize_window = |app: &mut App, width: f32, height: f32| {
+        let window = app
+            .world_mut()
+            .query_filtered::<Entity, With<PrimaryWindow>>()
+            .get_single(app.world())
+            .unwrap_or_else(|_| {
+                app.world_mut()
+                    .spawn((Window::default(), PrimaryWindow))
+                    .id()
+            });
+        app.world_mut()
+            .get_mut::<Window>(window)
+            .expect("The window should exist")
+            .resolution = WindowResolution::new(width, height);
+        app.world_mut().send_event(WindowResized {
+            window,
+            width,
+            height,
+        });
+        app.update();
+    };
+
+    // The 8x8 board and its labels are 800 pixels across, which must fit beside the side panels.
+    resize_window(&mut app, 1400.0, 900.0);
+    assert_eq!(get_zoom(&mut app), 1.0);
+    resize_window(&mut app, 1000.0, 500.0);
+    assert_eq!(get_zoom(&mut app), 0.625);
+    // A narrow window gives the board half of its width.
+    resize_window(&mut app, 800.0, 1000.0);
+    assert_eq!(get_zoom(&mut app), 0.5);
+
+    app.world_mut().resource_mut::<BoardScale>().0 = 1.5;
+    app.update();
+    assert_eq!(get_zoom(&mut app), 0.75);
+}
+
+#[test]
+fn network_messages_are_written_and_parsed_back_the_same() {
+    let messages = [
+        NetworkMessage::Hello {
+            version: PROTOCOL_VERSION,
+            role: PeerRole::Spectator,
+        },
+        NetworkMessage::Welcome {
+            color: Some(Color::Black),
+            variant: Variant::Grand,
+            time_control: Some(TimeControl {
+                minutes: 5,
+                increment_seconds: 3,
+            }),
+            starting_fen: "4k5/10/10/10/10/10/10/10/10/4K5 w - - 0 1".to_string(),
+        },
+        NetworkMessage::Move {
+            from: get_tile_pos("e10"),
+            to: get_tile_pos("e9"),
+        },
+        NetworkMessage::Clock {
+            white_seconds: 287.5,
+            black_seconds: 300.0,
+        },
+        NetworkMessage::Synced,
+        NetworkMessage::OfferDraw,
+        NetworkMessage::Undo { plies: 2 },
+        NetworkMessage::GameOver(GameEnded {
+            result: GameResult::Win(Color::White),
+            reason: GameEndReason::AllPiecesCaptured,
+        }),
+    ];
+    for message in messages {
+        assert_eq!(parse_message(&write_message(&message)), Ok(message));
+    }
+    assert_eq!(write_message(&get_move_message("e2", "e4")), "move e2e4");
+    assert!(parse_message("move e7e8q").is_err());
+    assert!(parse_message("hello some-other-game 1").is_err());
+    // A game must say whether it's playing or watching.
+    assert!(parse_message("hello bevy-chess 1").is_err());
+}
+
+#[test]
+fn messages_that_do_not_fit_in_the_connection_are_sent_later() {
+    let listener = listen("127.0.0.1:0").expect("Port should be free");
+    let address = listener
+        .local_addr()
+        .expect("Listener should have an address")
+        .to_string();
+    let mut sender = NetworkPeer::connect(&address).expect("Listener should be reachable");
+    let mut receiver = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break NetworkPeer::new(stream).expect("Stream should be set up"),
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(error) => panic!("{error}"),
+        }
+    };
+
+    // Far more than the connection can hold is sent at once, without waiting for it to be read.
+    let message = NetworkMessage::Reject("x".repeat(1000));
+    let message_count = 10_000;
+    for _ in 0..message_count {
+        sender
+            .send(&message)
+            .expect("Message should be sent or kept");
+    }
+    for _ in 0..10_000 {
+        if receiver.incoming.len() == message_count {
+            break;
+        }
+        sender.flush().expect("Kept messages should be sent");
+        receiver.receive().expect("Messages should be received");
+    }
+    assert_eq!(receiver.incoming.len(), message_count);
+    assert!(receiver
+        .incoming
+        .iter()
+        .all(|received| *received == message));
+}
+
+#[test]
+fn messages_sent_before_the_connection_is_closed_are_received() {
+    let listener = listen("127.0.0.1:0").expect("Port should be free");
+    let address = listener
+        .local_addr()
+        .expect("Listener should have an address")
+        .to_string();
+    let mut receiver = NetworkPeer::connect(&address).expect("Listener should be reachable");
+    let mut sender = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break NetworkPeer::new(stream).expect("Stream should be set up"),
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(error) => panic!("{error}"),
+        }
+    };
+
+    // The host turns the connection away and hangs up straight after.
+    let reject = NetworkMessage::Reject("The game already has two players".to_string());
+    sender.send(&reject).expect("Message should be sent");
+    drop(sender);
+    let mut result = Ok(Vec::new());
+    for _ in 0..500 {
+        result = receiver.receive();
+        if result.is_err() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    assert!(result.is_err());
+    assert_eq!(receiver.incoming.pop_front(), Some(reject));
+}
+
+fn get_move_message(from: &str, to: &str) -> NetworkMessage {
+    NetworkMessage::Move {
+        from: get_tile_pos(from),
+        to: get_tile_pos(to),
+    }
+}
+
+// Runs the hosting and joining games until the condition holds, giving messages time to cross the
+// connection between them.
+fn run_until(host: &mut App, guest: &mut App, condition: impl Fn(&mut App, &mut App) -> bool) {
+    for _ in 0..500 {
+        if condition(host, guest) {
+            return;
+        }
+        host.update();
+        guest.update();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+    panic!("The games should have caught up with each other");
+}
+
+fn host_network_game() -> (App, String) {
+    let mut host = build_headless_app(Variant::Standard, None, Odds::default());
+    host.insert_resource(LocalPlayer(Some(Color::White)));
+    let network_game = NetworkGame::host_on("127.0.0.1:0").expect("Game should be hosted");
+    let address = network_game
+        .listener
+        .as_ref()
+        .and_then(|listener| listener.local_addr().ok())
+        .expect("Game should be hosted on a port")
+        .to_string();
+    host.insert_resource(network_game);
+    host.update();
+    (host, address)
+}
+
+#[test]
+fn position_editor_cannot_be_opened_in_a_network_game() {
+    let (mut host, address) = host_network_game();
+    let mut guest = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut guest, AppState::MainMenu);
+    guest.insert_resource(NetworkGame::join(&address).expect("Game should be joined"));
+    run_until(&mut host, &mut guest, |host, guest| {
+        [host, guest].iter().all(|app| {
+            app.world()
+                .resource::<NetworkGame>()
+                .peer
+                .as_ref()
+                .is_some_and(|peer| peer.state == PeerState::Playing)
+        })
+    });
+
+    for app in [&mut host, &mut guest] {
+        press_key(app, KeyCode::KeyE);
+        assert!(!app.world().resource::<PositionEditor>().is_active);
+    }
+}
+
+#[test]
+fn network_game_is_joined_and_played_on_both_computers() {
+    let (mut host, address) = host_network_game();
+    play_moves(&mut host, &["e2e4"]);
+
+    let mut guest = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut guest, AppState::MainMenu);
+    guest.insert_resource(NetworkGame::join(&address).expect("Game should be joined"));
+
+    // The guest plays the other color, and is sent the moves played before joining.
+    run_until(&mut host, &mut guest, |_, guest| {
+        get_game_piece(guest, "e4") == white(Piece::Pawn)
+            && guest
+                .world()
+                .resource::<NetworkGame>()
+                .peer
+                .as_ref()
+                .is_some_and(|peer| peer.state == PeerState::Playing)
+    });
+    assert_eq!(
+        guest.world().resource::<LocalPlayer>().0,
+        Some(Color::Black)
+    );
+    assert!(guest.world().resource::<BoardOrientation>().is_flipped);
+    assert_eq!(get_sans(&guest), ["e4"]);
+
+    play_moves(&mut guest, &["e7e5"]);
+    run_until(&mut host, &mut guest, |host, _| {
+        get_game_piece(host, "e5") == black(Piece::Pawn)
+    });
+    play_moves(&mut host, &["g1f3"]);
+    run_until(&mut host, &mut guest, |_, guest| {
+        get_game_piece(guest, "f3") == white(Piece::Knight)
+    });
+
+    // Moving the host's own pieces isn't allowed, so the host hangs up.
+    guest
+        .world_mut()
+        .resource_mut::<NetworkGame>()
+        .peer
+        .as_mut()
+        .expect("Guest should be connected")
+        .send(&get_move_message("d2", "d4"))
+        .expect("Move should be sent");
+    run_until(&mut host, &mut guest, |host, _| {
+        host.world().resource::<NetworkGame>().peer.is_none()
+    });
+    assert_eq!(get_game_piece(&mut host, "d2"), white(Piece::Pawn));
+    assert_eq!(get_game_piece(&mut host, "d4"), None);
+}
+
+#[test]
+fn hosted_game_is_watched_by_spectators_who_cannot_move() {
+    let (mut host, address) = host_network_game();
+    let mut guest = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut guest, AppState::MainMenu);
+    guest.insert_resource(NetworkGame::join(&address).expect("Game should be joined"));
+    play_moves(&mut host, &["e2e4"]);
+    run_until(&mut host, &mut guest, |_, guest| get_sans(guest) == ["e4"]);
+    play_moves(&mut guest, &["e7e5"]);
+    run_until(&mut host, &mut guest, |host, _| {
+        get_sans(host) == ["e4", "e5"]
+    });
+
+    // A spectator joining partway through is sent the whole game so far.
+    let mut spectator = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut spectator, AppState::MainMenu);
+    spectator.insert_resource(NetworkGame::spectate(&address).expect("Game should be watched"));
+    run_until(&mut host, &mut spectator, |_, spectator| {
+        get_sans(spectator) == ["e4", "e5"]
+    });
+    assert_eq!(spectator.world().resource::<LocalPlayer>().0, None);
+    assert_eq!(host.world().resource::<NetworkGame>().spectators.len(), 1);
+
+    // Then it's sent every move, whichever player made it.
+    play_moves(&mut host, &["g1f3"]);
+    run_until(&mut host, &mut guest, |_, guest| get_sans(guest).len() == 3);
+    play_moves(&mut guest, &["b8c6"]);
+    run_until(&mut host, &mut guest, |host, _| get_sans(host).len() == 4);
+    run_until(&mut host, &mut spectator, |_, spectator| {
+        get_sans(spectator) == ["e4", "e5", "Nf3", "Nc6"]
+    });
+
+    // Neither color can be moved while watching, and it's white's turn.
+    play_moves(&mut spectator, &["d2d4"]);
+    assert_eq!(get_game_piece(&mut spectator, "d2"), white(Piece::Pawn));
+    assert_eq!(get_sans(&spectator).len(), 4);
+
+    // The game already has two players, so another one is turned away.
+    let mut latecomer = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut latecomer, AppState::MainMenu);
+    latecomer.insert_resource(NetworkGame::join(&address).expect("Host should be reachable"));
+    run_until(&mut host, &mut latecomer, |_, latecomer| {
+        latecomer.world().resource::<NetworkGame>().peer.is_none()
+    });
+    assert_eq!(
+        *latecomer.world().resource::<State<AppState>>(),
+        AppState::MainMenu
+    );
+}
+
+#[test]
+fn network_players_take_back_moves_and_agree_to_draws() {
+    let (mut host, address) = host_network_game();
+    let mut guest = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut guest, AppState::MainMenu);
+    guest.insert_resource(NetworkGame::join(&address).expect("Game should be joined"));
+    let mut spectator = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut spectator, AppState::MainMenu);
+    spectator.insert_resource(NetworkGame::spectate(&address).expect("Game should be watched"));
+    play_moves(&mut host, &["e2e4"]);
+    run_until(&mut host, &mut guest, |_, guest| get_sans(guest) == ["e4"]);
+    play_moves(&mut guest, &["e7e5"]);
+    run_until(&mut host, &mut guest, |host, _| {
+        get_sans(host) == ["e4", "e5"]
+    });
+    run_until(&mut host, &mut spectator, |_, spectator| {
+        get_sans(spectator) == ["e4", "e5"]
+    });
+
+    // The guest asks to take back their move, and the host accepts from the offer panel.
+    press_pause_menu_button(&mut guest, MenuButton::RequestTakeback);
+    run_until(&mut host, &mut guest, |host, _| {
+        host.world().resource::<PendingOffer>().0.is_some()
+    });
+    host.update();
+    press_menu_button(&mut host, MenuButton::AcceptOffer);
+    run_until(&mut host, &mut guest, |host, guest| {
+        get_sans(host) == ["e4"] && get_sans(guest) == ["e4"]
+    });
+    assert_eq!(get_game_piece(&mut guest, "e7"), black(Piece::Pawn));
+    assert!(guest.world().resource::<PendingOffer>().0.is_none());
+    run_until(&mut host, &mut spectator, |_, spectator| {
+        get_sans(spectator) == ["e4"]
+    });
+
+    // The host offers a draw, which the guest declines and then accepts when offered again.
+    press_pause_menu_button(&mut host, MenuButton::OfferDraw);
+    run_until(&mut host, &mut guest, |_, guest| {
+        guest.world().resource::<PendingOffer>().0.is_some()
+    });
+    guest.update();
+    press_menu_button(&mut guest, MenuButton::DeclineOffer);
+    run_until(&mut host, &mut guest, |host, _| {
+        host.world().resource::<PendingOffer>().0.is_none()
+    });
+    assert_eq!(get_game_outcome(&host), None);
+    press_pause_menu_button(&mut host, MenuButton::OfferDraw);
+    run_until(&mut host, &mut guest, |_, guest| {
+        guest.world().resource::<PendingOffer>().0.is_some()
+    });
+    guest.update();
+    press_menu_button(&mut guest, MenuButton::AcceptOffer);
+    let draw = Some(GameEnded {
+        result: GameResult::Draw,
+        reason: GameEndReason::Agreement,
+    });
+    run_until(&mut host, &mut guest, |host, guest| {
+        get_game_outcome(host) == draw && get_game_outcome(guest) == draw
+    });
+    run_until(&mut host, &mut spectator, |_, spectator| {
+        get_game_outcome(spectator) == draw
+    });
+}
+
+fn get_app_state(app: &App) -> AppState {
+    *app.world().resource::<State<AppState>>().get()
+}
+
+#[test]
+fn network_game_is_paused_and_resumed_on_both_computers() {
+    let (mut host, address) = host_network_game();
+    let mut guest = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut guest, AppState::MainMenu);
+    guest.insert_resource(NetworkGame::join(&address).expect("Game should be joined"));
+    play_moves(&mut host, &["e2e4"]);
+    run_until(&mut host, &mut guest, |_, guest| get_sans(guest) == ["e4"]);
+
+    // The game pauses on both sides, whichever side pauses it, until it resumes.
+    set_app_state(&mut guest, AppState::Paused);
+    run_until(&mut host, &mut guest, |host, _| {
+        get_app_state(host) == AppState::Paused
+    });
+    press_menu_button(&mut guest, MenuButton::Resume);
+    run_until(&mut host, &mut guest, |host, guest| {
+        get_app_state(host) == AppState::Playing && get_app_state(guest) == AppState::Playing
+    });
+    set_app_state(&mut host, AppState::Paused);
+    run_until(&mut host, &mut guest, |_, guest| {
+        get_app_state(guest) == AppState::Paused
+    });
+
+    // Resigning from the pause menu resumes the game on the other side too, so it can end.
+    press_menu_button(&mut host, MenuButton::Resign);
+    let resignation = Some(GameEnded {
+        result: GameResult::Win(Color::Black),
+        reason: GameEndReason::Resignation,
+    });
+    run_until(&mut host, &mut guest, |host, guest| {
+        get_game_outcome(host) == resignation && get_game_outcome(guest) == resignation
+    });
+}
+
+#[test]
+fn host_decides_when_a_network_player_runs_out_of_time() {
+    let (mut host, address) = host_network_game();
+    let mut guest = build_headless_app(Variant::Standard, None, Odds::default());
+    set_app_state(&mut guest, AppState::MainMenu);
+    guest.insert_resource(NetworkGame::join(&address).expect("Game should be joined"));
+    play_moves(&mut host, &["e2e4"]);
+    run_until(&mut host, &mut guest, |_, guest| get_sans(guest) == ["e4"]);
+    let almost_out_of_time = Clocks {
+        time_control: Some(TimeControl {
+            minutes: 1,
+            increment_seconds: 0,
+        }),
+        white_seconds: 60.0,
+        black_seconds: 0.001,
+    };
+
+    // The guest's own clock running out doesn't end the game, as their move may be on its way.
+    guest.insert_resource(almost_out_of_time);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    guest.update();
+    guest.update();
+    assert_eq!(guest.world().resource::<Clocks>().black_seconds, 0.0);
+    assert_eq!(get_game_outcome(&guest), None);
+
+    // The game ends once the host's clock runs out too, the same way for both.
+    host.insert_resource(almost_out_of_time);
+    let timeout = Some(GameEnded {
+        result: GameResult::Win(Color::White),
+        reason: GameEndReason::Timeout,
+    });
+    run_until(&mut host, &mut guest, |host, guest| {
+        get_game_outcome(host) == timeout && get_game_outcome(guest) == timeout
+    });
+}
+
+#[test]
+fn network_player_speaking_another_version_is_turned_away() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let (mut host, address) = host_network_game();
+    let mut stream = std::net::TcpStream::connect(&address).expect("Host should be reachable");
+    stream
+        .write_all(b"hello bevy-chess 99 player\n")
+        .expect("Hello should be sent");
+    for _ in 0..10 {
+        host.update();
+    }
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .expect("Host should reply");
+    assert!(reply.starts_with("reject "), "{reply}");
+    assert!(host.world().resource::<NetworkGame>().peer.is_none());
+}
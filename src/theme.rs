@@ -0,0 +1,126 @@
+use crate::components::{Color, GamePiece, Piece};
+use crate::resources::{BoardTheme, PieceSet, ThemeCatalog};
+use std::path::Path;
+
+// Where board theme manifests are kept in the assets folder, each named <id>.ron.
+pub const THEMES_FOLDER: &str = "themes";
+// Where piece sets are kept in the assets folder, each in a folder of its own named by its id.
+pub const PIECES_FOLDER: &str = "pieces";
+pub const PIECE_SET_MANIFEST: &str = "piece_set.ron";
+
+// Parses a board theme manifest, such as:
+// (name: "Walnut", tiles: "themes/walnut_tiles.png", layout: (plain: 0, mouseover: 2, ...))
+// The layout can be left out when the atlas is laid out like tiles.png.
+pub fn parse_board_theme(text: &str, id: &str) -> Result<BoardTheme, String> {
+    let mut board_theme: BoardTheme = ron::from_str(text).map_err(|error| error.to_string())?;
+    board_theme.id = id.to_string();
+    Ok(board_theme)
+}
+
+// Parses a piece set manifest, such as (name: "Outline", images: {'K': "pieces/outline/k.png"}).
+// Images are relative to the assets folder and keyed by the piece's FEN letter.
+pub fn parse_piece_set(text: &str, id: &str) -> Result<PieceSet, String> {
+    let mut piece_set: PieceSet = ron::from_str(text).map_err(|error| error.to_string())?;
+    piece_set.id = id.to_string();
+    Ok(piece_set)
+}
+
+fn read_file(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path)
+        .map_err(|error| format!("Couldn't read {}: {error}", path.display()))
+}
+
+// Returns the file names in a folder, sorted so themes are always listed in the same order.
+fn get_sorted_entries(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    names
+}
+
+// Looks through the assets folder for board themes and piece sets. The classic theme and set are
+// always listed first, as they're built in, though a manifest can replace the classic theme. A
+// piece set's images don't all need to be listed in its manifest: images in its folder named like
+// the classic set's (such as king_white.png) are used for any piece that isn't listed. Manifests
+// that can't be read are skipped, and returned as errors.
+pub fn find_themes(assets_path: &Path) -> (ThemeCatalog, Vec<String>) {
+    let mut catalog = ThemeCatalog {
+        board_themes: vec![BoardTheme::default()],
+        piece_sets: vec![PieceSet::default()],
+    };
+    let mut errors = Vec::new();
+
+    let themes_path = assets_path.join(THEMES_FOLDER);
+    for file_name in get_sorted_entries(&themes_path) {
+        let Some(id) = file_name.strip_suffix(".ron") else {
+            continue;
+        };
+        let path = themes_path.join(&file_name);
+        match read_file(&path).and_then(|text| parse_board_theme(&text, id)) {
+            Ok(board_theme) => {
+                catalog
+                    .board_themes
+                    .retain(|other| other.id != board_theme.id);
+                catalog.board_themes.push(board_theme);
+            }
+            Err(error) => errors.push(format!("Skipping {}: {error}", path.display())),
+        }
+    }
+    // Keep the classic theme first, even when a manifest replaced it.
+    catalog
+        .board_themes
+        .sort_by_key(|board_theme| board_theme.id != "classic");
+
+    let pieces_path = assets_path.join(PIECES_FOLDER);
+    for id in get_sorted_entries(&pieces_path) {
+        let path = pieces_path.join(&id).join(PIECE_SET_MANIFEST);
+        if id == PieceSet::default().id || !path.exists() {
+            continue;
+        }
+        let mut piece_set = match read_file(&path).and_then(|text| parse_piece_set(&text, &id)) {
+            Ok(piece_set) => piece_set,
+            Err(error) => {
+                errors.push(format!("Skipping {}: {error}", path.display()));
+                continue;
+            }
+        };
+        for game_piece in get_all_game_pieces() {
+            let classic_path = game_piece.get_asset_path();
+            let file_name = classic_path.rsplit('/').next().unwrap_or(classic_path);
+            if !piece_set.images.contains_key(&game_piece.get_letter())
+                && pieces_path.join(&id).join(file_name).exists()
+            {
+                piece_set.images.insert(
+                    game_piece.get_letter(),
+                    format!("{PIECES_FOLDER}/{id}/{file_name}"),
+                );
+            }
+        }
+        catalog.piece_sets.push(piece_set);
+    }
+
+    (catalog, errors)
+}
+
+fn get_all_game_pieces() -> impl Iterator<Item = GamePiece> {
+    [
+        Piece::Archbishop,
+        Piece::Bishop,
+        Piece::Chancellor,
+        Piece::King,
+        Piece::Knight,
+        Piece::Pawn,
+        Piece::Queen,
+        Piece::Rook,
+    ]
+    .into_iter()
+    .flat_map(|piece| {
+        [Color::White, Color::Black]
+            .into_iter()
+            .map(move |color| GamePiece { piece, color })
+    })
+}